@@ -0,0 +1,31 @@
+/// Pads `rows` with spaces before the first `delimiter` in each, so that it
+/// lines up at the same column in every row that has it. Rows without it
+/// are left alone. Tabs count up to the next multiple of `tab_stop`.
+pub fn align(rows: &mut [String], delimiter: char, tab_stop: usize) {
+    let found: Vec<Option<(usize, usize)>> = rows
+        .iter()
+        .map(|row| {
+            let at = row.find(delimiter)?;
+            Some((at, width(&row[..at], tab_stop)))
+        })
+        .collect();
+    let Some(column) = found.iter().flatten().map(|&(_, column)| column).max() else {
+        return;
+    };
+    for (row, found) in rows.iter_mut().zip(found) {
+        if let Some((at, width)) = found {
+            row.insert_str(at, &" ".repeat(column - width));
+        }
+    }
+}
+
+/// Columns `text` takes on screen.
+fn width(text: &str, tab_stop: usize) -> usize {
+    text.chars().fold(0, |column, ch| {
+        if ch == '\t' {
+            column + tab_stop - column % tab_stop
+        } else {
+            column + 1
+        }
+    })
+}
@@ -0,0 +1,46 @@
+use std::ops::{Range, RangeInclusive};
+
+/// Where a block's corner moves by a row or column.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Step {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// A rectangular selection, with corners as `(row, render column)`.
+/// Columns may lie past the end of short rows.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Block {
+    /// The corner the selection started from.
+    pub anchor: (usize, usize),
+    /// The corner that moves, where the cursor is.
+    pub corner: (usize, usize),
+}
+
+impl Block {
+    /// An empty block at `pos`.
+    pub fn at(pos: (usize, usize)) -> Self {
+        Self {
+            anchor: pos,
+            corner: pos,
+        }
+    }
+
+    pub fn rows(&self) -> RangeInclusive<usize> {
+        self.anchor.0.min(self.corner.0)..=self.anchor.0.max(self.corner.0)
+    }
+
+    /// Render columns the block covers. When empty, the block is a column
+    /// on each of its rows where typing goes.
+    pub fn columns(&self) -> Range<usize> {
+        self.anchor.1.min(self.corner.1)..self.anchor.1.max(self.corner.1)
+    }
+
+    /// Makes the block zero columns wide at `column`.
+    pub fn collapse_to(&mut self, column: usize) {
+        self.anchor.1 = column;
+        self.corner.1 = column;
+    }
+}
@@ -0,0 +1,57 @@
+use crate::word_motion::is_word_char;
+
+/// A change of letter case.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Case {
+    Upper,
+    Lower,
+    /// The first letter of each word upper case, the rest lower case.
+    Title,
+    /// Upper case letters lower case, and the other way around.
+    Toggle,
+}
+
+/// `text` with its letters in `case`. Letters may change length, as `ß`
+/// becomes `SS`. For title case, `in_word` says whether the char before
+/// `text` is part of a word, which its first letter would then continue.
+pub fn convert(text: &str, case: Case, mut in_word: bool) -> String {
+    let mut converted = String::with_capacity(text.len());
+    for ch in text.chars() {
+        let upper = match case {
+            Case::Upper => true,
+            Case::Lower => false,
+            Case::Title => !in_word,
+            Case::Toggle => ch.is_lowercase(),
+        };
+        if upper {
+            converted.extend(ch.to_uppercase());
+        } else {
+            converted.extend(ch.to_lowercase());
+        }
+        in_word = is_word_char(ch);
+    }
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_upper_and_lower_case() {
+        assert_eq!(convert("Hello, World", Case::Upper, false), "HELLO, WORLD");
+        assert_eq!(convert("Hello, World", Case::Lower, false), "hello, world");
+        assert_eq!(convert("straße", Case::Upper, false), "STRASSE");
+    }
+
+    #[test]
+    fn title_cases_each_word() {
+        assert_eq!(convert("the qUICK fox_tail", Case::Title, false), "The Quick Fox_tail");
+        assert_eq!(convert("ello there", Case::Title, true), "ello There");
+    }
+
+    #[test]
+    fn toggles_case() {
+        assert_eq!(convert("aBc 1", Case::Toggle, false), "AbC 1");
+    }
+}
@@ -0,0 +1,173 @@
+use crate::layout::Direction;
+use crate::sort::SortOptions;
+
+/// An ex-style command typed after `:`.
+#[derive(PartialEq, Debug)]
+pub enum Command {
+    /// `:w`
+    Write,
+    /// `:q`, or `:q!` with `force` to discard unsaved changes.
+    Quit { force: bool },
+    /// `:wq`
+    WriteQuit,
+    /// `:e <file>` opens `file` in a new buffer, or shows it if it is
+    /// already open.
+    Edit { path: String },
+    /// `:e` reads the buffer's file again, or `:e!` with `force` to discard
+    /// unsaved changes.
+    Reload { force: bool },
+    /// `:bn` shows the next buffer in the pane.
+    NextBuffer,
+    /// `:bp` shows the previous buffer in the pane.
+    PreviousBuffer,
+    /// `:grep <text>` searches the files under the current directory for
+    /// `text` and lists the matches. `:grep` alone lists the last search's
+    /// matches again, or prompts for text if there was none.
+    Grep { text: Option<String> },
+    /// `:ls` lists the open buffers to pick one from.
+    ListBuffers,
+    /// `:bd` closes the buffer in the pane, asking whether to save it if it
+    /// has unsaved changes, or `:bd!` with `force` to discard them.
+    CloseBuffer { force: bool },
+    /// `:sp [file]` splits the pane, showing `file` in the new one if given.
+    /// `:vsp [file]` splits it side by side.
+    Split {
+        direction: Direction,
+        path: Option<String>,
+    },
+    /// `:close` closes the focused pane.
+    ClosePane,
+    /// `:only` closes every pane but the focused one.
+    OnlyPane,
+    /// `:set option=value`
+    Set { option: String, value: String },
+    /// `:marks` lists the marks in the buffer.
+    Marks,
+    /// `:diff` shows in the gutter how the buffer differs from its file as
+    /// saved rather than from git's `HEAD`, or goes back to `HEAD`.
+    Diff,
+    /// `:hex` shows the buffer's bytes in hex, or its text again. `:nohex`
+    /// with `off` only goes back to the text.
+    Hex { off: bool },
+    /// `:jumps` lists the jump list walked with Ctrl-O and Ctrl-I.
+    Jumps,
+    /// `:sort` sorts the selected rows, or all of them. `:sort!` sorts in
+    /// reverse, `:sort i` ignores case, `:sort n` sorts by number and
+    /// `:sort u` keeps only the first of equal rows.
+    Sort(SortOptions),
+    /// `:reverse` reverses the order of the selected rows, or all of them.
+    Reverse,
+    /// `:dedup` removes rows repeating the row above them, among the
+    /// selected rows or all of them, as `uniq` does. `:dedup!` with `all`
+    /// removes every row repeating an earlier one.
+    Dedup { all: bool },
+    /// `:align <char>` pads the selected rows, or all of them, so the first
+    /// `char` in each lines up.
+    Align(char),
+    /// `:NN` jumps to line NN.
+    Goto(usize),
+}
+
+/// Parses the text typed at the `:` prompt. Errors are messages for the
+/// message bar.
+pub fn parse(input: &str) -> Result<Command, String> {
+    let input = input.trim();
+    let (name, argument) = match input.split_once(' ') {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (input, ""),
+    };
+    let command = match name {
+        "w" => Command::Write,
+        "q" => Command::Quit { force: false },
+        "q!" => Command::Quit { force: true },
+        "wq" => Command::WriteQuit,
+        "marks" => Command::Marks,
+        "diff" => Command::Diff,
+        "hex" => Command::Hex { off: false },
+        "nohex" => Command::Hex { off: true },
+        "jumps" | "ju" => Command::Jumps,
+        "dedup" | "uniq" => Command::Dedup { all: false },
+        "dedup!" | "uniq!" => Command::Dedup { all: true },
+        "sort" | "sort!" => Command::Sort(SortOptions::parse(argument, name == "sort!")?),
+        "reverse" => Command::Reverse,
+        "align" => {
+            let mut chars = argument.chars();
+            match (chars.next(), chars.next()) {
+                (Some(delimiter), None) => Command::Align(delimiter),
+                _ => return Err("Argument required: align <char>".into()),
+            }
+        }
+        "e" | "e!" if argument.is_empty() => Command::Reload {
+            force: name == "e!",
+        },
+        "e" | "e!" => Command::Edit {
+            path: argument.into(),
+        },
+        "bn" | "bnext" => Command::NextBuffer,
+        "bp" | "bprevious" => Command::PreviousBuffer,
+        "ls" | "buffers" => Command::ListBuffers,
+        "gr" | "grep" => Command::Grep {
+            text: (!argument.is_empty()).then(|| argument.into()),
+        },
+        "bd" | "bdelete" => Command::CloseBuffer { force: false },
+        "bd!" | "bdelete!" => Command::CloseBuffer { force: true },
+        "clo" | "close" => Command::ClosePane,
+        "on" | "only" => Command::OnlyPane,
+        "sp" | "split" | "vs" | "vsp" | "vsplit" => Command::Split {
+            direction: if name.starts_with('v') {
+                Direction::Columns
+            } else {
+                Direction::Rows
+            },
+            path: (!argument.is_empty()).then(|| argument.into()),
+        },
+        "set" => match argument.split_once('=') {
+            Some((option, value)) => Command::Set {
+                option: option.trim().into(),
+                value: value.trim().into(),
+            },
+            None => return Err(format!("expected option=value, found `{}`", argument)),
+        },
+        _ => match input.parse() {
+            Ok(line) => Command::Goto(line),
+            Err(_) => return Err(format!("Not a command: {}", input)),
+        },
+    };
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_write_and_quit() {
+        assert_eq!(parse("w"), Ok(Command::Write));
+        assert_eq!(parse("q"), Ok(Command::Quit { force: false }));
+        assert_eq!(parse("q!"), Ok(Command::Quit { force: true }));
+        assert_eq!(parse(" wq "), Ok(Command::WriteQuit));
+    }
+
+    #[test]
+    fn parses_a_line_number() {
+        assert_eq!(parse("42"), Ok(Command::Goto(42)));
+    }
+
+    #[test]
+    fn parses_set() {
+        assert_eq!(
+            parse("set tab_stop = 4"),
+            Ok(Command::Set {
+                option: "tab_stop".into(),
+                value: "4".into(),
+            })
+        );
+        assert!(parse("set tab_stop").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        assert_eq!(parse("frobnicate"), Err("Not a command: frobnicate".into()));
+        assert!(parse("").is_err());
+    }
+}
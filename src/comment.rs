@@ -0,0 +1,90 @@
+/// File extensions and the token starting a line comment in them.
+const TOKENS: [(&[&str], &str); 6] = [
+    (
+        &[
+            "rs", "c", "h", "cc", "cpp", "hpp", "go", "java", "js", "ts", "jsx", "tsx", "swift",
+            "kt", "cs", "scala", "zig",
+        ],
+        "//",
+    ),
+    (
+        &[
+            "sh", "bash", "zsh", "fish", "py", "rb", "pl", "toml", "yaml", "yml", "conf", "r",
+            "nix", "mk",
+        ],
+        "#",
+    ),
+    (&["lua", "sql", "hs", "elm", "ada"], "--"),
+    (&["vim"], "\""),
+    (&["lisp", "el", "clj", "scm", "ini", "asm"], ";"),
+    (&["tex", "erl", "m"], "%"),
+];
+
+/// The token starting a line comment in files with `extension`.
+pub fn token_for(extension: &str) -> Option<&'static str> {
+    let extension = extension.to_ascii_lowercase();
+    TOKENS
+        .iter()
+        .find(|(extensions, _)| extensions.contains(&extension.as_str()))
+        .map(|&(_, token)| token)
+}
+
+/// Comments out `rows` with `token` and a space after their indentation,
+/// or if every one already starts with `token` takes it off again, with
+/// the space after it. Blank rows are left alone either way.
+pub fn toggle(rows: &mut [String], token: &str) {
+    let split = |row: &str| row.len() - row.trim_start_matches([' ', '\t']).len();
+    let commented = rows
+        .iter()
+        .filter(|row| !row.trim().is_empty())
+        .all(|row| row[split(row)..].starts_with(token));
+    for row in rows.iter_mut().filter(|row| !row.trim().is_empty()) {
+        let indent = split(row);
+        if commented {
+            let mut end = indent + token.len();
+            if row[end..].starts_with(' ') {
+                end += 1;
+            }
+            row.replace_range(indent..end, "");
+        } else {
+            row.insert_str(indent, &format!("{} ", token));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toggled(rows: &[&str], token: &str) -> Vec<String> {
+        let mut rows: Vec<String> = rows.iter().map(|row| row.to_string()).collect();
+        toggle(&mut rows, token);
+        rows
+    }
+
+    #[test]
+    fn finds_the_token_by_extension() {
+        assert_eq!(token_for("rs"), Some("//"));
+        assert_eq!(token_for("PY"), Some("#"));
+        assert_eq!(token_for("lua"), Some("--"));
+        assert_eq!(token_for("txt"), None);
+    }
+
+    #[test]
+    fn comments_out_after_the_indentation() {
+        assert_eq!(
+            toggled(&["fn a() {", "    b();", "", "}"], "//"),
+            ["// fn a() {", "    // b();", "", "// }"]
+        );
+    }
+
+    #[test]
+    fn uncomments_when_every_row_is_commented() {
+        assert_eq!(toggled(&["# a", "\t#b", "  "], "#"), ["a", "\tb", "  "]);
+    }
+
+    #[test]
+    fn comments_out_a_mix_of_rows_again() {
+        assert_eq!(toggled(&["# a", "b"], "#"), ["# # a", "# b"]);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::keymap::Keymap;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::{env, fs};
+
+/// User settings, read from `$XDG_CONFIG_HOME/dot/config` (or
+/// `~/.config/dot/config`) as `option = value` lines. Lines starting with
+/// `#` are comments, and `bind <key> = <action>` lines change the keymap.
+pub struct Config {
+    /// Columns per tab stop, unless a file's own indentation says otherwise.
+    pub tab_stop: usize,
+    /// Indent with spaces rather than tabs, unless detected otherwise.
+    pub soft_tabs: bool,
+    /// Start new rows with the leading whitespace of the row they were
+    /// opened from.
+    pub auto_indent: bool,
+    /// Add the closing bracket or quote when typing an opening one, and
+    /// move over it when it is typed next.
+    pub auto_close: bool,
+    /// Number the rows in a gutter left of the text.
+    pub line_numbers: bool,
+    /// Wrap rows wider than the screen onto more screen rows, at word
+    /// boundaries, instead of scrolling sideways.
+    pub wrap: bool,
+    /// Shown in the gutter on the screen rows a wrapped row continues on.
+    /// Empty for none.
+    pub wrap_marker: String,
+    /// With `wrap`, move Up and Down by screen row rather than by row.
+    pub move_by_screen_row: bool,
+    /// `H`, `M` and `L` go to the start of the row, rather than keeping
+    /// the column.
+    pub start_of_line: bool,
+    /// Rows kept visible above and below the cursor when scrolling.
+    pub scroll_off: usize,
+    /// Columns kept visible left and right of the cursor when scrolling
+    /// sideways.
+    pub side_scroll_off: usize,
+    pub highlight_trailing_whitespace: bool,
+    /// Flag rows whose indentation has a space before a tab.
+    pub highlight_mixed_indent: bool,
+    /// Extensions of files where trailing whitespace and mixed indentation
+    /// mean something, and aren't highlighted.
+    pub whitespace_exempt: Vec<String>,
+    /// Draw tabs, trailing spaces and non-breaking spaces visibly.
+    pub show_whitespace: bool,
+    /// With `show_whitespace`, draw every space as `·`, to check
+    /// indentation, rather than only trailing ones.
+    pub show_all_spaces: bool,
+    /// Drawn after the end of each row, to tell empty rows from ones of
+    /// whitespace. Only drawn, never part of the text.
+    pub eol_marker: Option<char>,
+    pub strip_trailing_whitespace_on_save: bool,
+    /// Seconds after the last key to save a buffer with unsaved changes in.
+    /// 0 for never.
+    pub autosave_interval: usize,
+    /// Copy a file to `<name>~` before saving over it the first time in a
+    /// session.
+    pub make_backup: bool,
+    /// End saved files with exactly one newline.
+    pub final_newline: bool,
+    /// With `final_newline`, also drop blank lines at the end of the file.
+    pub collapse_trailing_blank_lines: bool,
+    /// Keep highlighting search matches after the search prompt closes,
+    /// until Escape is pressed.
+    pub persist_search_highlight: bool,
+    /// Keep prompt histories between sessions.
+    pub persist_history: bool,
+    /// Also copy to the system clipboard through the terminal, with OSC 52
+    /// escape sequences. Not every terminal supports them.
+    pub osc52_clipboard: bool,
+    /// Take mouse clicks from the terminal: a click moves the cursor and
+    /// Alt+Click adds one. Selecting text with the mouse then usually takes
+    /// Shift held down.
+    pub mouse: bool,
+    /// vi-style editing: keys move and act in Normal mode, and `i` and
+    /// the like enter Insert mode to type. Off, keys always type and
+    /// actions come from the key bindings.
+    pub modal: bool,
+    pub keymap: Keymap,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tab_stop: 8,
+            soft_tabs: false,
+            auto_indent: true,
+            auto_close: true,
+            line_numbers: false,
+            wrap: false,
+            wrap_marker: "↪".into(),
+            move_by_screen_row: true,
+            start_of_line: true,
+            scroll_off: 3,
+            side_scroll_off: 5,
+            highlight_trailing_whitespace: true,
+            highlight_mixed_indent: true,
+            whitespace_exempt: vec!["md".into(), "markdown".into()],
+            show_whitespace: false,
+            show_all_spaces: false,
+            eol_marker: None,
+            strip_trailing_whitespace_on_save: false,
+            autosave_interval: 0,
+            make_backup: false,
+            final_newline: true,
+            collapse_trailing_blank_lines: false,
+            persist_search_highlight: true,
+            persist_history: true,
+            osc52_clipboard: false,
+            mouse: true,
+            modal: false,
+            keymap: Keymap::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file, falling back to the defaults for anything
+    /// missing. Lines that could not be applied are returned as errors.
+    pub fn load() -> (Self, Vec<String>) {
+        let mut config = Self::default();
+        let mut errors = Vec::new();
+        let contents = match Self::path().map(fs::read_to_string) {
+            Some(Ok(contents)) => contents,
+            _ => return (config, errors),
+        };
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let result = match line.split_once('=') {
+                Some((option, value)) => config.set(option.trim(), value.trim()),
+                None => Err(format!("expected `option = value`, found `{}`", line)),
+            };
+            if let Err(err) = result {
+                errors.push(format!("config line {}: {}", number + 1, err));
+            }
+        }
+        (config, errors)
+    }
+
+    fn path() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join("dot").join("config"))
+    }
+
+    /// Sets `option` from its textual `value`.
+    pub fn set(&mut self, option: &str, value: &str) -> Result<(), String> {
+        match option {
+            "tab_stop" => self.tab_stop = parse_usize(value, 1..=32)?,
+            "soft_tabs" => self.soft_tabs = parse_bool(value)?,
+            "auto_indent" => self.auto_indent = parse_bool(value)?,
+            "auto_close" => self.auto_close = parse_bool(value)?,
+            "line_numbers" => self.line_numbers = parse_bool(value)?,
+            "wrap" => self.wrap = parse_bool(value)?,
+            "wrap_marker" => self.wrap_marker = value.into(),
+            "move_by_screen_row" => self.move_by_screen_row = parse_bool(value)?,
+            "start_of_line" => self.start_of_line = parse_bool(value)?,
+            "scroll_off" => self.scroll_off = parse_usize(value, 0..=999)?,
+            "side_scroll_off" => self.side_scroll_off = parse_usize(value, 0..=999)?,
+            "highlight_trailing_whitespace" => {
+                self.highlight_trailing_whitespace = parse_bool(value)?
+            }
+            "highlight_mixed_indent" => self.highlight_mixed_indent = parse_bool(value)?,
+            "whitespace_exempt" => self.whitespace_exempt = parse_list(value),
+            "show_whitespace" => self.show_whitespace = parse_bool(value)?,
+            "show_all_spaces" => self.show_all_spaces = parse_bool(value)?,
+            "eol_marker" => self.eol_marker = parse_char(value)?,
+            "strip_trailing_whitespace_on_save" => {
+                self.strip_trailing_whitespace_on_save = parse_bool(value)?
+            }
+            "autosave_interval" => self.autosave_interval = parse_usize(value, 0..=86400)?,
+            "make_backup" => self.make_backup = parse_bool(value)?,
+            "final_newline" => self.final_newline = parse_bool(value)?,
+            "collapse_trailing_blank_lines" => {
+                self.collapse_trailing_blank_lines = parse_bool(value)?
+            }
+            "persist_search_highlight" => self.persist_search_highlight = parse_bool(value)?,
+            "persist_history" => self.persist_history = parse_bool(value)?,
+            "osc52_clipboard" => self.osc52_clipboard = parse_bool(value)?,
+            "mouse" => self.mouse = parse_bool(value)?,
+            "modal" => self.modal = parse_bool(value)?,
+            _ => match option.strip_prefix("bind ") {
+                Some(key) => self.keymap.bind(key.trim(), value)?,
+                None => return Err(format!("unknown option `{}`", option)),
+            },
+        }
+        Ok(())
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "on" | "yes" | "1" => Ok(true),
+        "false" | "off" | "no" | "0" => Ok(false),
+        _ => Err(format!("expected a boolean, found `{}`", value)),
+    }
+}
+
+/// A single char, or `None` for an empty `value`.
+fn parse_char(value: &str) -> Result<Option<char>, String> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (ch, None) => Ok(ch),
+        _ => Err(format!("expected a single char, found `{}`", value)),
+    }
+}
+
+/// Comma-separated `value`, trimmed, leaving out empty items.
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn parse_usize(value: &str, range: RangeInclusive<usize>) -> Result<usize, String> {
+    match value.parse() {
+        Ok(number) if range.contains(&number) => Ok(number),
+        _ => Err(format!(
+            "expected a number from {} to {}, found `{}`",
+            range.start(),
+            range.end(),
+            value
+        )),
+    }
+}
@@ -0,0 +1,237 @@
+use std::ops::Range;
+
+/// Edits a diff looks for at most before giving up and calling everything
+/// between the common start and end changed, which bounds its time and
+/// memory on files with nothing in common.
+const MAX_EDITS: usize = 1000;
+
+/// A run of rows that differ between an old text and a new one.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// How a row of the new text differs from the old one.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LineChange {
+    Added,
+    Changed,
+    /// Rows of the old text were removed just above this one, or below it
+    /// for the last row.
+    Deleted,
+}
+
+/// The hunks turning `old` into `new`, in order, from a shortest edit
+/// script found with Myers' algorithm.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+    let matches = common(a, b).unwrap_or_default();
+    let mut hunks = Vec::new();
+    let (mut x, mut y) = (0, 0);
+    for (match_x, match_y) in matches.into_iter().chain([(a.len(), b.len())]) {
+        if match_x > x || match_y > y {
+            hunks.push(Hunk {
+                old: prefix + x..prefix + match_x,
+                new: prefix + y..prefix + match_y,
+            });
+        }
+        (x, y) = (match_x + 1, match_y + 1);
+    }
+    hunks
+}
+
+/// The pairs of equal rows a shortest edit script from `a` to `b` keeps,
+/// in order, or `None` if it takes more than [`MAX_EDITS`] edits.
+fn common<T: PartialEq>(a: &[T], b: &[T]) -> Option<Vec<(usize, usize)>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    // The furthest `x` reached on each diagonal `k = x - y`.
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    let at = |k: isize| (k + max + 1) as usize;
+    // `v` after each number of edits `d`, for diagonals `-d..=d`.
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    for d in 0..=max.min(MAX_EDITS as isize) {
+        let mut done = false;
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+                v[at(k + 1)]
+            } else {
+                v[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                done = true;
+                break;
+            }
+        }
+        trace.push(v[at(-d)..=at(d)].to_vec());
+        if done {
+            return Some(backtrack(&trace, n, m));
+        }
+    }
+    None
+}
+
+/// Walks the edit script `trace` leads to back from `(n, m)`, collecting
+/// the diagonal steps.
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let previous = &trace[d as usize - 1];
+        let get = |k: isize| previous[(k + d - 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && get(k - 1) < get(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = get(previous_k);
+        let previous_y = previous_x - previous_k;
+        // Where the edit from the previous diagonal lands, before the run
+        // of equal rows after it.
+        let (start_x, start_y) = if previous_k == k + 1 {
+            (previous_x, previous_y + 1)
+        } else {
+            (previous_x + 1, previous_y)
+        };
+        while x > start_x && y > start_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        matches.push((x as usize, y as usize));
+    }
+    matches.reverse();
+    matches
+}
+
+/// How row `row` of a new text of `rows` rows differs from the old one,
+/// going by its `hunks`.
+pub fn change_at(hunks: &[Hunk], row: usize, rows: usize) -> Option<LineChange> {
+    // Hunks are apart by at least an equal row, so those wholly above the
+    // row come first.
+    let above = hunks.partition_point(|hunk| {
+        if hunk.new.is_empty() {
+            hunk.new.start < row
+        } else {
+            hunk.new.end <= row
+        }
+    });
+    let hunk = hunks.get(above)?;
+    if hunk.new.contains(&row) {
+        Some(if hunk.old.is_empty() {
+            LineChange::Added
+        } else {
+            LineChange::Changed
+        })
+    } else if hunk.new.is_empty() && hunk_row(hunk, rows) == row {
+        Some(LineChange::Deleted)
+    } else {
+        None
+    }
+}
+
+/// The row of a new text of `rows` rows that `hunk` shows on.
+pub fn hunk_row(hunk: &Hunk, rows: usize) -> usize {
+    hunk.new.start.min(rows.saturating_sub(1))
+}
+
+/// The row of the old text matching row `row` of the new one, going by
+/// their `hunks`: the first old row of the hunk it is in, or the row it
+/// would be with the changes above it undone.
+pub fn old_row(hunks: &[Hunk], row: usize) -> usize {
+    let Some(hunk) = hunks.iter().rev().find(|hunk| hunk.new.start <= row) else {
+        return row;
+    };
+    if row < hunk.new.end {
+        hunk.old.start + (row - hunk.new.start).min(hunk.old.len().saturating_sub(1))
+    } else {
+        row - hunk.new.end + hunk.old.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hunk(old: Range<usize>, new: Range<usize>) -> Hunk {
+        Hunk { old, new }
+    }
+
+    #[test]
+    fn finds_no_hunks_in_equal_texts() {
+        assert_eq!(diff(&["a", "b"], &["a", "b"]), []);
+        assert_eq!(diff::<&str>(&[], &[]), []);
+    }
+
+    #[test]
+    fn finds_added_changed_and_deleted_rows() {
+        let old = ["a", "b", "c", "d", "e"];
+        let new = ["a", "x", "c", "e", "f"];
+        assert_eq!(
+            diff(&old, &new),
+            [hunk(1..2, 1..2), hunk(3..4, 3..3), hunk(5..5, 4..5)]
+        );
+    }
+
+    #[test]
+    fn classifies_each_row() {
+        let old = ["a", "b", "c", "d", "e"];
+        let new = ["a", "x", "c", "e", "f"];
+        let hunks = diff(&old, &new);
+        let changes: Vec<_> = (0..new.len()).map(|row| change_at(&hunks, row, new.len())).collect();
+        assert_eq!(
+            changes,
+            [
+                None,
+                Some(LineChange::Changed),
+                None,
+                Some(LineChange::Deleted),
+                Some(LineChange::Added),
+            ]
+        );
+    }
+
+    #[test]
+    fn shows_rows_deleted_at_the_end_on_the_last_row() {
+        let hunks = diff(&["a", "b", "c"], &["a"]);
+        assert_eq!(hunks, [hunk(1..3, 1..1)]);
+        assert_eq!(change_at(&hunks, 0, 1), Some(LineChange::Deleted));
+    }
+
+    #[test]
+    fn maps_rows_back_to_the_old_text() {
+        let hunks = diff(&["a", "b", "c", "d"], &["a", "x", "y", "z", "c", "d"]);
+        assert_eq!(hunks, [hunk(1..2, 1..4)]);
+        assert_eq!(old_row(&hunks, 0), 0);
+        assert_eq!(old_row(&hunks, 3), 1);
+        assert_eq!(old_row(&hunks, 4), 2);
+        assert_eq!(old_row(&hunks, 5), 3);
+    }
+
+    #[test]
+    fn gives_up_on_texts_with_nothing_in_common() {
+        let old: Vec<usize> = (0..2000).collect();
+        let new: Vec<usize> = (2000..4000).collect();
+        assert_eq!(diff(&old, &new), [hunk(0..2000, 0..2000)]);
+    }
+}
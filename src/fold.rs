@@ -0,0 +1,194 @@
+use std::ops::RangeInclusive;
+
+/// The rows folding away under `row`: those after it indented deeper, with
+/// the blank rows between them. `indent` gives a row's indentation, `None`
+/// for a blank one. `None` if the next row with text isn't deeper.
+pub fn body(
+    row: usize,
+    rows: usize,
+    indent: impl Fn(usize) -> Option<usize>,
+) -> Option<RangeInclusive<usize>> {
+    let level = indent(row)?;
+    let mut last = None;
+    for at in row + 1..rows {
+        match indent(at) {
+            None => {}
+            Some(deeper) if deeper > level => last = Some(at),
+            Some(_) => break,
+        }
+    }
+    last.map(|last| row + 1..=last)
+}
+
+/// The row heading the block `row` is in: the nearest one above with text
+/// indented less.
+pub fn header(row: usize, indent: impl Fn(usize) -> Option<usize>) -> Option<usize> {
+    let level = indent(row)?;
+    (0..row)
+        .rev()
+        .find(|&at| indent(at).is_some_and(|other| other < level))
+}
+
+/// Rows of a buffer hidden under the row before them. Folds stay on their
+/// rows as rows are inserted or deleted around them, and may sit inside
+/// each other.
+#[derive(Default)]
+pub struct Folds {
+    folds: Vec<RangeInclusive<usize>>,
+}
+
+impl Folds {
+    pub fn is_empty(&self) -> bool {
+        self.folds.is_empty()
+    }
+
+    pub fn close(&mut self, rows: RangeInclusive<usize>) {
+        self.folds.push(rows);
+    }
+
+    /// Opens every fold.
+    pub fn clear(&mut self) {
+        self.folds.clear();
+    }
+
+    /// Opens the fold under `header`. Returns whether there was one.
+    pub fn open(&mut self, header: usize) -> bool {
+        let count = self.folds.len();
+        self.folds.retain(|fold| *fold.start() != header + 1);
+        self.folds.len() < count
+    }
+
+    /// Opens the folds hiding `row`, so it shows.
+    pub fn reveal(&mut self, row: usize) {
+        self.folds.retain(|fold| !fold.contains(&row));
+    }
+
+    pub fn hidden(&self, row: usize) -> bool {
+        self.folds.iter().any(|fold| fold.contains(&row))
+    }
+
+    /// How many rows are folded away under `header`, if it shows a fold.
+    pub fn under(&self, header: usize) -> Option<usize> {
+        self.folds
+            .iter()
+            .filter(|fold| *fold.start() == header + 1)
+            .map(|fold| fold.end() - header)
+            .max()
+    }
+
+    /// The last row of the fold hiding `row` together with the most rows
+    /// after it, or `row` itself when it shows.
+    pub fn last_hidden(&self, row: usize) -> usize {
+        self.folds
+            .iter()
+            .filter(|fold| fold.contains(&row))
+            .map(|fold| *fold.end())
+            .max()
+            .unwrap_or(row)
+    }
+
+    /// The row showing for `row`: the header of the outermost fold hiding
+    /// it, or `row` itself.
+    pub fn shown(&self, row: usize) -> usize {
+        self.folds
+            .iter()
+            .filter(|fold| fold.contains(&row))
+            .map(|fold| fold.start() - 1)
+            .min()
+            .unwrap_or(row)
+    }
+
+    /// The first row after `row` that shows. May be past the end of the
+    /// buffer if the rest is folded away.
+    pub fn next_shown(&self, row: usize) -> usize {
+        let mut next = row + 1;
+        while self.hidden(next) {
+            next = self.last_hidden(next) + 1;
+        }
+        next
+    }
+
+    /// Moves folds below a row inserted at `at` down, and grows those it
+    /// lands inside.
+    pub fn row_inserted(&mut self, at: usize) {
+        for fold in &mut self.folds {
+            let (start, end) = (*fold.start(), *fold.end());
+            if at <= start {
+                *fold = start + 1..=end + 1;
+            } else if at <= end {
+                *fold = start..=end + 1;
+            }
+        }
+    }
+
+    /// Moves folds below row `at` up as it is deleted, shrinking those it
+    /// was in. Folds left empty go away, as do those it headed.
+    pub fn row_deleted(&mut self, at: usize) {
+        self.folds
+            .retain(|fold| *fold != (at..=at) && *fold.start() != at + 1);
+        for fold in &mut self.folds {
+            let (start, end) = (*fold.start(), *fold.end());
+            if at < start {
+                *fold = start - 1..=end - 1;
+            } else if at <= end {
+                *fold = start..=end - 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROWS: [&str; 7] = ["fn a() {", "    if b {", "        c();", "", "    }", "}", "d"];
+
+    fn indent(row: usize) -> Option<usize> {
+        let line = ROWS[row];
+        (!line.trim().is_empty()).then(|| line.len() - line.trim_start().len())
+    }
+
+    #[test]
+    fn finds_the_body_under_a_row() {
+        assert_eq!(body(0, ROWS.len(), indent), Some(1..=4));
+        assert_eq!(body(1, ROWS.len(), indent), Some(2..=2));
+        assert_eq!(body(2, ROWS.len(), indent), None);
+        assert_eq!(body(3, ROWS.len(), indent), None);
+    }
+
+    #[test]
+    fn finds_the_header_of_a_block() {
+        assert_eq!(header(2, indent), Some(1));
+        assert_eq!(header(4, indent), Some(0));
+        assert_eq!(header(0, indent), None);
+    }
+
+    #[test]
+    fn hides_rows_under_nested_folds() {
+        let mut folds = Folds::default();
+        folds.close(2..=3);
+        folds.close(1..=4);
+        assert!(folds.hidden(2) && !folds.hidden(0));
+        assert_eq!(folds.under(0), Some(4));
+        assert_eq!(folds.shown(3), 0);
+        assert_eq!(folds.next_shown(0), 5);
+        assert!(folds.open(0));
+        assert_eq!(folds.next_shown(1), 4);
+        folds.reveal(3);
+        assert!(folds.is_empty());
+    }
+
+    #[test]
+    fn moves_folds_with_rows_inserted_and_deleted() {
+        let mut folds = Folds::default();
+        folds.close(3..=5);
+        folds.row_inserted(0);
+        assert_eq!(folds.under(3), Some(3));
+        folds.row_inserted(5);
+        assert_eq!(folds.under(3), Some(4));
+        folds.row_deleted(0);
+        assert_eq!(folds.under(2), Some(4));
+        folds.row_deleted(2);
+        assert!(folds.is_empty());
+    }
+}
@@ -0,0 +1,72 @@
+use std::cmp::Reverse;
+
+/// Bonus for a char matched at the start of a word, where abbreviations
+/// usually point.
+const WORD_START: i32 = 10;
+
+/// Bonus for a char matched straight after the one before it.
+const CONSECUTIVE: i32 = 8;
+
+/// How well `pattern` matches `candidate` as a subsequence, ignoring case:
+/// higher for chars matched at word starts and in runs, lower for each char
+/// skipped between them. `None` unless `candidate` has every char of
+/// `pattern` in order.
+pub fn score(pattern: &str, candidate: &str) -> Option<i32> {
+    let lower = |ch: char| ch.to_lowercase().next().unwrap_or(ch);
+    let pattern: Vec<char> = pattern.chars().map(lower).collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    let Some((&first, rest)) = pattern.split_first() else {
+        return Some(0);
+    };
+    let bonus: Vec<i32> = (0..chars.len())
+        .map(|at| {
+            let word_start = at.checked_sub(1).map(|before| chars[before]).is_none_or(|before| {
+                !before.is_alphanumeric() || (before.is_lowercase() && chars[at].is_uppercase())
+            });
+            1 + if word_start { WORD_START } else { 0 }
+        })
+        .collect();
+    let chars: Vec<char> = chars.into_iter().map(lower).collect();
+    // The best score of the pattern so far with its last char matched at
+    // each char of the candidate, where it can be.
+    let mut best: Vec<Option<i32>> = (0..chars.len())
+        .map(|at| (chars[at] == first).then_some(bonus[at]))
+        .collect();
+    for &ch in rest {
+        let mut next = vec![None; chars.len()];
+        // The best of `best[before] + before` for the chars before the one
+        // before `at`, which leave a gap.
+        let mut gapped: Option<i32> = None;
+        for at in 0..chars.len() {
+            if at >= 2 {
+                let before = at - 2;
+                gapped = gapped.max(best[before].map(|score| score + before as i32));
+            }
+            if chars[at] != ch {
+                continue;
+            }
+            let run = at
+                .checked_sub(1)
+                .and_then(|before| best[before])
+                .map(|score| score + CONSECUTIVE);
+            let skip = gapped.map(|score| score - at as i32 + 1);
+            next[at] = run.max(skip).map(|score| score + bonus[at]);
+        }
+        best = next;
+    }
+    best.into_iter().flatten().max()
+}
+
+/// Indices of the `candidates` `pattern` matches, best first. Ties go to
+/// the shorter candidate, then to the earlier one.
+pub fn rank<'a>(pattern: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<usize> {
+    let mut scored: Vec<(usize, i32, usize)> = candidates
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, candidate)| {
+            score(pattern, candidate).map(|score| (index, score, candidate.len()))
+        })
+        .collect();
+    scored.sort_by_key(|&(_, score, len)| (Reverse(score), len));
+    scored.into_iter().map(|(index, ..)| index).collect()
+}
@@ -0,0 +1,53 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// Reads a file as of git's `HEAD` commit on a background thread, with
+/// `git show`, so a slow repository can't stall the UI.
+pub struct HeadJob {
+    receiver: Receiver<Option<Vec<String>>>,
+}
+
+impl HeadJob {
+    pub fn spawn(path: &Path) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => ".".into(),
+        };
+        let name = path.file_name().unwrap_or_default().to_owned();
+        thread::spawn(move || {
+            let mut spec = "HEAD:./".to_string();
+            spec.push_str(&name.to_string_lossy());
+            let output = Command::new("git")
+                .arg("-C")
+                .arg(&dir)
+                .args(["show", &spec])
+                .stdin(Stdio::null())
+                .stderr(Stdio::null())
+                .output();
+            let rows = output.ok().filter(|output| output.status.success()).map(|output| {
+                let mut rows: Vec<String> =
+                    String::from_utf8_lossy(&output.stdout).lines().map(String::from).collect();
+                if rows.is_empty() {
+                    rows.push(String::new());
+                }
+                rows
+            });
+            let _ = sender.send(rows);
+        });
+        Self { receiver }
+    }
+
+    /// The file's rows as committed once git is done, `Some(None)` if git
+    /// isn't installed or the file isn't in a repository or isn't
+    /// committed, and `None` while git is still running.
+    pub fn poll(&self) -> Option<Option<Vec<String>>> {
+        match self.receiver.try_recv() {
+            Ok(rows) => Some(rows),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(None),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::hex;
+use crate::search::Query;
+use crate::walk::FileWalk;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Matches a search across files keeps at most, so a huge tree can't fill
+/// up memory.
+pub const MAX_MATCHES: usize = 1000;
+
+/// Threads searching files at once, if the machine doesn't say how many
+/// it can run.
+const DEFAULT_WORKERS: usize = 4;
+
+/// A match in a file.
+#[derive(Clone)]
+pub struct GrepMatch {
+    /// The file's path, relative to the directory searched.
+    pub path: String,
+    pub row: usize,
+    /// Byte column of the match in `line`.
+    pub col: usize,
+    /// The row the match is on.
+    pub line: String,
+}
+
+/// Searches the text files under a directory for a query on background
+/// threads, leaving out what `.gitignore` files there leave out.
+///
+/// Dropping the job cancels the threads.
+pub struct GrepJob {
+    receiver: Receiver<Vec<GrepMatch>>,
+    cancel: Arc<AtomicBool>,
+    finished: bool,
+}
+
+impl GrepJob {
+    pub fn spawn(root: PathBuf, query: Query) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancelled = Arc::clone(&cancel);
+        thread::spawn(move || {
+            let mut walk = FileWalk::spawn(root.clone());
+            let (path_sender, path_receiver) = mpsc::channel::<String>();
+            let paths = Arc::new(Mutex::new(path_receiver));
+            let found = Arc::new(AtomicUsize::new(0));
+            let workers = thread::available_parallelism().map_or(DEFAULT_WORKERS, usize::from);
+            let workers: Vec<_> = (0..workers)
+                .map(|_| {
+                    let (root, query, sender) = (root.clone(), query.clone(), sender.clone());
+                    let (paths, found) = (Arc::clone(&paths), Arc::clone(&found));
+                    let cancelled = Arc::clone(&cancelled);
+                    thread::spawn(move || loop {
+                        let Ok(path) = paths.lock().unwrap().recv() else {
+                            break;
+                        };
+                        if cancelled.load(Ordering::Relaxed)
+                            || found.load(Ordering::Relaxed) >= MAX_MATCHES
+                        {
+                            break;
+                        }
+                        let mut matches = search_file(&root, path, &query);
+                        let before = found.fetch_add(matches.len(), Ordering::Relaxed);
+                        matches.truncate(MAX_MATCHES.saturating_sub(before));
+                        if !matches.is_empty() && sender.send(matches).is_err() {
+                            break;
+                        }
+                    })
+                })
+                .collect();
+            drop(paths);
+            // Once every worker has stopped, the paths left have nowhere
+            // to go.
+            'walk: while let Some(batch) = walk.wait() {
+                for path in batch {
+                    if cancelled.load(Ordering::Relaxed) || path_sender.send(path).is_err() {
+                        break 'walk;
+                    }
+                }
+            }
+            drop(path_sender);
+            for worker in workers {
+                let _ = worker.join();
+            }
+        });
+        Self {
+            receiver,
+            cancel,
+            finished: false,
+        }
+    }
+
+    /// Appends the matches found since the last call to `matches`, without
+    /// waiting for more. Returns whether anything changed, including the
+    /// search finishing.
+    pub fn receive(&mut self, matches: &mut Vec<GrepMatch>) -> bool {
+        let mut changed = false;
+        while !self.finished {
+            match self.receiver.try_recv() {
+                Ok(batch) => matches.extend(batch),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => self.finished = true,
+            }
+            changed = true;
+        }
+        changed
+    }
+
+    /// Whether every file has been searched and every match received.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+}
+
+impl Drop for GrepJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The matches for `query` in file `path` under `root`, none if it can't
+/// be read or looks binary.
+fn search_file(root: &Path, path: String, query: &Query) -> Vec<GrepMatch> {
+    let Ok(bytes) = fs::read(root.join(&path)) else {
+        return Vec::new();
+    };
+    if hex::is_binary(&bytes) {
+        return Vec::new();
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    let mut matches = Vec::new();
+    for (row, line) in text.lines().enumerate() {
+        for col in query.matches(line) {
+            matches.push(GrepMatch {
+                path: path.clone(),
+                row,
+                col,
+                line: line.to_string(),
+            });
+            if matches.len() == MAX_MATCHES {
+                return matches;
+            }
+        }
+    }
+    matches
+}
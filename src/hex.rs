@@ -0,0 +1,156 @@
+/// Bytes shown on each row of a hex view.
+pub const BYTES_PER_ROW: usize = 16;
+
+/// Width of the offset at the start of each row, with its `: `.
+const OFFSET_WIDTH: usize = 10;
+
+/// Bytes looked at for a NUL to tell a binary file from a text one, as git
+/// does.
+pub const SNIFF_LEN: usize = 8000;
+
+/// Whether a file starting with `start` looks binary rather than text.
+pub fn is_binary(start: &[u8]) -> bool {
+    start[..start.len().min(SNIFF_LEN)].contains(&0)
+}
+
+/// Row `row` of `bytes` as `xxd` prints it: the offset of its first byte,
+/// the bytes in hex in pairs, and the bytes again as ASCII, `.` for those
+/// that aren't printable.
+pub fn line(bytes: &[u8], row: usize) -> String {
+    let start = (row * BYTES_PER_ROW).min(bytes.len());
+    let chunk = &bytes[start..(start + BYTES_PER_ROW).min(bytes.len())];
+    let mut line = format!("{:08x}: ", start);
+    for index in 0..BYTES_PER_ROW {
+        match chunk.get(index) {
+            Some(byte) => line.push_str(&format!("{:02x}", byte)),
+            None => line.push_str("  "),
+        }
+        if index % 2 == 1 {
+            line.push(' ');
+        }
+    }
+    line.push(' ');
+    line.extend(chunk.iter().map(|&byte| match byte {
+        b' '..=b'~' => byte as char,
+        _ => '.',
+    }));
+    line
+}
+
+/// The column of `line` the hex digits of byte `index` of its row start
+/// at.
+pub fn hex_column(index: usize) -> usize {
+    OFFSET_WIDTH + index / 2 * 5 + index % 2 * 2
+}
+
+/// The column of `line` byte `index` of its row shows at as ASCII.
+pub fn ascii_column(index: usize) -> usize {
+    hex_column(BYTES_PER_ROW) + 1 + index
+}
+
+/// A buffer's file shown as bytes rather than text, read-only.
+pub struct HexView {
+    pub bytes: Vec<u8>,
+    /// Index of the byte under the cursor.
+    pub cursor: usize,
+    /// The first row shown.
+    pub row_offset: usize,
+}
+
+impl HexView {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            cursor: 0,
+            row_offset: 0,
+        }
+    }
+
+    /// The lines showing `data` from byte `offset` on, rounded down to the
+    /// start of its row, cut to fit a window `win_size` columns wide and
+    /// rows tall.
+    pub fn render(data: &[u8], win_size: (usize, usize), offset: usize) -> Vec<String> {
+        let (columns, rows) = win_size;
+        // An empty file still shows its offset.
+        let total = data.len().div_ceil(BYTES_PER_ROW).max(1);
+        (offset / BYTES_PER_ROW..total)
+            .take(rows)
+            .map(|row| line(data, row).chars().take(columns).collect())
+            .collect()
+    }
+
+    /// Moves the cursor `delta` bytes along, stopping at either end.
+    pub fn move_by(&mut self, delta: isize) {
+        let last = self.bytes.len().saturating_sub(1);
+        self.cursor = self.cursor.saturating_add_signed(delta).min(last);
+    }
+
+    /// Scrolls so the cursor's row is among `screen_rows` rows shown.
+    pub fn scroll(&mut self, screen_rows: usize) {
+        let row = self.cursor / BYTES_PER_ROW;
+        self.row_offset = self.row_offset.min(row);
+        if row >= self.row_offset + screen_rows {
+            self.row_offset = row + 1 - screen_rows;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_binary_from_text() {
+        assert!(is_binary(b"ELF\0\x01"));
+        assert!(!is_binary(b"plain text\n"));
+        let mut late = vec![b'a'; SNIFF_LEN];
+        late.push(0);
+        assert!(!is_binary(&late));
+    }
+
+    #[test]
+    fn lays_out_a_row_as_xxd_does() {
+        let bytes = b"Hello, world!\n\x00\xffmore";
+        assert_eq!(
+            line(bytes, 0),
+            "00000000: 4865 6c6c 6f2c 2077 6f72 6c64 210a 00ff  Hello, world!..."
+        );
+        assert_eq!(
+            line(bytes, 1),
+            "00000010: 6d6f 7265                                more"
+        );
+    }
+
+    #[test]
+    fn finds_the_columns_of_a_byte() {
+        let bytes = b"0123456789abcdef";
+        let row = line(bytes, 0);
+        for index in [0, 1, 2, 15] {
+            let hex = format!("{:02x}", bytes[index]);
+            assert_eq!(&row[hex_column(index)..hex_column(index) + 2], hex);
+            assert_eq!(row.as_bytes()[ascii_column(index)], bytes[index]);
+        }
+    }
+
+    #[test]
+    fn renders_the_rows_that_fit() {
+        let data = [0u8; 40];
+        let lines = HexView::render(&data, (12, 2), 20);
+        assert_eq!(lines, ["00000010: 00", "00000020: 00"]);
+        assert_eq!(HexView::render(&[], (80, 5), 0), ["00000000: ".to_string() + &" ".repeat(41)]);
+    }
+
+    #[test]
+    fn moves_and_scrolls_within_the_bytes() {
+        let mut view = HexView::new(vec![0; 100]);
+        view.move_by(-1);
+        assert_eq!(view.cursor, 0);
+        view.move_by(1000);
+        assert_eq!(view.cursor, 99);
+        view.scroll(3);
+        assert_eq!(view.row_offset, 4);
+        view.move_by(-99);
+        view.scroll(3);
+        assert_eq!(view.row_offset, 0);
+    }
+}
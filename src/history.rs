@@ -0,0 +1,108 @@
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::{env, fs};
+
+/// Entries each history keeps; older ones are dropped.
+const MAX_ENTRIES: usize = 100;
+
+/// Previous inputs of one kind of prompt, oldest first.
+#[derive(Default)]
+pub struct History {
+    entries: Vec<String>,
+}
+
+impl History {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, at: usize) -> Option<&str> {
+        self.entries.get(at).map(String::as_str)
+    }
+
+    /// Records `entry` unless it repeats the latest one.
+    pub fn push(&mut self, entry: String) {
+        if self.entries.last() == Some(&entry) {
+            return;
+        }
+        self.entries.push(entry);
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+    }
+}
+
+/// The history of every prompt, saved to `$XDG_DATA_HOME/dot/history` (or
+/// `~/.local/share/dot/history`) between sessions as `kind<TAB>entry` lines.
+#[derive(Default)]
+pub struct Histories {
+    pub goto: History,
+    pub command: History,
+    pub search: History,
+    /// Replacement text typed at the replace prompt.
+    pub replace: History,
+    /// File names typed at the save-as prompt.
+    pub file: History,
+}
+
+impl Histories {
+    /// Reads the saved histories, starting empty if there are none.
+    pub fn load() -> Self {
+        let mut histories = Self::default();
+        let Some(Ok(contents)) = Self::path().map(fs::read_to_string) else {
+            return histories;
+        };
+        for (kind, entry) in contents.lines().filter_map(|line| line.split_once('\t')) {
+            if let Some(history) = histories.by_kind(kind) {
+                history.push(entry.into());
+            }
+        }
+        histories
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = Self::path() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+        for (kind, history) in self.kinds() {
+            for entry in &history.entries {
+                writeln!(writer, "{}\t{}", kind, entry)?;
+            }
+        }
+        writer.flush()
+    }
+
+    fn kinds(&self) -> [(&str, &History); 5] {
+        [
+            ("goto", &self.goto),
+            ("command", &self.command),
+            ("search", &self.search),
+            ("replace", &self.replace),
+            ("file", &self.file),
+        ]
+    }
+
+    fn by_kind(&mut self, kind: &str) -> Option<&mut History> {
+        match kind {
+            "goto" => Some(&mut self.goto),
+            "command" => Some(&mut self.command),
+            "search" => Some(&mut self.search),
+            "replace" => Some(&mut self.replace),
+            "file" => Some(&mut self.file),
+            _ => None,
+        }
+    }
+
+    fn path() -> Option<PathBuf> {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+            })
+            .map(|dir| dir.join("dot").join("history"))
+    }
+}
@@ -0,0 +1,105 @@
+/// A line of a `.gitignore` file.
+struct Rule {
+    pattern: String,
+    /// `!pattern`, which brings back what earlier rules left out.
+    negated: bool,
+    /// `pattern/`, which only leaves out directories.
+    dir_only: bool,
+    /// Patterns with a `/` before their end match the whole path from the
+    /// `.gitignore`'s directory, others just the last part.
+    anchored: bool,
+}
+
+/// The rules of a `.gitignore` file, for the paths under its directory.
+/// Globs take `*`, `?` and `**`, but not character classes.
+pub struct Ignore {
+    /// The directory of the file, relative to where the walk started.
+    base: String,
+    rules: Vec<Rule>,
+}
+
+impl Ignore {
+    /// Reads the rules in `contents`, of a `.gitignore` in `base`.
+    pub fn parse(base: &str, contents: &str) -> Self {
+        let rules = contents
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (negated, line) = match line.strip_prefix('!') {
+                    Some(line) => (true, line),
+                    None => (false, line.strip_prefix('\\').unwrap_or(line)),
+                };
+                let (dir_only, line) = match line.strip_suffix('/') {
+                    Some(line) => (true, line),
+                    None => (false, line),
+                };
+                Rule {
+                    pattern: line.trim_start_matches('/').to_string(),
+                    negated,
+                    dir_only,
+                    anchored: line.contains('/'),
+                }
+            })
+            .collect();
+        Self {
+            base: base.to_string(),
+            rules,
+        }
+    }
+
+    /// Whether `path`, relative to where the walk started, is left out by
+    /// these rules, `Some(false)` if a `!` rule brings it back, or `None`
+    /// if no rule is about it. Later rules win, as in git.
+    fn verdict(&self, path: &str, is_dir: bool) -> Option<bool> {
+        let path = if self.base.is_empty() {
+            path
+        } else {
+            path.strip_prefix(&self.base)?.strip_prefix('/')?
+        };
+        let name = path.rsplit('/').next().unwrap_or(path);
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                (is_dir || !rule.dir_only)
+                    && glob(
+                        rule.pattern.as_bytes(),
+                        if rule.anchored { path } else { name }.as_bytes(),
+                    )
+            })
+            .map(|rule| !rule.negated)
+    }
+}
+
+/// Whether `ignores`, outermost directory first, leave out `path`. Rules
+/// nearer the path win over those further up.
+pub fn is_ignored(ignores: &[Ignore], path: &str, is_dir: bool) -> bool {
+    ignores
+        .iter()
+        .rev()
+        .find_map(|ignore| ignore.verdict(path, is_dir))
+        .unwrap_or(false)
+}
+
+/// Matches `text` against a glob `pattern` where `*` and `?` stay within
+/// one path component and `**` goes across them.
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => {
+            // `a/**/b` also matches `a/b`.
+            let after_slash = rest.strip_prefix(b"/");
+            (0..=text.len()).any(|at| {
+                glob(rest, &text[at..]) || after_slash.is_some_and(|rest| glob(rest, &text[at..]))
+            })
+        }
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&at| at == 0 || text[at - 1] != b'/')
+            .any(|at| glob(rest, &text[at..])),
+        [b'?', rest @ ..] => {
+            text.first().is_some_and(|&byte| byte != b'/') && glob(rest, &text[1..])
+        }
+        [byte, rest @ ..] => text.first() == Some(byte) && glob(rest, &text[1..]),
+    }
+}
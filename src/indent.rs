@@ -0,0 +1,88 @@
+/// Indented lines looked at before deciding on a style.
+const SAMPLE_LINES: usize = 100;
+
+/// How a file indents its lines.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Indent {
+    Tabs,
+    Spaces(usize),
+}
+
+/// Guesses the indentation style from the leading whitespace of `lines`,
+/// the way editorconfig-style detectors do: whichever of tabs or spaces
+/// clearly dominates wins, and the space width is the most common increase
+/// in indentation between consecutive lines. Returns `None` when the sample
+/// is mixed or has no indentation to go by.
+pub fn detect<'a>(lines: impl IntoIterator<Item = &'a str>) -> Option<Indent> {
+    let mut tabs = 0;
+    let mut spaces = 0;
+    // Number of times the indentation grew by 0..=8 spaces.
+    let mut increases = [0; 9];
+    let mut previous = 0;
+    for line in lines {
+        let content = line.trim_start_matches([' ', '\t']);
+        if content.is_empty() {
+            continue;
+        }
+        let indent = &line[..line.len() - content.len()];
+        if indent.starts_with('\t') {
+            tabs += 1;
+            continue;
+        }
+        if !indent.is_empty() {
+            spaces += 1;
+        }
+        if !indent.contains('\t') {
+            let width = indent.len();
+            if width > previous && width - previous < increases.len() {
+                increases[width - previous] += 1;
+            }
+            previous = width;
+        }
+        if tabs + spaces >= SAMPLE_LINES {
+            break;
+        }
+    }
+    if tabs > spaces * 2 {
+        return Some(Indent::Tabs);
+    }
+    if spaces > tabs * 2 {
+        // Single-space steps are usually alignment rather than indentation.
+        let (width, count) = (2..increases.len())
+            .map(|width| (width, increases[width]))
+            .rev()
+            .max_by_key(|&(_, count)| count)?;
+        return (count > 0).then_some(Indent::Spaces(width));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_two_spaces() {
+        let lines = ["fn main() {", "  if x {", "    y();", "  }", "}"];
+        assert_eq!(detect(lines), Some(Indent::Spaces(2)));
+    }
+
+    #[test]
+    fn detects_four_spaces() {
+        let lines = ["fn main() {", "    if x {", "        y();", "    }", "}"];
+        assert_eq!(detect(lines), Some(Indent::Spaces(4)));
+    }
+
+    #[test]
+    fn detects_tabs() {
+        let lines = ["fn main() {", "\tif x {", "\t\ty();", "\t}", "}"];
+        assert_eq!(detect(lines), Some(Indent::Tabs));
+    }
+
+    #[test]
+    fn gives_up_on_a_mix() {
+        let lines = ["a", "\tb", "    c", "\td", "    e"];
+        assert_eq!(detect(lines), None);
+        assert_eq!(detect(["a", "b"]), None);
+    }
+}
@@ -0,0 +1,134 @@
+/// Jumps remembered per session; older ones are dropped.
+const MAX_JUMPS: usize = 100;
+
+/// A cursor position to jump back to.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Jump {
+    /// `EditorRows::id` of the buffer the position is in.
+    pub buffer: usize,
+    pub row: usize,
+    pub col: usize,
+}
+
+/// Positions left by large cursor jumps, walked with Ctrl-O and Ctrl-I.
+#[derive(Default)]
+pub struct JumpList {
+    jumps: Vec<Jump>,
+    /// Position in `jumps` while walking it; `jumps.len()` when not.
+    index: usize,
+}
+
+impl JumpList {
+    /// Records `from` as the position a jump left. Jumps that were walked
+    /// back over are forgotten, as is an older entry for the same row.
+    pub fn push(&mut self, from: Jump) {
+        self.jumps.truncate(self.index);
+        self.jumps
+            .retain(|jump| (jump.buffer, jump.row) != (from.buffer, from.row));
+        self.jumps.push(from);
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.index = self.jumps.len();
+    }
+
+    /// Steps back from `current`, which is remembered so that
+    /// [`JumpList::forward`] can return to it.
+    pub fn back(&mut self, current: Jump) -> Option<Jump> {
+        if self.index == 0 {
+            return None;
+        }
+        if self.index == self.jumps.len() {
+            self.jumps.push(current);
+        }
+        self.index -= 1;
+        Some(self.jumps[self.index])
+    }
+
+    pub fn forward(&mut self) -> Option<Jump> {
+        if self.index + 1 >= self.jumps.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(self.jumps[self.index])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jumps.is_empty()
+    }
+
+    /// Lists the jumps by `label`, oldest first, with `>` before the one
+    /// the walk is at.
+    pub fn summary(&self, label: impl Fn(&Jump) -> String) -> String {
+        self.jumps
+            .iter()
+            .enumerate()
+            .map(|(at, jump)| {
+                let marker = if at == self.index { ">" } else { "" };
+                format!("{}{}", marker, label(jump))
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Forgets the jumps into buffers `open` says are gone.
+    pub fn retain(&mut self, open: impl Fn(usize) -> bool) {
+        let gone_before = self.jumps[..self.index]
+            .iter()
+            .filter(|jump| !open(jump.buffer))
+            .count();
+        self.jumps.retain(|jump| open(jump.buffer));
+        self.index -= gone_before;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(row: usize) -> Jump {
+        Jump { buffer: 0, row, col: 0 }
+    }
+
+    fn rows(list: &JumpList) -> String {
+        list.summary(|jump| jump.row.to_string())
+    }
+
+    #[test]
+    fn walks_back_and_forward() {
+        let mut list = JumpList::default();
+        list.push(at(1));
+        list.push(at(2));
+        assert_eq!(list.back(at(3)), Some(at(2)));
+        assert_eq!(list.back(at(2)), Some(at(1)));
+        assert_eq!(list.back(at(1)), None);
+        assert_eq!(rows(&list), ">1 2 3");
+        assert_eq!(list.forward(), Some(at(2)));
+        assert_eq!(list.forward(), Some(at(3)));
+        assert_eq!(list.forward(), None);
+    }
+
+    #[test]
+    fn forgets_jumps_walked_back_over_and_repeated_rows() {
+        let mut list = JumpList::default();
+        list.push(at(1));
+        list.push(at(2));
+        list.push(at(3));
+        list.back(at(4));
+        list.back(at(3));
+        list.push(at(5));
+        assert_eq!(rows(&list), "1 5");
+        list.push(at(1));
+        assert_eq!(rows(&list), "5 1");
+    }
+
+    #[test]
+    fn forgets_jumps_into_closed_buffers() {
+        let mut list = JumpList::default();
+        list.push(Jump { buffer: 1, row: 5, col: 0 });
+        list.push(at(1));
+        list.back(at(2));
+        list.retain(|buffer| buffer == 0);
+        assert_eq!(rows(&list), ">1 2");
+    }
+}
@@ -0,0 +1,519 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+
+/// Something a key can be bound to.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Action {
+    Quit,
+    Save,
+    Reload,
+    Find,
+    /// Prompts for a file under the working directory by a fuzzy match of
+    /// its path, and opens it.
+    FindFile,
+    /// Searches the files under the current directory and lists the
+    /// matches.
+    FindInFiles,
+    /// Opens the next match of the last search across files.
+    NextMatch,
+    PreviousMatch,
+    Replace,
+    GotoLine,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    LineStart,
+    LineEnd,
+    PageUp,
+    PageDown,
+    HalfPageUp,
+    HalfPageDown,
+    /// Scrolls a row up, taking the cursor along only at the edge.
+    ScrollLineUp,
+    ScrollLineDown,
+    /// Scrolls the cursor's row to the middle of the screen.
+    ScrollCenter,
+    ScrollTop,
+    ScrollBottom,
+    /// Folds the indented block at the cursor away, or opens the fold
+    /// there.
+    ToggleFold,
+    OpenFold,
+    CloseFold,
+    OpenAllFolds,
+    CloseAllFolds,
+    SelectUp,
+    SelectDown,
+    SelectLeft,
+    SelectRight,
+    SelectAll,
+    SelectNextOccurrence,
+    SelectBlockUp,
+    SelectBlockDown,
+    SelectBlockLeft,
+    SelectBlockRight,
+    WordLeft,
+    WordRight,
+    SelectWordLeft,
+    SelectWordRight,
+    JumpBack,
+    JumpForward,
+    /// Compares the buffer with git's `HEAD` again.
+    RefreshDiff,
+    /// Moves to the next change the diff shows.
+    NextChange,
+    PreviousChange,
+    SetMark,
+    JumpToMark,
+    RecordMacro,
+    PlayMacro,
+    Window,
+    NextBuffer,
+    PreviousBuffer,
+    /// Lists the open buffers to pick one from.
+    ListBuffers,
+    CloseBuffer,
+    Copy,
+    Cut,
+    Paste,
+    DeleteForward,
+    DeleteLine,
+    DeleteWordBack,
+    DeleteWordForward,
+    JoinLines,
+    Uppercase,
+    Lowercase,
+    TitleCase,
+    TransposeChars,
+    TransposeWords,
+    ToggleComment,
+    ToggleWrap,
+    ToggleWhitespace,
+    /// Shows the buffer's bytes in hex, or its text again.
+    ToggleHex,
+    Undo,
+    Redo,
+    /// Prompts for an action by name and runs it.
+    CommandPalette,
+}
+
+/// The names actions go by in the config file and the command palette.
+const NAMES: &[(&str, Action)] = &[
+    ("quit", Action::Quit),
+    ("save", Action::Save),
+    ("reload", Action::Reload),
+    ("find", Action::Find),
+    ("find_file", Action::FindFile),
+    ("find_in_files", Action::FindInFiles),
+    ("next_match", Action::NextMatch),
+    ("previous_match", Action::PreviousMatch),
+    ("replace", Action::Replace),
+    ("goto_line", Action::GotoLine),
+    ("move_up", Action::MoveUp),
+    ("move_down", Action::MoveDown),
+    ("move_left", Action::MoveLeft),
+    ("move_right", Action::MoveRight),
+    ("line_start", Action::LineStart),
+    ("line_end", Action::LineEnd),
+    ("page_up", Action::PageUp),
+    ("page_down", Action::PageDown),
+    ("half_page_up", Action::HalfPageUp),
+    ("half_page_down", Action::HalfPageDown),
+    ("scroll_line_up", Action::ScrollLineUp),
+    ("scroll_line_down", Action::ScrollLineDown),
+    ("scroll_center", Action::ScrollCenter),
+    ("scroll_top", Action::ScrollTop),
+    ("scroll_bottom", Action::ScrollBottom),
+    ("toggle_fold", Action::ToggleFold),
+    ("open_fold", Action::OpenFold),
+    ("close_fold", Action::CloseFold),
+    ("open_all_folds", Action::OpenAllFolds),
+    ("close_all_folds", Action::CloseAllFolds),
+    ("select_up", Action::SelectUp),
+    ("select_down", Action::SelectDown),
+    ("select_left", Action::SelectLeft),
+    ("select_right", Action::SelectRight),
+    ("select_all", Action::SelectAll),
+    ("select_next_occurrence", Action::SelectNextOccurrence),
+    ("select_block_up", Action::SelectBlockUp),
+    ("select_block_down", Action::SelectBlockDown),
+    ("select_block_left", Action::SelectBlockLeft),
+    ("select_block_right", Action::SelectBlockRight),
+    ("word_left", Action::WordLeft),
+    ("word_right", Action::WordRight),
+    ("select_word_left", Action::SelectWordLeft),
+    ("select_word_right", Action::SelectWordRight),
+    ("jump_back", Action::JumpBack),
+    ("jump_forward", Action::JumpForward),
+    ("refresh_diff", Action::RefreshDiff),
+    ("next_change", Action::NextChange),
+    ("previous_change", Action::PreviousChange),
+    ("set_mark", Action::SetMark),
+    ("jump_to_mark", Action::JumpToMark),
+    ("record_macro", Action::RecordMacro),
+    ("play_macro", Action::PlayMacro),
+    ("window", Action::Window),
+    ("next_buffer", Action::NextBuffer),
+    ("previous_buffer", Action::PreviousBuffer),
+    ("list_buffers", Action::ListBuffers),
+    ("close_buffer", Action::CloseBuffer),
+    ("copy", Action::Copy),
+    ("cut", Action::Cut),
+    ("paste", Action::Paste),
+    ("delete_forward", Action::DeleteForward),
+    ("delete_line", Action::DeleteLine),
+    ("delete_word_back", Action::DeleteWordBack),
+    ("delete_word_forward", Action::DeleteWordForward),
+    ("join_lines", Action::JoinLines),
+    ("uppercase", Action::Uppercase),
+    ("lowercase", Action::Lowercase),
+    ("title_case", Action::TitleCase),
+    ("transpose_chars", Action::TransposeChars),
+    ("transpose_words", Action::TransposeWords),
+    ("toggle_comment", Action::ToggleComment),
+    ("toggle_wrap", Action::ToggleWrap),
+    ("toggle_whitespace", Action::ToggleWhitespace),
+    ("toggle_hex", Action::ToggleHex),
+    ("undo", Action::Undo),
+    ("redo", Action::Redo),
+    ("command_palette", Action::CommandPalette),
+];
+
+impl Action {
+    fn parse(name: &str) -> Option<Self> {
+        NAMES
+            .iter()
+            .find(|&&(other, _)| other == name)
+            .map(|&(_, action)| action)
+    }
+
+    /// Every action, in the order of their names in the config file.
+    pub fn all() -> impl Iterator<Item = Self> {
+        NAMES.iter().map(|&(_, action)| action)
+    }
+
+    /// The action's name in the config file, e.g. `toggle_comment`.
+    pub fn name(self) -> &'static str {
+        NAMES
+            .iter()
+            .find(|&&(_, action)| action == self)
+            .map(|&(name, _)| name)
+            .expect("every action has a name")
+    }
+}
+
+/// Maps keys to the actions they run in every mode. Set from the config
+/// file with `bind <key> = <action>` lines, e.g. `bind ctrl-x = quit`, or
+/// `bind <key> = none` to remove a binding.
+pub struct Keymap {
+    bindings: HashMap<(KeyCode, KeyModifiers), Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = [
+            (KeyCode::Char('q'), KeyModifiers::CONTROL, Action::Quit),
+            (KeyCode::Char('s'), KeyModifiers::CONTROL, Action::Save),
+            (KeyCode::Char('p'), KeyModifiers::CONTROL, Action::CommandPalette),
+            (KeyCode::F(5), KeyModifiers::NONE, Action::Reload),
+            (KeyCode::Char('f'), KeyModifiers::CONTROL, Action::Find),
+            (KeyCode::Char('f'), KeyModifiers::ALT, Action::FindFile),
+            // Alt-Shift-F.
+            (KeyCode::Char('F'), KeyModifiers::ALT, Action::FindInFiles),
+            // Ctrl-\, as in nano.
+            (KeyCode::Char('4'), KeyModifiers::CONTROL, Action::Replace),
+            (KeyCode::Char('g'), KeyModifiers::CONTROL, Action::GotoLine),
+            (KeyCode::Char('k'), KeyModifiers::CONTROL, Action::DeleteLine),
+            (KeyCode::Char('j'), KeyModifiers::CONTROL, Action::JoinLines),
+            // Alt-U, Alt-L and Alt-C, as in Emacs.
+            (KeyCode::Char('u'), KeyModifiers::ALT, Action::Uppercase),
+            (KeyCode::Char('l'), KeyModifiers::ALT, Action::Lowercase),
+            (KeyCode::Char('c'), KeyModifiers::ALT, Action::TitleCase),
+            (KeyCode::Char('t'), KeyModifiers::CONTROL, Action::TransposeChars),
+            (KeyCode::Char('t'), KeyModifiers::ALT, Action::TransposeWords),
+            (KeyCode::Char('z'), KeyModifiers::CONTROL, Action::Undo),
+            // Ctrl-R, as in vim.
+            (KeyCode::Char('r'), KeyModifiers::CONTROL, Action::Redo),
+            // Alt-Z, as in VS Code.
+            (KeyCode::Char('z'), KeyModifiers::ALT, Action::ToggleWrap),
+            // Ctrl-/, which most terminals send as Ctrl-7.
+            (KeyCode::Char('7'), KeyModifiers::CONTROL, Action::ToggleComment),
+            (KeyCode::Char('/'), KeyModifiers::CONTROL, Action::ToggleComment),
+            (KeyCode::Char('w'), KeyModifiers::ALT, Action::ToggleWhitespace),
+            (KeyCode::Char('x'), KeyModifiers::ALT, Action::ToggleHex),
+            (KeyCode::Char('g'), KeyModifiers::ALT, Action::RefreshDiff),
+            (KeyCode::Up, KeyModifiers::NONE, Action::MoveUp),
+            (KeyCode::Down, KeyModifiers::NONE, Action::MoveDown),
+            (KeyCode::Left, KeyModifiers::NONE, Action::MoveLeft),
+            (KeyCode::Right, KeyModifiers::NONE, Action::MoveRight),
+            (KeyCode::Home, KeyModifiers::NONE, Action::LineStart),
+            (KeyCode::End, KeyModifiers::NONE, Action::LineEnd),
+            (KeyCode::PageUp, KeyModifiers::NONE, Action::PageUp),
+            (KeyCode::PageDown, KeyModifiers::NONE, Action::PageDown),
+            (KeyCode::Char('u'), KeyModifiers::CONTROL, Action::HalfPageUp),
+            (KeyCode::Up, KeyModifiers::CONTROL, Action::ScrollLineUp),
+            (KeyCode::Down, KeyModifiers::CONTROL, Action::ScrollLineDown),
+            // Ctrl-L, as in Emacs.
+            (KeyCode::Char('l'), KeyModifiers::CONTROL, Action::ScrollCenter),
+            (KeyCode::Up, KeyModifiers::SHIFT, Action::SelectUp),
+            (KeyCode::Down, KeyModifiers::SHIFT, Action::SelectDown),
+            (KeyCode::Left, KeyModifiers::SHIFT, Action::SelectLeft),
+            (KeyCode::Right, KeyModifiers::SHIFT, Action::SelectRight),
+            (KeyCode::Char('a'), KeyModifiers::CONTROL, Action::SelectAll),
+            (KeyCode::Char('d'), KeyModifiers::CONTROL, Action::SelectNextOccurrence),
+            // Normal mode's Ctrl-D scrolls until there is a word selected.
+            (KeyCode::Char('d'), KeyModifiers::ALT, Action::SelectNextOccurrence),
+            (KeyCode::Char('o'), KeyModifiers::CONTROL, Action::JumpBack),
+            // Terminals send Ctrl-I as Tab.
+            (KeyCode::Tab, KeyModifiers::NONE, Action::JumpForward),
+            (KeyCode::Char('b'), KeyModifiers::CONTROL, Action::SetMark),
+            (KeyCode::Char('e'), KeyModifiers::CONTROL, Action::JumpToMark),
+            (KeyCode::Char('r'), KeyModifiers::ALT, Action::RecordMacro),
+            (KeyCode::Char('y'), KeyModifiers::CONTROL, Action::PlayMacro),
+            // A prefix for pane commands, Ctrl-W in vim, which closes the
+            // buffer here as in most editors.
+            (KeyCode::Char('p'), KeyModifiers::ALT, Action::Window),
+            (KeyCode::Char('w'), KeyModifiers::CONTROL, Action::CloseBuffer),
+            // Tab switching, as in browsers.
+            (KeyCode::PageDown, KeyModifiers::CONTROL, Action::NextBuffer),
+            (KeyCode::PageUp, KeyModifiers::CONTROL, Action::PreviousBuffer),
+            (KeyCode::Char('b'), KeyModifiers::ALT, Action::ListBuffers),
+            (KeyCode::Char('c'), KeyModifiers::CONTROL, Action::Copy),
+            (KeyCode::Char('x'), KeyModifiers::CONTROL, Action::Cut),
+            (KeyCode::Char('v'), KeyModifiers::CONTROL, Action::Paste),
+            (KeyCode::Left, KeyModifiers::CONTROL, Action::WordLeft),
+            (KeyCode::Right, KeyModifiers::CONTROL, Action::WordRight),
+            (
+                KeyCode::Left,
+                KeyModifiers::CONTROL.union(KeyModifiers::SHIFT),
+                Action::SelectWordLeft,
+            ),
+            (
+                KeyCode::Right,
+                KeyModifiers::CONTROL.union(KeyModifiers::SHIFT),
+                Action::SelectWordRight,
+            ),
+            (
+                KeyCode::Up,
+                KeyModifiers::CONTROL.union(KeyModifiers::ALT),
+                Action::SelectBlockUp,
+            ),
+            (
+                KeyCode::Down,
+                KeyModifiers::CONTROL.union(KeyModifiers::ALT),
+                Action::SelectBlockDown,
+            ),
+            (
+                KeyCode::Left,
+                KeyModifiers::CONTROL.union(KeyModifiers::ALT),
+                Action::SelectBlockLeft,
+            ),
+            (
+                KeyCode::Right,
+                KeyModifiers::CONTROL.union(KeyModifiers::ALT),
+                Action::SelectBlockRight,
+            ),
+            (KeyCode::Delete, KeyModifiers::NONE, Action::DeleteForward),
+            (KeyCode::Backspace, KeyModifiers::CONTROL, Action::DeleteWordBack),
+            // Most terminals send Ctrl-Backspace as Ctrl-H.
+            (KeyCode::Char('h'), KeyModifiers::CONTROL, Action::DeleteWordBack),
+            (KeyCode::Delete, KeyModifiers::CONTROL, Action::DeleteWordForward),
+        ];
+        Self {
+            bindings: bindings
+                .into_iter()
+                .map(|(code, modifiers, action)| ((code, modifiers), action))
+                .collect(),
+        }
+    }
+}
+
+impl Keymap {
+    pub fn resolve(&self, key: &KeyEvent) -> Option<Action> {
+        self.bindings
+            .get(&normalize(key.code, key.modifiers))
+            .copied()
+    }
+
+    /// Binds the key named `key` to the action named `action`.
+    pub fn bind(&mut self, key: &str, action: &str) -> Result<(), String> {
+        let key = parse_key(key).ok_or_else(|| format!("unknown key `{}`", key))?;
+        if action == "none" {
+            self.bindings.remove(&key);
+            return Ok(());
+        }
+        let action = Action::parse(action).ok_or_else(|| format!("unknown action `{}`", action))?;
+        self.bindings.insert(key, action);
+        Ok(())
+    }
+
+    /// The name of a key bound to `action`, the shortest if there are
+    /// several, in the form `bind` takes.
+    pub fn key_for(&self, action: Action) -> Option<String> {
+        self.bindings
+            .iter()
+            .filter(|&(_, &bound)| bound == action)
+            .map(|(&(code, modifiers), _)| key_name(code, modifiers))
+            .min_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)))
+    }
+}
+
+/// Parses key names like `ctrl-s`, `alt-shift-left`, `f5` or `x`.
+fn parse_key(name: &str) -> Option<(KeyCode, KeyModifiers)> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name;
+    while let Some((modifier, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+        modifiers |= match modifier {
+            "ctrl" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return None,
+        };
+        rest = key;
+    }
+    let code = match rest {
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "delete" => KeyCode::Delete,
+        "backspace" => KeyCode::Backspace,
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "esc" => KeyCode::Esc,
+        _ => match rest.strip_prefix('f').and_then(|n| n.parse().ok()) {
+            Some(n @ 1..=12) => KeyCode::F(n),
+            _ => {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => KeyCode::Char(ch),
+                    _ => return None,
+                }
+            }
+        },
+    };
+    Some(normalize(code, modifiers))
+}
+
+/// Names a key the way [`parse_key`] reads it.
+fn key_name(code: KeyCode, modifiers: KeyModifiers) -> String {
+    let mut name = String::new();
+    for (modifier, prefix) in [
+        (KeyModifiers::CONTROL, "ctrl-"),
+        (KeyModifiers::ALT, "alt-"),
+        (KeyModifiers::SHIFT, "shift-"),
+    ] {
+        if modifiers.contains(modifier) {
+            name.push_str(prefix);
+        }
+    }
+    match code {
+        KeyCode::Up => name.push_str("up"),
+        KeyCode::Down => name.push_str("down"),
+        KeyCode::Left => name.push_str("left"),
+        KeyCode::Right => name.push_str("right"),
+        KeyCode::Home => name.push_str("home"),
+        KeyCode::End => name.push_str("end"),
+        KeyCode::PageUp => name.push_str("pageup"),
+        KeyCode::PageDown => name.push_str("pagedown"),
+        KeyCode::Delete => name.push_str("delete"),
+        KeyCode::Backspace => name.push_str("backspace"),
+        KeyCode::Enter => name.push_str("enter"),
+        KeyCode::Tab => name.push_str("tab"),
+        KeyCode::Esc => name.push_str("esc"),
+        KeyCode::F(n) => name.push_str(&format!("f{}", n)),
+        // Ctrl-\ is read as Ctrl-4.
+        KeyCode::Char('4') if modifiers.contains(KeyModifiers::CONTROL) => name.push('\\'),
+        KeyCode::Char(ch) => name.push(ch),
+        _ => name.push('?'),
+    }
+    name
+}
+
+/// Folds Shift into the char of char keys, since terminals differ in
+/// whether they report it for capitals. Ctrl-\ is read as Ctrl-4.
+fn normalize(code: KeyCode, modifiers: KeyModifiers) -> (KeyCode, KeyModifiers) {
+    match code {
+        KeyCode::Char('\\') if modifiers.contains(KeyModifiers::CONTROL) => {
+            (KeyCode::Char('4'), modifiers)
+        }
+        KeyCode::Char(ch) if modifiers.contains(KeyModifiers::SHIFT) => (
+            KeyCode::Char(ch.to_ascii_uppercase()),
+            modifiers - KeyModifiers::SHIFT,
+        ),
+        _ => (code, modifiers),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn binds_a_key_to_an_action() {
+        let mut keymap = Keymap::default();
+        keymap.bind("ctrl-x", "quit").unwrap();
+        assert_eq!(
+            keymap.resolve(&key(KeyCode::Char('x'), KeyModifiers::CONTROL)),
+            Some(Action::Quit)
+        );
+        keymap.bind("alt-shift-left", "select_word_left").unwrap();
+        assert_eq!(
+            keymap.resolve(&key(KeyCode::Left, KeyModifiers::ALT | KeyModifiers::SHIFT)),
+            Some(Action::SelectWordLeft)
+        );
+    }
+
+    #[test]
+    fn binds_a_key_from_a_config_line() {
+        let mut config = Config::default();
+        config.set("bind ctrl-x", "quit").unwrap();
+        assert_eq!(
+            config.keymap.resolve(&key(KeyCode::Char('x'), KeyModifiers::CONTROL)),
+            Some(Action::Quit)
+        );
+    }
+
+    #[test]
+    fn unbinds_a_key() {
+        let mut keymap = Keymap::default();
+        keymap.bind("ctrl-s", "none").unwrap();
+        assert_eq!(keymap.resolve(&key(KeyCode::Char('s'), KeyModifiers::CONTROL)), None);
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_actions() {
+        let mut keymap = Keymap::default();
+        assert_eq!(keymap.bind("hyper-x", "quit"), Err("unknown key `hyper-x`".into()));
+        assert_eq!(keymap.bind("ctrl-x", "explode"), Err("unknown action `explode`".into()));
+    }
+
+    #[test]
+    fn folds_shift_into_capitals() {
+        let keymap = Keymap::default();
+        assert_eq!(
+            keymap.resolve(&key(KeyCode::Char('F'), KeyModifiers::ALT | KeyModifiers::SHIFT)),
+            Some(Action::FindInFiles)
+        );
+    }
+
+    #[test]
+    fn names_keys_as_bind_reads_them() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.key_for(Action::Save), Some("ctrl-s".into()));
+        assert_eq!(keymap.key_for(Action::Replace), Some("ctrl-\\".into()));
+        for action in Action::all() {
+            assert_eq!(Action::parse(action.name()), Some(action));
+        }
+    }
+}
@@ -0,0 +1,371 @@
+/// Screen rows a pane keeps at least, so resizing can't squeeze it away.
+pub const MIN_HEIGHT: usize = 3;
+
+/// Screen columns a pane keeps at least.
+pub const MIN_WIDTH: usize = 10;
+
+/// Which way a split lays out its parts.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Direction {
+    /// Stacked top to bottom, with a row naming the buffer between each
+    /// two.
+    Rows,
+    /// Side by side, with a divider column between each two.
+    Columns,
+}
+
+impl Direction {
+    /// The least room a pane takes this way.
+    fn min(self) -> usize {
+        match self {
+            Direction::Rows => MIN_HEIGHT,
+            Direction::Columns => MIN_WIDTH,
+        }
+    }
+}
+
+/// A part of the screen, in cells.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Rect {
+    pub left: usize,
+    pub top: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    /// The row just below the rectangle.
+    pub fn bottom(&self) -> usize {
+        self.top + self.height
+    }
+
+    /// The column just right of the rectangle.
+    pub fn right(&self) -> usize {
+        self.left + self.width
+    }
+
+    pub fn contains(&self, column: usize, row: usize) -> bool {
+        (self.left..self.right()).contains(&column) && (self.top..self.bottom()).contains(&row)
+    }
+
+    fn size(&self, direction: Direction) -> usize {
+        match direction {
+            Direction::Rows => self.height,
+            Direction::Columns => self.width,
+        }
+    }
+
+    /// The `size` cells from `start` on `direction`'s way.
+    fn part(&self, direction: Direction, start: usize, size: usize) -> Rect {
+        match direction {
+            Direction::Rows => Rect {
+                top: self.top + start,
+                height: size,
+                ..*self
+            },
+            Direction::Columns => Rect {
+                left: self.left + start,
+                width: size,
+                ..*self
+            },
+        }
+    }
+}
+
+/// How the text area is split into panes, which are numbered top to
+/// bottom and left to right as they appear in the tree.
+pub enum Layout {
+    Pane,
+    /// Parts laid out `direction`'s way, each with its size that way.
+    Split {
+        direction: Direction,
+        parts: Vec<(Layout, usize)>,
+    },
+}
+
+impl Layout {
+    pub fn panes(&self) -> usize {
+        match self {
+            Layout::Pane => 1,
+            Layout::Split { parts, .. } => parts.iter().map(|(part, _)| part.panes()).sum(),
+        }
+    }
+
+    /// The least room the layout takes `direction`'s way, dividers
+    /// included.
+    fn min_size(&self, direction: Direction) -> usize {
+        match self {
+            Layout::Pane => direction.min(),
+            Layout::Split {
+                direction: own,
+                parts,
+            } => {
+                let sizes = parts.iter().map(|(part, _)| part.min_size(direction));
+                if *own == direction {
+                    sizes.sum::<usize>() + parts.len() - 1
+                } else {
+                    sizes.max().unwrap_or(0)
+                }
+            }
+        }
+    }
+
+    /// Makes the parts' sizes add up to `area`, growing the last part or
+    /// shrinking the last ones down to their least first.
+    pub fn fit(&mut self, area: Rect) {
+        let Layout::Split { direction, parts } = self else {
+            return;
+        };
+        let direction = *direction;
+        let total = area.size(direction).saturating_sub(parts.len() - 1);
+        let mut sum: usize = parts.iter().map(|(_, size)| size).sum();
+        if sum < total {
+            parts.last_mut().unwrap().1 += total - sum;
+            sum = total;
+        }
+        for squeeze in [true, false] {
+            for (part, size) in parts.iter_mut().rev() {
+                let least = if squeeze { part.min_size(direction) } else { 0 };
+                let change = size.saturating_sub(least).min(sum - total);
+                *size -= change;
+                sum -= change;
+            }
+        }
+        let mut start = 0;
+        for (part, size) in parts {
+            part.fit(area.part(direction, start, *size));
+            start += *size + 1;
+        }
+    }
+
+    /// Where each pane is in `area`, in order.
+    pub fn rects(&self, area: Rect) -> Vec<Rect> {
+        let Layout::Split { direction, parts } = self else {
+            return vec![area];
+        };
+        let mut start = 0;
+        parts
+            .iter()
+            .flat_map(|(part, size)| {
+                let rects = part.rects(area.part(*direction, start, *size));
+                start += size + 1;
+                rects
+            })
+            .collect()
+    }
+
+    /// The columns between panes side by side in `area`, each as tall as
+    /// the split it divides.
+    pub fn dividers(&self, area: Rect) -> Vec<Rect> {
+        let Layout::Split { direction, parts } = self else {
+            return Vec::new();
+        };
+        let mut dividers = Vec::new();
+        let mut start = 0;
+        for (at, (part, size)) in parts.iter().enumerate() {
+            dividers.extend(part.dividers(area.part(*direction, start, *size)));
+            start += size;
+            if *direction == Direction::Columns && at + 1 < parts.len() {
+                dividers.push(area.part(Direction::Columns, start, 1));
+            }
+            start += 1;
+        }
+        dividers
+    }
+
+    /// Splits pane `pane`, `size` cells `direction`'s way, in two. The
+    /// new pane comes after it, and the first keeps the odd cell. Returns
+    /// `false` if either would be too small.
+    pub fn split(&mut self, pane: usize, direction: Direction, size: usize) -> bool {
+        let second = size.saturating_sub(1) / 2;
+        let first = size.saturating_sub(1) - second;
+        if second < direction.min() {
+            return false;
+        }
+        match self {
+            Layout::Pane => {
+                *self = Layout::Split {
+                    direction,
+                    parts: vec![(Layout::Pane, first), (Layout::Pane, second)],
+                };
+                true
+            }
+            Layout::Split {
+                direction: own,
+                parts,
+            } => {
+                let (at, inner) = part_of(parts, pane);
+                if *own == direction && matches!(parts[at].0, Layout::Pane) {
+                    parts[at].1 = first;
+                    parts.insert(at + 1, (Layout::Pane, second));
+                    true
+                } else {
+                    parts[at].0.split(inner, direction, size)
+                }
+            }
+        }
+    }
+
+    /// Removes pane `pane`, giving its room to the part after it, or the
+    /// one before for the last part.
+    pub fn remove(&mut self, pane: usize) {
+        let Layout::Split { parts, .. } = self else {
+            return;
+        };
+        let (at, inner) = part_of(parts, pane);
+        if matches!(parts[at].0, Layout::Pane) {
+            let (_, size) = parts.remove(at);
+            let neighbour = at.min(parts.len() - 1);
+            parts[neighbour].1 += size + 1;
+        } else {
+            parts[at].0.remove(inner);
+        }
+        if parts.len() == 1 {
+            let (only, _) = parts.remove(0);
+            *self = only;
+        }
+    }
+
+    /// Grows pane `pane` by `delta` cells `direction`'s way, or shrinks it
+    /// for a negative `delta`, trading them with the part after it in the
+    /// innermost split that way, or the one before for the last part.
+    /// Neither goes below its least size. Returns `false` if no split is
+    /// that way.
+    pub fn resize(&mut self, pane: usize, direction: Direction, delta: isize) -> bool {
+        let Layout::Split {
+            direction: own,
+            parts,
+        } = self
+        else {
+            return false;
+        };
+        let (at, inner) = part_of(parts, pane);
+        if parts[at].0.resize(inner, direction, delta) {
+            return true;
+        }
+        if *own != direction {
+            return false;
+        }
+        let other = if at + 1 < parts.len() { at + 1 } else { at - 1 };
+        let (grow, shrink) = if delta > 0 { (at, other) } else { (other, at) };
+        let room = parts[shrink].1.saturating_sub(parts[shrink].0.min_size(direction));
+        let change = delta.unsigned_abs().min(room);
+        parts[shrink].1 -= change;
+        parts[grow].1 += change;
+        true
+    }
+
+    /// Shares `area` out evenly between the parts of every split.
+    pub fn equalize(&mut self, area: Rect) {
+        let Layout::Split { direction, parts } = self else {
+            return;
+        };
+        let available = area.size(*direction).saturating_sub(parts.len() - 1);
+        let (size, extra) = (available / parts.len(), available % parts.len());
+        let mut start = 0;
+        for (at, (part, part_size)) in parts.iter_mut().enumerate() {
+            *part_size = size + (at < extra) as usize;
+            part.equalize(area.part(*direction, start, *part_size));
+            start += *part_size + 1;
+        }
+    }
+}
+
+/// The part of `parts` holding pane `pane`, and the pane's number within
+/// it.
+fn part_of(parts: &[(Layout, usize)], mut pane: usize) -> (usize, usize) {
+    for (at, (part, _)) in parts.iter().enumerate() {
+        let panes = part.panes();
+        if pane < panes {
+            return (at, pane);
+        }
+        pane -= panes;
+    }
+    panic!("no pane {} in the layout", pane)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA: Rect = Rect {
+        left: 0,
+        top: 0,
+        width: 80,
+        height: 23,
+    };
+
+    fn rect(left: usize, top: usize, width: usize, height: usize) -> Rect {
+        Rect {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn stacks_panes_with_a_row_between() {
+        let mut layout = Layout::Pane;
+        assert!(layout.split(0, Direction::Rows, 23));
+        assert_eq!(layout.panes(), 2);
+        assert_eq!(layout.rects(AREA), [rect(0, 0, 80, 11), rect(0, 12, 80, 11)]);
+        assert_eq!(layout.dividers(AREA), []);
+    }
+
+    #[test]
+    fn splits_a_pane_side_by_side_with_a_divider() {
+        let mut layout = Layout::Pane;
+        assert!(layout.split(0, Direction::Rows, 23));
+        assert!(layout.split(1, Direction::Columns, 80));
+        assert_eq!(
+            layout.rects(AREA),
+            [rect(0, 0, 80, 11), rect(0, 12, 40, 11), rect(41, 12, 39, 11)]
+        );
+        assert_eq!(layout.dividers(AREA), [rect(40, 12, 1, 11)]);
+    }
+
+    #[test]
+    fn refuses_to_split_a_pane_too_small() {
+        let mut layout = Layout::Pane;
+        assert!(!layout.split(0, Direction::Rows, 2 * MIN_HEIGHT));
+        assert!(!layout.split(0, Direction::Columns, 2 * MIN_WIDTH));
+        assert_eq!(layout.panes(), 1);
+    }
+
+    #[test]
+    fn gives_a_removed_pane_room_to_its_neighbour() {
+        let mut layout = Layout::Pane;
+        layout.split(0, Direction::Rows, 23);
+        layout.split(1, Direction::Rows, 11);
+        assert_eq!(layout.panes(), 3);
+        layout.remove(1);
+        assert_eq!(layout.rects(AREA), [rect(0, 0, 80, 11), rect(0, 12, 80, 11)]);
+        layout.remove(1);
+        assert!(matches!(layout, Layout::Pane));
+    }
+
+    #[test]
+    fn resizes_down_to_the_least_height() {
+        let mut layout = Layout::Pane;
+        layout.split(0, Direction::Rows, 23);
+        assert!(layout.resize(0, Direction::Rows, 3));
+        assert_eq!(layout.rects(AREA), [rect(0, 0, 80, 14), rect(0, 15, 80, 8)]);
+        assert!(layout.resize(1, Direction::Rows, -100));
+        assert_eq!(layout.rects(AREA)[1], rect(0, 20, 80, MIN_HEIGHT));
+        assert!(!layout.resize(0, Direction::Columns, 1));
+    }
+
+    #[test]
+    fn fits_and_equalizes_to_the_area() {
+        let mut layout = Layout::Pane;
+        layout.split(0, Direction::Rows, 23);
+        layout.split(1, Direction::Rows, 11);
+        layout.fit(rect(0, 0, 80, 12));
+        let heights: Vec<_> = layout.rects(AREA).iter().map(|rect| rect.height).collect();
+        assert_eq!(heights, [4, 3, 3]);
+        layout.equalize(AREA);
+        let heights: Vec<_> = layout.rects(AREA).iter().map(|rect| rect.height).collect();
+        assert_eq!(heights, [7, 7, 7]);
+    }
+}
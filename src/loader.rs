@@ -0,0 +1,147 @@
+use crate::Row;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Files at least this large are read in the background after the first
+/// screenful has been shown.
+pub const LAZY_THRESHOLD: u64 = 32 * 1024 * 1024;
+
+/// Rows read synchronously before handing the rest of a file to a [`Loader`].
+pub const FIRST_ROWS: usize = 1000;
+
+/// Rows the loader thread sends per message.
+const BATCH_SIZE: usize = 4096;
+
+/// Batches taken from the channel per call to [`Loader::receive`], so that
+/// a fast loader can't stall the UI.
+const BATCHES_PER_RECEIVE: usize = 64;
+
+/// Reads one line of `reader` into a row, stripping the line terminator.
+/// Returns the row and whether it was terminated by a newline, or `None` at
+/// the end of the file.
+pub fn read_row(
+    reader: &mut impl BufRead,
+    line: &mut Vec<u8>,
+    tab_stop: usize,
+) -> io::Result<Option<(Row, bool)>> {
+    line.clear();
+    if reader.read_until(b'\n', line)? == 0 {
+        return Ok(None);
+    }
+    let newline = line.last() == Some(&b'\n');
+    if newline {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    }
+    let row = Row::new(String::from_utf8_lossy(line).into_owned(), tab_stop);
+    Ok(Some((row, newline)))
+}
+
+enum Message {
+    Rows(Vec<Row>, u64),
+    Finished(bool),
+    Failed(io::Error),
+}
+
+/// Reads the remainder of a file on a background thread.
+///
+/// Dropping the loader cancels the thread and waits for it to exit.
+pub struct Loader {
+    receiver: Receiver<Message>,
+    cancel: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+    loaded: u64,
+    total: u64,
+}
+
+impl Loader {
+    /// Continues reading `reader`, of which `loaded` out of `total` bytes
+    /// have already been consumed. `final_newline` is whether the last row
+    /// read so far ended with a newline.
+    pub fn spawn(
+        mut reader: BufReader<File>,
+        loaded: u64,
+        total: u64,
+        mut final_newline: bool,
+        tab_stop: usize,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancelled = Arc::clone(&cancel);
+        let handle = thread::spawn(move || {
+            let mut line = Vec::new();
+            while !cancelled.load(Ordering::Relaxed) {
+                let mut rows = Vec::with_capacity(BATCH_SIZE);
+                let mut bytes = 0;
+                while rows.len() < BATCH_SIZE {
+                    match read_row(&mut reader, &mut line, tab_stop) {
+                        Ok(Some((row, newline))) => {
+                            bytes += line.len() as u64 + newline as u64;
+                            final_newline = newline;
+                            rows.push(row);
+                        }
+                        Ok(None) => break,
+                        Err(err) => {
+                            let _ = sender.send(Message::Failed(err));
+                            return;
+                        }
+                    }
+                }
+                let finished = rows.len() < BATCH_SIZE;
+                if sender.send(Message::Rows(rows, bytes)).is_err() {
+                    return;
+                }
+                if finished {
+                    let _ = sender.send(Message::Finished(final_newline));
+                    return;
+                }
+            }
+        });
+        Self {
+            receiver,
+            cancel,
+            handle: Some(handle),
+            loaded,
+            total,
+        }
+    }
+
+    /// Hands every row read so far to `push`. Returns whether the file ended
+    /// with a newline once the whole file has been read.
+    pub fn receive(&mut self, mut push: impl FnMut(Row)) -> io::Result<Option<bool>> {
+        for _ in 0..BATCHES_PER_RECEIVE {
+            match self.receiver.try_recv() {
+                Ok(Message::Rows(rows, bytes)) => {
+                    self.loaded += bytes;
+                    rows.into_iter().for_each(&mut push);
+                }
+                Ok(Message::Finished(final_newline)) => return Ok(Some(final_newline)),
+                Ok(Message::Failed(err)) => return Err(err),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    return Err(io::Error::other("loader thread exited early"))
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn percent(&self) -> u64 {
+        (self.loaded * 100).checked_div(self.total).unwrap_or(100)
+    }
+}
+
+impl Drop for Loader {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -1,33 +1,420 @@
+mod align;
+mod block;
+mod case;
+mod comment;
+mod command;
+mod config;
+mod diff;
+mod fold;
+mod fuzzy;
+mod git;
+mod grep;
+mod hex;
+mod history;
+mod ignore;
+mod indent;
+mod jump_list;
+mod keymap;
+mod layout;
+mod loader;
+mod marks;
+mod motion;
+mod osc52;
+mod pairs;
+mod search;
+mod sort;
+mod surround;
+mod text_buffer;
+mod text_object;
+mod undo;
+mod walk;
+mod word_motion;
+mod wrap;
+
+use block::{Block, Step};
+use case::Case;
+use command::Command;
+use config::Config;
+use diff::{Hunk, LineChange};
+use fold::Folds;
+use git::HeadJob;
+use grep::{GrepJob, GrepMatch};
+use hex::HexView;
+use history::{Histories, History};
+use indent::Indent;
+use jump_list::{Jump, JumpList};
+use keymap::Action;
+use layout::{Direction, Layout, Rect};
+
 use crossterm::event::*;
 use crossterm::terminal::ClearType;
+use crossterm::style::{Attribute, Color, ResetColor, SetBackgroundColor, SetForegroundColor};
 use crossterm::{cursor, event, execute, queue, terminal};
-use std::io::{stdout, Write, self};
-use std::time::Duration;
+use loader::Loader;
+use marks::Marks;
+use motion::{CharSearch, Motion, MotionKind};
+use search::{Query, SearchDirection, SearchEvent, SearchJob};
+use sort::SortOptions;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{stdout, Write, self, BufRead, BufReader, BufWriter};
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use std::{cmp, env, fs};
+use text_buffer::{Snapshot, TextBuffer};
+use text_object::TextObject;
+use undo::{Change, Group, UndoStack};
+use walk::FileWalk;
+use word_motion::{is_word_char, Lines, WordMotion};
+
+/// Upper bound on keys queued for macro replay, which stops a macro that
+/// replays itself from running forever.
+const MAX_REPLAY_KEYS: usize = 1_000_000;
 
 struct CleanUp;
 
 impl Drop for CleanUp {
     fn drop(&mut self) {
         terminal::disable_raw_mode().expect("Could not disable raw mode");
+        execute!(stdout(), event::DisableMouseCapture).expect("Could not release the mouse");
+        execute!(stdout(), cursor::SetCursorStyle::DefaultUserShape)
+            .expect("Could not reset the cursor");
         Output::clear_screen().expect("Error");
     }
 }
 
+struct StatusMessage {
+    message: Option<String>,
+    set_time: Option<Instant>,
+}
+
+impl StatusMessage {
+    fn new(initial_message: String) -> Self {
+        Self {
+            message: Some(initial_message),
+            set_time: Some(Instant::now()),
+        }
+    }
+
+    fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+        self.set_time = Some(Instant::now())
+    }
+
+    fn message(&mut self) -> Option<&String> {
+        self.set_time.and_then(|time| {
+            if time.elapsed() > Duration::from_secs(5) {
+                self.message = None;
+                self.set_time = None;
+                None
+            } else {
+                Some(self.message.as_ref().unwrap())
+            }
+        })
+    }
+}
+
+/// What keys typed in the buffer do.
+#[derive(Copy, Clone, PartialEq)]
+enum Mode {
+    /// Keys are commands.
+    Normal,
+    /// Printable keys are inserted into the buffer.
+    Insert,
+    /// Printable keys overwrite the text under the cursor.
+    Replace,
+    /// Like `Normal`, with a selection from `Output::selection_anchor` to
+    /// the cursor.
+    Visual,
+    /// The `:` prompt is open.
+    Command,
+    /// An operator was typed and waits for a motion.
+    OperatorPending(Op),
+}
+
+/// An operator acting on the text a motion moves over.
+#[derive(Copy, Clone, PartialEq)]
+enum Op {
+    Delete,
+    Yank,
+    Change,
+    /// `>`, adding a level of indentation to each row.
+    Indent,
+    /// `<`, taking a level of indentation off each row.
+    Outdent,
+    /// `ys`, putting the delimiters the char stands for around the text.
+    /// `None` until the char is typed, after the motion.
+    Surround(Option<char>),
+}
+
+/// Text yanked or deleted from the buffer.
+#[derive(Default)]
+struct Register {
+    /// Rows separated by newlines.
+    text: String,
+    kind: RegisterKind,
+}
+
+/// How a register's text goes back into the buffer.
+#[derive(Copy, Clone, PartialEq, Default)]
+enum RegisterKind {
+    /// At the cursor.
+    #[default]
+    Chars,
+    /// As whole rows, below the cursor's row.
+    Lines,
+    /// As a rectangle, each row at the cursor's column on successive rows.
+    Block,
+}
+
+/// What an operator acts on.
+#[derive(Copy, Clone)]
+enum OperatorTarget {
+    /// Whole rows, as with `dd`.
+    Rows,
+    Motion(Motion),
+    /// A text object, the inner one with `true`.
+    Object(TextObject, bool),
+}
+
+/// Stands for Backspace in the text typed in Insert mode.
+const BACKSPACE: char = '\u{8}';
+
+/// What typing a char does with `auto_close`.
+#[derive(Copy, Clone)]
+enum Typed {
+    Insert,
+    /// Moves over the same char after the cursor, which an earlier
+    /// [`Typed::Pair`] added.
+    Over,
+    /// Inserts the char, and the one closing it after the cursor.
+    Pair(char),
+}
+
+/// An edit `.` can repeat, described by what it did rather than by the keys
+/// that did it, so it can be applied anywhere.
+#[derive(Clone)]
+enum Edit {
+    /// Entering Insert mode with `entry` (`i`, `a`, `o`, ...) and typing
+    /// `text`.
+    Insert { entry: char, text: String },
+    /// An operator, with the text typed after a change.
+    Operator {
+        op: Op,
+        target: OperatorTarget,
+        count: Option<usize>,
+        text: String,
+    },
+    /// An edit bound in the keymap, such as a paste, run `count` times.
+    Action(Action, usize),
+    /// `r`, replacing `count` chars with the char.
+    Replace(char, usize),
+    /// `~` on `count` chars.
+    ToggleCase(usize),
+    /// `gJ` on `count` rows.
+    JoinWithoutSpaces(usize),
+    /// `cs` from the first delimiter to the second, or `ds` without one.
+    ChangeSurround(char, Option<char>),
+    /// `p` (after the cursor) or `P` (before it), `count` times.
+    Put(bool, usize),
+}
+
+impl Mode {
+    fn name(self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Replace => "REPLACE",
+            Mode::Visual => "VISUAL",
+            Mode::Command => "COMMAND",
+            Mode::OperatorPending(_) => "OPERATOR",
+        }
+    }
+
+    /// A bar where typed text goes in, a block over the char acted on
+    /// otherwise.
+    fn cursor_style(self) -> cursor::SetCursorStyle {
+        match self {
+            Mode::Insert | Mode::Command => cursor::SetCursorStyle::SteadyBar,
+            Mode::Replace => cursor::SetCursorStyle::SteadyUnderScore,
+            _ => cursor::SetCursorStyle::SteadyBlock,
+        }
+    }
+}
+
+/// How often the buffer's file is looked at for changes by other programs
+/// while waiting for a key.
+const FILE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long after the last key a diff left out of date by edits is worked
+/// out again, so typing doesn't redo it at every key.
+const DIFF_DELAY: Duration = Duration::from_millis(300);
+
+/// Rows a turn of the mouse wheel scrolls.
+const WHEEL_LINES: usize = 3;
+
+/// Where on the screen to scroll the cursor's row to.
+#[derive(Copy, Clone)]
+enum ScreenPlace {
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// Rows `{` or `}` must move for the jump list to remember where from.
+const PARAGRAPH_JUMP_ROWS: usize = 5;
+
+/// Choices listed above the status bar while a prompt narrows them down,
+/// as in the command palette.
+struct Menu {
+    /// Each choice, with a note shown at the right end of its row.
+    items: Vec<(String, String)>,
+    /// Index in `items` of the highlighted choice.
+    selected: usize,
+    /// Chars of each choice's label drawn as a search match, if any.
+    marked: Vec<Range<usize>>,
+}
+
+/// Most choices a menu lists at once.
+const MENU_ROWS: usize = 10;
+
+/// A pane of the text area, showing a buffer.
+struct View {
+    /// `EditorRows::id` of the buffer shown.
+    buffer: usize,
+    cursor_controller: CursorController,
+}
+
 struct Output {
     win_size: (usize, usize),
     editor_contents: EditorContents,
     cursor_controller: CursorController,
+    editor_rows: EditorRows,
+    status_message: StatusMessage,
+    config: Config,
+    /// Progress of the running or last search, shown in the status bar.
+    search_status: Option<String>,
+    /// Query whose matches are highlighted on screen.
+    search_query: Option<Query>,
+    /// Position of the match the last search moved to.
+    search_match: Option<(usize, usize)>,
+    /// Count typed before a command, e.g. the 5 of `5@a`. Shown in the
+    /// status bar until the command is entered.
+    pending_count: Option<usize>,
+    mode: Mode,
+    /// Where the selection started, as `(row, byte column)`; the cursor is
+    /// the other end.
+    selection_anchor: Option<(usize, usize)>,
+    /// The unnamed register, filled by copying, cutting and operators.
+    clipboard: Register,
+    /// Keys typed so far of a command that takes several, shown in the
+    /// status bar.
+    pending_keys: String,
+    /// Choices listed for the prompt being typed at, if it offers any.
+    menu: Option<Menu>,
+    /// The panes the text area is split into, in `layout` order. The
+    /// focused pane's cursor and buffer are `cursor_controller` and
+    /// `editor_rows`; its copies here are stale.
+    views: Vec<View>,
+    /// Where on screen each of `views` goes.
+    layout: Layout,
+    /// Index into `views` of the pane keys go to.
+    focus: usize,
+    /// The open buffers other than the focused pane's.
+    buffers: Vec<EditorRows>,
+    /// Every open buffer's id, in the order the tab line lists them.
+    buffer_order: Vec<usize>,
+    /// Where the cursor was in buffers the focused pane switched away from,
+    /// for when they are shown again.
+    saved_cursors: HashMap<usize, CursorController>,
+    /// Cursors added with Ctrl-D or Alt+Click in the focused pane. Typing
+    /// and some edits happen at all of them.
+    extra_cursors: Vec<ExtraCursor>,
+    /// The rectangle selected with Ctrl+Alt+arrows or Alt+drag in the
+    /// focused pane.
+    block: Option<Block>,
+    /// Where an Alt+drag started, as `(row, render column)`.
+    drag_start: Option<(usize, usize)>,
+    /// Closing chars `auto_close` added after the cursor in this insert,
+    /// which typing them moves over.
+    auto_closed: usize,
+    /// Set while an edit is made at each of several cursors or keys are
+    /// replayed, whose changes are undone together as one step.
+    undo_grouped: bool,
 }
 
 impl Output {
     fn new() -> Self {
         let win_size = terminal::size()
-            .map(|(x, y)| (x as usize, y as usize))
+            .map(|(x, y)| (x as usize, y as usize - 2))
             .unwrap();
-        Self { 
+        let (mut config, config_errors) = Config::load();
+        let mut files: Vec<String> = env::args().skip(1).collect();
+        let compare = files.iter().position(|arg| arg == "--diff").map(|at| files.remove(at));
+        if let Some(at) = files.iter().position(|arg| arg == "--vi") {
+            files.remove(at);
+            config.modal = true;
+        }
+        let (opened, open_errors) = EditorRows::from_files(files, &config);
+        // Files after the first open in the background.
+        let mut opened = opened.into_iter();
+        let editor_rows = opened.next().unwrap_or_else(|| EditorRows::empty(None, &config));
+        let mut output = Self::with_buffers(win_size, config, editor_rows, opened.collect());
+        if let Some(error) = config_errors.into_iter().chain(open_errors).next() {
+            output.status_message.set_message(error);
+        }
+        if compare.is_some() {
+            output.compare_files();
+        }
+        output
+    }
+
+    /// Shows `editor_rows` on a screen `win_size` big, with `buffers` open
+    /// behind it.
+    fn with_buffers(
+        win_size: (usize, usize),
+        config: Config,
+        editor_rows: EditorRows,
+        buffers: Vec<EditorRows>,
+    ) -> Self {
+        let buffer_order = std::iter::once(editor_rows.id)
+            .chain(buffers.iter().map(|editor_rows| editor_rows.id))
+            .collect();
+        let initial_message =
+            "HELP: Ctrl-S = Save | Ctrl-Q = Quit | Ctrl-G = Goto line | Ctrl-P = Commands".into();
+        let view = View {
+            buffer: editor_rows.id,
+            cursor_controller: CursorController::new(win_size),
+        };
+        let mode = if config.modal { Mode::Normal } else { Mode::Insert };
+        Self {
             win_size,
             editor_contents: EditorContents::new(),
-            cursor_controller: CursorController::new(),
+            cursor_controller: CursorController::new(win_size),
+            editor_rows,
+            status_message: StatusMessage::new(initial_message),
+            config,
+            search_status: None,
+            search_query: None,
+            search_match: None,
+            pending_count: None,
+            mode,
+            selection_anchor: None,
+            clipboard: Register::default(),
+            pending_keys: String::new(),
+            menu: None,
+            views: vec![view],
+            layout: Layout::Pane,
+            focus: 0,
+            buffers,
+            buffer_order,
+            saved_cursors: HashMap::new(),
+            extra_cursors: Vec::new(),
+            block: None,
+            drag_start: None,
+            auto_closed: 0,
+            undo_grouped: false,
         }
     }
 
@@ -36,11 +423,169 @@ impl Output {
         execute!(stdout(), cursor::MoveTo(0, 0))
     }
 
-    fn draw_rows(&mut self) {
-        let screen_rows = self.win_size.1;
-        let screen_columns = self.win_size.0;
+    /// Draws the focused pane's rows in `rect`. The selection is only
+    /// drawn when the pane really has focus, rather than being swapped in
+    /// to be drawn.
+    fn draw_rows(&mut self, rect: Rect, focused: bool) {
+        let (screen_columns, screen_rows) = (rect.width, rect.height);
+        let gutter_width = self.gutter_width();
+        let lines = self.display_lines(screen_rows);
+        let exempt = self.editor_rows.whitespace_exempt(&self.config);
         for i in 0..screen_rows {
-            if i == screen_rows / 3 {
+            queue!(self.editor_contents, cursor::MoveTo(rect.left as u16, (rect.top + i) as u16))
+                .unwrap();
+            if let Some((file_row, columns)) = lines.get(i).cloned() {
+                // The row being typed on isn't flagged mid-thought.
+                let typing = focused
+                    && file_row == self.cursor_controller.cursor_y
+                    && matches!(self.mode, Mode::Insert | Mode::Replace);
+                let flag_whitespace = !exempt && !typing;
+                let mixed_indent = flag_whitespace
+                    && self.config.highlight_mixed_indent
+                    && self.editor_rows.get_editor_row(file_row).mixed_indent;
+                // Only the first screen row of a wrapped row shows its gutter,
+                // the rest the wrap marker.
+                let continued = self.cursor_controller.wrap && columns.start > 0;
+                if continued && gutter_width > 0 {
+                    let marker = format!("{:>1$} ", self.config.wrap_marker, gutter_width - 1);
+                    self.editor_contents.push_str(&marker);
+                } else if gutter_width > 0 {
+                    self.draw_gutter(file_row, gutter_width, mixed_indent);
+                }
+                let row = self.editor_rows.get_editor_row(file_row);
+                let mut highlight = row.highlight.clone();
+                // Without a gutter to flag it in, the indentation itself is.
+                if mixed_indent && gutter_width == 0 {
+                    let content = row.row_content.trim_start_matches([' ', '\t']);
+                    let indent = row.get_render_x(row.row_content.len() - content.len(), self.editor_rows.tab_stop);
+                    highlight[..indent].fill(HighlightType::MixedIndent);
+                }
+                let render = if self.config.show_whitespace {
+                    let tab_stop = self.editor_rows.tab_stop;
+                    row.visible_render(&mut highlight, tab_stop, self.config.show_all_spaces)
+                } else {
+                    row.render.clone()
+                };
+                if let Some(query) = self.search_query.as_ref().filter(|query| !query.text.is_empty()) {
+                    let tab_stop = self.editor_rows.tab_stop;
+                    for col in query.matches(&row.row_content) {
+                        let highlight_type = if self.search_match == Some((file_row, col)) {
+                            HighlightType::CurrentMatch
+                        } else {
+                            HighlightType::Match
+                        };
+                        let start = row.get_render_x(col, tab_stop);
+                        let end = row.get_render_x(col + query.text.len(), tab_stop);
+                        highlight[start..end].fill(highlight_type);
+                    }
+                }
+                let extra_cursors: &[ExtraCursor] = if focused { &self.extra_cursors } else { &[] };
+                let selections = self
+                    .selection()
+                    .filter(|_| focused)
+                    .into_iter()
+                    .chain(extra_cursors.iter().filter_map(|cursor| {
+                        self.selection_between(cursor.anchor?, self.clamp_pos(cursor.pos))
+                    }));
+                for (start, end) in selections {
+                    if (start.0..=end.0).contains(&file_row) {
+                        let tab_stop = self.editor_rows.tab_stop;
+                        let from = if file_row == start.0 { start.1 } else { 0 };
+                        let to = if file_row == end.0 { end.1 } else { row.row_content.len() };
+                        let from = row.get_render_x(from, tab_stop);
+                        let to = row.get_render_x(to, tab_stop);
+                        highlight[from..to].fill(HighlightType::Selection);
+                    }
+                }
+                let block = self.block.filter(|block| focused && block.rows().contains(&file_row));
+                if let Some(block) = block {
+                    let columns = block.columns();
+                    // The block reaches past the end of short rows.
+                    let width = columns.end.max(columns.start + 1);
+                    if highlight.len() < width {
+                        highlight.resize(width, HighlightType::Normal);
+                    }
+                    if columns.is_empty() {
+                        highlight[columns.start] = HighlightType::Cursor;
+                    } else {
+                        highlight[columns].fill(HighlightType::Selection);
+                    }
+                }
+                let render_len = render.chars().count();
+                for cursor in extra_cursors {
+                    let (cursor_row, col) = self.clamp_pos(cursor.pos);
+                    if cursor_row == file_row {
+                        let render_x = row.get_render_x(col, self.editor_rows.tab_stop);
+                        // A cursor past the end of the row gets a cell of its own.
+                        if render_x == highlight.len() {
+                            highlight.push(HighlightType::Cursor);
+                        } else {
+                            highlight[render_x] = HighlightType::Cursor;
+                        }
+                    }
+                }
+                // The end of row marker takes the cell after the text.
+                let eol_marker = self.config.eol_marker;
+                if eol_marker.is_some() && highlight.len() == render_len {
+                    highlight.push(HighlightType::Whitespace);
+                }
+                let past_end = highlight.len().saturating_sub(render_len);
+                let mut current_style = HighlightType::Normal;
+                let mut drawn = 0;
+                render
+                    .chars()
+                    .chain(eol_marker)
+                    .chain(std::iter::repeat_n(' ', past_end - eol_marker.is_some() as usize))
+                    .zip(highlight)
+                    .skip(columns.start)
+                    .take(columns.len())
+                    .for_each(|(ch, highlight_type)| {
+                        let style = match highlight_type {
+                            HighlightType::TrailingWhitespace
+                                if !self.config.highlight_trailing_whitespace || !flag_whitespace =>
+                            {
+                                HighlightType::Normal
+                            }
+                            _ => highlight_type,
+                        };
+                        if style != current_style {
+                            current_style = style;
+                            queue!(self.editor_contents, ResetColor).unwrap();
+                            self.editor_contents
+                                .push_str(&Attribute::NoReverse.to_string());
+                            match style {
+                                HighlightType::Normal => {}
+                                HighlightType::Cursor => self
+                                    .editor_contents
+                                    .push_str(&Attribute::Reverse.to_string()),
+                                HighlightType::Control | HighlightType::Whitespace => {
+                                    queue!(self.editor_contents, SetForegroundColor(style.to_color()))
+                                        .unwrap()
+                                }
+                                _ => queue!(self.editor_contents, SetBackgroundColor(style.to_color()))
+                                    .unwrap(),
+                            }
+                        }
+                        self.editor_contents.push(ch);
+                        drawn += 1;
+                    });
+                queue!(self.editor_contents, ResetColor).unwrap();
+                self.editor_contents
+                    .push_str(&Attribute::NoReverse.to_string());
+                // A folded row ends with how many rows are folded under it.
+                let last_line = lines.get(i + 1).is_none_or(|&(next, _)| next != file_row);
+                if let Some(folded) = self.editor_rows.folds.under(file_row).filter(|_| last_line) {
+                    let room = columns.len().saturating_sub(drawn);
+                    let marker: String = format!(" ▸ {} lines", folded).chars().take(room).collect();
+                    queue!(
+                        self.editor_contents,
+                        SetForegroundColor(HighlightType::Whitespace.to_color())
+                    )
+                    .unwrap();
+                    self.editor_contents.push_str(&marker);
+                    queue!(self.editor_contents, ResetColor).unwrap();
+                }
+            } else if self.editor_rows.is_new() && i == screen_rows / 3 {
                 let mut welcome = format!("Pound Editor --- Version {}", "0.0.1");
                 if welcome.len() > screen_columns {
                     welcome.truncate(screen_columns)
@@ -57,14 +602,328 @@ impl Output {
             } else {
                 self.editor_contents.push('~');
             }
+        }
+    }
+
+    /// What the focused pane shows on each of `screen_rows` screen rows: a
+    /// buffer row and the render columns of it. Long rows take several
+    /// screen rows with `wrap`, and folded rows none. Stops early at the end
+    /// of the buffer.
+    fn display_lines(&self, screen_rows: usize) -> Vec<(usize, Range<usize>)> {
+        let cursor_controller = &self.cursor_controller;
+        let columns = cursor_controller.screen_columns;
+        let mut lines = Vec::with_capacity(screen_rows);
+        for file_row in cursor_controller.row_offset..self.editor_rows.number_of_rows() {
+            if lines.len() >= screen_rows {
+                break;
+            }
+            if self.editor_rows.folds.hidden(file_row) {
+                continue;
+            }
+            if !cursor_controller.wrap {
+                let start = cursor_controller.column_offset;
+                lines.push((file_row, start..start + columns));
+                continue;
+            }
+            let breaks = cursor_controller.wrap_breaks(&self.editor_rows, file_row);
+            for (line, &start) in breaks.iter().enumerate() {
+                let end = breaks.get(line + 1).copied().unwrap_or(start + columns);
+                lines.push((file_row, start..end));
+            }
+        }
+        lines.truncate(screen_rows);
+        lines
+    }
+
+    /// Columns left of the text for marks, changes and line numbers, or 0
+    /// when there is nothing to show there.
+    fn gutter_width(&self) -> usize {
+        let width = if self.config.line_numbers {
+            // The mark column, the widest line number and a space.
+            self.editor_rows.number_of_rows().to_string().len() + 2
+        } else if self.editor_rows.marks.is_empty() && self.editor_rows.diff.is_none() {
+            0
+        } else {
+            2
+        };
+        // Room for the wrap marker and a space.
+        if self.config.wrap && !self.config.wrap_marker.is_empty() {
+            width.max(self.config.wrap_marker.chars().count() + 1)
+        } else {
+            width
+        }
+    }
+
+    /// Draws the gutter of `file_row`: its mark, or else how it changed
+    /// in color, and its line number. The mark column is in the warning
+    /// color for `mixed_indent`.
+    fn draw_gutter(&mut self, file_row: usize, width: usize, mixed_indent: bool) {
+        let change = self.editor_rows.line_change(file_row);
+        // The first of two files compared had rows removed, not added.
+        let old = self
+            .editor_rows
+            .diff
+            .as_ref()
+            .is_some_and(|diff| diff.source == DiffSource::File { old: true });
+        let mark = match (self.editor_rows.marks.on_row(file_row), change) {
+            (Some(mark), _) => mark.to_string(),
+            (None, Some(change)) => {
+                let (sign, color) = match change {
+                    LineChange::Added if old => ('-', Color::Red),
+                    LineChange::Deleted if old => ('+', Color::Green),
+                    LineChange::Added => ('+', Color::Green),
+                    LineChange::Changed => ('~', Color::Yellow),
+                    LineChange::Deleted => ('-', Color::Red),
+                };
+                format!("{}{}{}", SetForegroundColor(color), sign, ResetColor)
+            }
+            (None, None) => " ".into(),
+        };
+        let mark = if mixed_indent {
+            let color = SetBackgroundColor(HighlightType::MixedIndent.to_color());
+            format!("{}{}{}", color, mark, ResetColor)
+        } else {
+            mark
+        };
+        let gutter = if self.config.line_numbers {
+            format!("{}{:>2$} ", mark, file_row + 1, width - 2)
+        } else {
+            format!("{}{}", mark, " ".repeat(width - 1))
+        };
+        self.editor_contents.push_str(&gutter);
+    }
+
+    /// Screen rows for panes, leaving out the tab line.
+    fn text_rows(&self) -> usize {
+        self.win_size.1 - self.has_tab_line() as usize
+    }
+
+    /// The part of the screen split into panes, below the tab line.
+    fn text_area(&self) -> Rect {
+        Rect {
+            left: 0,
+            top: self.has_tab_line() as usize,
+            width: self.win_size.0,
+            height: self.text_rows(),
+        }
+    }
+
+    /// Where each pane is on screen, in `views` order.
+    fn pane_rects(&mut self) -> Vec<Rect> {
+        let area = self.text_area();
+        self.layout.fit(area);
+        self.layout.rects(area)
+    }
+
+    /// The tab line is only shown while several buffers are open.
+    fn has_tab_line(&self) -> bool {
+        self.buffer_order.len() > 1
+    }
+
+    /// Draws the names of the open buffers along the top, `+` marking
+    /// unsaved changes. The focused pane's buffer stands out.
+    fn draw_tab_line(&mut self) {
+        let tabs: Vec<(String, bool)> = self
+            .buffer_order
+            .iter()
+            .map(|&id| {
+                let editor_rows = self.buffer(id);
+                let label = format!(
+                    " {}{} ",
+                    editor_rows.display_name(),
+                    if editor_rows.dirty > 0 { "+" } else { "" }
+                );
+                (label, id == self.editor_rows.id)
+            })
+            .collect();
+        let mut room = self.win_size.0;
+        self.editor_contents
+            .push_str(&Attribute::Reverse.to_string());
+        for (label, current) in tabs {
+            let label: String = label.chars().take(room).collect();
+            room -= label.chars().count();
+            if current {
+                self.editor_contents.push_str(&Attribute::NoReverse.to_string());
+                self.editor_contents.push_str(&label);
+                self.editor_contents.push_str(&Attribute::Reverse.to_string());
+            } else {
+                self.editor_contents.push_str(&label);
+            }
+        }
+        self.editor_contents.push_str(&" ".repeat(room));
+        self.editor_contents
+            .push_str(&Attribute::Reset.to_string());
+        self.editor_contents.push_str("\r\n");
+    }
+
+    /// Draws the line below a pane in `rect` that isn't at the bottom,
+    /// naming its buffer. Unless the pane is at the right edge, the line
+    /// also takes the corner below the divider right of it.
+    fn draw_divider(&mut self, rect: Rect) {
+        let label = format!(
+            " {} {}",
+            self.editor_rows.display_name(),
+            if self.editor_rows.dirty > 0 { "(modified)" } else { "" }
+        );
+        let width = rect.width + (rect.right() < self.win_size.0) as usize;
+        let label: String = label.chars().take(width).collect();
+        let padding = width - label.chars().count();
+        queue!(self.editor_contents, cursor::MoveTo(rect.left as u16, rect.bottom() as u16))
+            .unwrap();
+        self.editor_contents
+            .push_str(&Attribute::Reverse.to_string());
+        self.editor_contents.push_str(&label);
+        self.editor_contents.push_str(&" ".repeat(padding));
+        self.editor_contents
+            .push_str(&Attribute::Reset.to_string());
+    }
+
+    /// Draws the focused buffer's bytes in `rect`, `xxd` style, with the
+    /// byte under the cursor in reverse when the pane has focus.
+    fn draw_hex(&mut self, rect: Rect, focused: bool) {
+        let Some(hex) = &self.editor_rows.hex else {
+            return;
+        };
+        let (row_offset, cursor) = (hex.row_offset, hex.cursor);
+        let cursor_row = cursor / hex::BYTES_PER_ROW;
+        let index = cursor % hex::BYTES_PER_ROW;
+        let highlighted = [
+            hex::hex_column(index)..hex::hex_column(index) + 2,
+            hex::ascii_column(index)..hex::ascii_column(index) + 1,
+        ];
+        let offset = row_offset * hex::BYTES_PER_ROW;
+        let lines = HexView::render(&hex.bytes, (rect.width, rect.height), offset);
+        for (i, line) in lines.iter().enumerate() {
+            queue!(self.editor_contents, cursor::MoveTo(rect.left as u16, (rect.top + i) as u16))
+                .unwrap();
+            for (column, ch) in line.chars().enumerate() {
+                let reverse = focused
+                    && row_offset + i == cursor_row
+                    && !hex.bytes.is_empty()
+                    && highlighted.iter().any(|cells| cells.contains(&column));
+                if reverse {
+                    self.editor_contents.push_str(&Attribute::Reverse.to_string());
+                }
+                self.editor_contents.push(ch);
+                if reverse {
+                    self.editor_contents.push_str(&Attribute::NoReverse.to_string());
+                }
+            }
+        }
+    }
+
+    /// Draws the column `rect` between panes side by side.
+    fn draw_column_divider(&mut self, rect: Rect) {
+        for row in rect.top..rect.bottom() {
+            queue!(self.editor_contents, cursor::MoveTo(rect.left as u16, row as u16)).unwrap();
+            self.editor_contents.push('│');
+        }
+    }
+
+    fn draw_status_bar(&mut self) {
+        queue!(self.editor_contents, cursor::MoveTo(0, self.win_size.1 as u16)).unwrap();
+        self.editor_contents
+            .push_str(&Attribute::Reverse.to_string());
+        let mut info = if self.config.modal {
+            format!("{} | ", self.mode.name())
+        } else {
+            String::new()
+        };
+        info.push_str(&format!(
+            "{} {} -- {} lines",
+            self.editor_rows.display_name(),
+            if self.editor_rows.dirty > 0 { "(modified)" } else { "" },
+            self.editor_rows.number_of_rows()
+        ));
+        if let Some(percent) = self.editor_rows.loading_percent() {
+            info.push_str(&format!(" [loading {}%]", percent));
+        }
+        if self.editor_rows.hex.is_some() {
+            info.push_str(" [hex]");
+        }
+        if let Some(search_status) = &self.search_status {
+            info.push_str(&format!(" | {}", search_status));
+        }
+        let info: String = info.chars().take(self.win_size.0).collect();
+        let info_len = info.chars().count();
+        let line_info = format!(
+            "{}{}:{} | {}/{}",
+            match (self.pending_keys.as_str(), self.pending_count) {
+                ("", None) => String::new(),
+                (keys, count) => format!(
+                    "{}{} | ",
+                    keys,
+                    count.map(|count| count.to_string()).unwrap_or_default()
+                ),
+            },
+            if self.editor_rows.soft_tabs { "spaces" } else { "tabs" },
+            self.editor_rows.tab_stop,
+            self.cursor_controller.cursor_y + 1,
+            self.editor_rows.number_of_rows()
+        );
+        self.editor_contents.push_str(&info);
+        for i in info_len..self.win_size.0 {
+            if self.win_size.0 - i == line_info.len() {
+                self.editor_contents.push_str(&line_info);
+                break;
+            } else {
+                self.editor_contents.push(' ')
+            }
+        }
+        self.editor_contents
+            .push_str(&Attribute::Reset.to_string());
+        self.editor_contents.push_str("\r\n");
+    }
+
+    fn draw_message_bar(&mut self) {
+        queue!(
+            self.editor_contents,
+            terminal::Clear(ClearType::UntilNewLine)
+        )
+        .unwrap();
+        if let Some(msg) = self.status_message.message() {
+            let msg: String = msg.chars().take(self.win_size.0).collect();
+            self.editor_contents.push_str(&msg);
+        }
+    }
+
+    /// Lists the menu's choices in the rows above the status bar, the first
+    /// nearest the prompt.
+    fn draw_menu(&mut self) {
+        let Some(menu) = &self.menu else {
+            return;
+        };
+        let width = self.win_size.0;
+        let shown = menu.items.len().min(MENU_ROWS).min(self.win_size.1);
+        for (index, (label, note)) in menu.items.iter().take(shown).enumerate() {
+            let note_len = note.chars().count();
+            let label: String = label.chars().take(width.saturating_sub(note_len + 1)).collect();
+            let padding = width.saturating_sub(label.chars().count() + note_len);
             queue!(
                 self.editor_contents,
+                cursor::MoveTo(0, (self.win_size.1 - 1 - index) as u16),
                 terminal::Clear(ClearType::UntilNewLine)
             )
             .unwrap();
-            if i < screen_rows - 1 {
-                self.editor_contents.push_str("\r\n");
+            if index == menu.selected {
+                self.editor_contents.push_str(&Attribute::Reverse.to_string());
+            }
+            let marked = menu.marked.get(index).filter(|marked| !marked.is_empty());
+            if let Some(marked) = marked {
+                let byte = |at| label.char_indices().nth(at).map_or(label.len(), |(byte, _)| byte);
+                let (start, end) = (byte(marked.start), byte(marked.end));
+                self.editor_contents.push_str(&label[..start]);
+                queue!(self.editor_contents, SetBackgroundColor(HighlightType::Match.to_color()))
+                    .unwrap();
+                self.editor_contents.push_str(&label[start..end]);
+                queue!(self.editor_contents, ResetColor).unwrap();
+                self.editor_contents.push_str(&label[end..]);
+            } else {
+                self.editor_contents.push_str(&label);
             }
+            self.editor_contents
+                .push_str(&format!("{}{}", " ".repeat(padding), note));
+            self.editor_contents.push_str(&Attribute::Reset.to_string());
         }
     }
 
@@ -75,153 +934,7432 @@ impl Output {
             terminal::Clear(ClearType::All), 
             cursor::MoveTo(0, 0)
         )?;
-        self.draw_rows();
-        let cursor_x = self.cursor_controller.cursor_x;
-        let cursor_y = self.cursor_controller.cursor_y;
+        if self.has_tab_line() {
+            self.draw_tab_line();
+        }
+        self.sync_compared_panes();
+        let focus = self.focus;
+        let (mut cursor_x, mut cursor_y) = (0, 0);
+        let area = self.text_area();
+        for (index, rect) in self.pane_rects().into_iter().enumerate() {
+            self.focus_view(index);
+            if let Some(hex) = &mut self.editor_rows.hex {
+                hex.scroll(rect.height);
+                if index == focus {
+                    let column = hex::hex_column(hex.cursor % hex::BYTES_PER_ROW);
+                    cursor_x = (rect.left + column).min(rect.right() - 1);
+                    cursor_y = rect.top + hex.cursor / hex::BYTES_PER_ROW - hex.row_offset;
+                }
+                self.draw_hex(rect, index == focus);
+                if rect.bottom() < area.bottom() {
+                    self.draw_divider(rect);
+                }
+                continue;
+            }
+            // Whatever took the cursor into a fold opens it.
+            self.editor_rows.folds.reveal(self.cursor_controller.cursor_y);
+            let gutter_width = self.gutter_width();
+            let cursor_controller = &mut self.cursor_controller;
+            cursor_controller.screen_columns = rect.width.saturating_sub(gutter_width).max(1);
+            cursor_controller.screen_rows = rect.height;
+            cursor_controller.wrap = self.config.wrap;
+            cursor_controller.by_screen_row = self.config.move_by_screen_row;
+            cursor_controller.scroll_off = self.config.scroll_off;
+            cursor_controller.side_scroll_off = self.config.side_scroll_off;
+            cursor_controller.scroll(&self.editor_rows);
+            if index == focus {
+                let (row, render_x) = (cursor_controller.cursor_y, cursor_controller.render_x);
+                let lines = self.display_lines(rect.height);
+                let line = lines
+                    .iter()
+                    .rposition(|(file_row, columns)| *file_row == row && columns.start <= render_x)
+                    .unwrap_or(0);
+                cursor_x = rect.left + gutter_width + render_x - lines[line].1.start;
+                cursor_y = rect.top + line;
+            }
+            self.draw_rows(rect, index == focus);
+            if rect.bottom() < area.bottom() {
+                self.draw_divider(rect);
+            }
+        }
+        for divider in self.layout.dividers(area) {
+            self.draw_column_divider(divider);
+        }
+        self.focus_view(focus);
+        self.draw_status_bar();
+        self.draw_message_bar();
+        self.draw_menu();
         queue!(
             self.editor_contents, 
-            cursor::MoveTo(0, 0),
+            cursor::MoveTo(cursor_x as u16, cursor_y as u16),
+            self.mode.cursor_style(),
             cursor::Show
         )?;
         self.editor_contents.flush()
     }
 
-    fn move_cursor(&mut self,direction:char) {
-        self.cursor_controller.move_cursor(direction);
+    /// Makes pane `index` the one `cursor_controller` and `editor_rows`
+    /// belong to, putting the focused pane's state back in `views` and
+    /// `buffers`.
+    fn focus_view(&mut self, index: usize) {
+        if index == self.focus {
+            return;
+        }
+        let focused = &mut self.views[self.focus];
+        focused.buffer = self.editor_rows.id;
+        std::mem::swap(&mut focused.cursor_controller, &mut self.cursor_controller);
+        std::mem::swap(&mut self.cursor_controller, &mut self.views[index].cursor_controller);
+        let buffer = self.views[index].buffer;
+        if buffer != self.editor_rows.id {
+            let at = self
+                .buffers
+                .iter()
+                .position(|editor_rows| editor_rows.id == buffer)
+                .expect("pane shows a buffer that is gone");
+            std::mem::swap(&mut self.editor_rows, &mut self.buffers[at]);
+        }
+        self.focus = index;
+        // The buffer may have been edited in another pane meanwhile.
+        self.clamp_cursor();
     }
-}
 
-struct Reader;
+    /// Keeps the cursor inside the buffer.
+    fn clamp_cursor(&mut self) {
+        let cursor_controller = &mut self.cursor_controller;
+        cursor_controller.cursor_y = cursor_controller
+            .cursor_y
+            .min(self.editor_rows.number_of_rows() - 1);
+        cursor_controller.cursor_x = self
+            .editor_rows
+            .get_editor_row(cursor_controller.cursor_y)
+            .snap(cursor_controller.cursor_x);
+    }
 
-impl Reader {
-    fn read_key(&self) -> std::result::Result<KeyEvent, std::io::Error> {
-        loop {
-            if event::poll(Duration::from_millis(500))? {
-                if let Event::Key(event) = event::read()? {
-                    return Ok(event);
-                }
-            }
+    /// Reads the focused buffer's file again, dropping unsaved changes and
+    /// the undo history, and keeps the cursor where it was if the file is
+    /// still long enough.
+    fn reload(&mut self) -> io::Result<()> {
+        let Some(file) = self.editor_rows.filename.clone() else {
+            return Err(io::Error::other("no file name specified"));
+        };
+        let mut editor_rows = EditorRows::from_file(file, &self.config)?;
+        editor_rows.id = self.editor_rows.id;
+        editor_rows.backed_up = self.editor_rows.backed_up;
+        self.editor_rows = editor_rows;
+        self.extra_cursors.clear();
+        self.block = None;
+        self.set_mode(Mode::Normal);
+        self.clamp_cursor();
+        self.status_message
+            .set_message(format!("\"{}\" reloaded", self.editor_rows.display_name()));
+        Ok(())
+    }
+
+    /// Splits the focused pane in two showing the same place, stacked with
+    /// `Direction::Rows` or side by side with `Direction::Columns`, keeping
+    /// the top or left one focused. Returns `false` if there isn't room.
+    fn split(&mut self, direction: Direction) -> bool {
+        let rect = self.pane_rects()[self.focus];
+        let size = match direction {
+            Direction::Rows => rect.height,
+            Direction::Columns => rect.width,
+        };
+        if !self.layout.split(self.focus, direction, size) {
+            self.status_message.set_message("Not enough room to split".into());
+            return false;
         }
+        let view = View {
+            buffer: self.editor_rows.id,
+            cursor_controller: self.cursor_controller.clone(),
+        };
+        self.views.insert(self.focus + 1, view);
+        true
     }
-}
 
-struct CursorController {
-    cursor_x: usize,
-    cursor_y: usize,
-}
+    /// Shows the first two files side by side, each compared with the
+    /// other, as `--diff` asks for.
+    fn compare_files(&mut self) {
+        if self.buffers.len() != 1 {
+            self.status_message.set_message("--diff compares two files".into());
+            return;
+        }
+        if !self.split(Direction::Columns) {
+            return;
+        }
+        let other = &mut self.buffers[0];
+        self.views[1].buffer = other.id;
+        let (old, new) = (self.editor_rows.row_strings(), other.row_strings());
+        other.set_diff_base(DiffSource::File { old: false }, Some(old));
+        self.editor_rows.set_diff_base(DiffSource::File { old: true }, Some(new));
+        let changes = self.editor_rows.hunk_rows().len();
+        self.status_message.set_message(format!("{} changes", changes));
+    }
 
-impl CursorController {
-    fn new() -> CursorController {
-        Self {
-            cursor_x: 0,
-            cursor_y: 0,
+    /// Scrolls the other pane of a `--diff` along with the focused one, to
+    /// the rows matching those the focused one shows.
+    fn sync_compared_panes(&mut self) {
+        let Some(diff) = &self.editor_rows.diff else {
+            return;
+        };
+        let DiffSource::File { .. } = diff.source else {
+            return;
+        };
+        if self.views.len() != 2 || self.views[1 - self.focus].buffer == self.editor_rows.id {
+            return;
         }
+        self.cursor_controller.scroll(&self.editor_rows);
+        let other = &mut self.views[1 - self.focus].cursor_controller;
+        other.row_offset = diff::old_row(&diff.hunks, self.cursor_controller.row_offset);
+        other.cursor_y = diff::old_row(&diff.hunks, self.cursor_controller.cursor_y);
     }
 
-    fn move_cursor(&mut self, direction: char) {
-        match direction {
-            'w' => {
-                self.cursor_y -= 1;
-            }
-            'a' => {
-                self.cursor_x -= 1;
-            }
-            's' => {
-                self.cursor_y += 1;
+    /// Closes the focused pane, giving its room to the one after it, or
+    /// before it for the last in its split, which takes the keys. Its
+    /// buffer stays open.
+    fn close_pane(&mut self) {
+        if self.views.len() == 1 {
+            self.status_message.set_message("Can't close the last pane".into());
+            return;
+        }
+        let closing = self.focus;
+        self.cycle_focus(if closing + 1 < self.views.len() { 1 } else { -1 });
+        self.views.remove(closing);
+        self.layout.remove(closing);
+        if closing < self.focus {
+            self.focus -= 1;
+        }
+    }
+
+    /// Closes every pane but the focused one.
+    fn only_pane(&mut self) {
+        let focused = self.views.swap_remove(self.focus);
+        self.views = vec![focused];
+        self.layout = Layout::Pane;
+        self.focus = 0;
+    }
+
+    /// Tells where the cursor is and how many words and chars the buffer
+    /// has, as `g Ctrl-G` does in vim. Words are runs of non-whitespace,
+    /// and chars count the newlines the file is saved with.
+    fn show_counts(&mut self) {
+        let (row, col) = self.cursor();
+        let editor_rows = &self.editor_rows;
+        let rows = editor_rows.number_of_rows();
+        let (mut words, mut chars) = (0, 0);
+        for at in 0..rows {
+            let content = editor_rows.get_row(at);
+            words += content.split_whitespace().count();
+            chars += content.chars().count();
+        }
+        chars += rows - 1 + editor_rows.final_newline as usize;
+        let col = editor_rows.get_row(row)[..col].chars().count();
+        self.status_message.set_message(format!(
+            "Col {}, Row {} of {} ({} words, {} chars)",
+            col + 1,
+            row + 1,
+            rows,
+            words,
+            chars
+        ));
+    }
+
+    /// Describes the char under the cursor, as `ga` does in vim.
+    fn show_char_info(&mut self) {
+        let (row, col) = self.cursor();
+        let message = match self.editor_rows.get_row(row)[col..].chars().next() {
+            Some(ch) => char_info(ch),
+            None => "NUL".into(),
+        };
+        self.status_message.set_message(message);
+    }
+
+    /// Shows in the gutter how the buffer differs from its file as saved,
+    /// or goes back to how it differs from git's `HEAD`, if the file is in
+    /// git.
+    fn toggle_diff(&mut self) {
+        let editor_rows = &mut self.editor_rows;
+        if editor_rows.diff.as_ref().is_some_and(|diff| diff.source == DiffSource::Saved) {
+            editor_rows.set_diff_base(DiffSource::Saved, None);
+            editor_rows.refresh_git();
+            self.status_message.set_message("Diff off".into());
+            return;
+        }
+        let Some(file) = &editor_rows.filename else {
+            self.status_message.set_message("No file to compare with".into());
+            return;
+        };
+        let message = match fs::read(file) {
+            Ok(contents) => {
+                let mut rows: Vec<String> =
+                    String::from_utf8_lossy(&contents).lines().map(String::from).collect();
+                if rows.is_empty() {
+                    rows.push(String::new());
+                }
+                editor_rows.set_diff_base(DiffSource::Saved, Some(rows));
+                format!("{} changes since saved", editor_rows.hunk_rows().len())
             }
-            'd' => {
-                self.cursor_x += 1;
+            Err(err) => format!("Can't read {}: {}", file.display(), err),
+        };
+        self.status_message.set_message(message);
+    }
+
+    /// Compares the focused buffer with git's `HEAD` again, as after a
+    /// commit, rather than with the file as saved.
+    fn refresh_diff(&mut self) {
+        let editor_rows = &mut self.editor_rows;
+        if editor_rows.filename.is_none() {
+            self.status_message.set_message("No file to compare with".into());
+            return;
+        }
+        if editor_rows.diff.as_ref().is_some_and(|diff| diff.source == DiffSource::Saved) {
+            editor_rows.set_diff_base(DiffSource::Saved, None);
+        }
+        editor_rows.refresh_git();
+        self.status_message.set_message("Comparing with HEAD".into());
+    }
+
+    /// Shows the focused buffer's bytes in hex, or its text again. A buffer
+    /// with unsaved changes shows the bytes saving it would write.
+    fn toggle_hex(&mut self) {
+        let editor_rows = &mut self.editor_rows;
+        if editor_rows.hex.take().is_some() {
+            self.status_message.set_message("Hex view off".into());
+            return;
+        }
+        let bytes = match &editor_rows.filename {
+            Some(file) if editor_rows.dirty == 0 => fs::read(file),
+            _ => Ok(editor_rows.bytes()),
+        };
+        match bytes {
+            Ok(bytes) => {
+                let message = format!("Hex view: {} bytes", bytes.len());
+                editor_rows.hex = Some(HexView::new(bytes));
+                self.set_mode(Mode::Normal);
+                self.status_message.set_message(message);
             }
-            _ => unimplemented!(),
+            Err(err) => self.status_message.set_message(format!("Can't read file: {}", err)),
         }
     }
-}
 
-struct EditorContents {
-    content: String,
-}
+    /// Moves to the `count`th change below the cursor's row, or above it
+    /// with `back`. Returns `false` if there are fewer.
+    fn jump_to_change(&mut self, back: bool, count: usize) -> bool {
+        let row = self.cursor_controller.cursor_y;
+        let rows = self.editor_rows.hunk_rows();
+        let target = if back {
+            rows.into_iter().rev().filter(|&at| at < row).nth(count - 1)
+        } else {
+            rows.into_iter().filter(|&at| at > row).nth(count - 1)
+        };
+        let Some(target) = target else {
+            return false;
+        };
+        self.cursor_controller.cursor_y = target;
+        self.cursor_controller.cursor_x = 0;
+        true
+    }
 
-impl EditorContents {
-    fn new() -> Self {
-        Self {
-            content: String::new(),
+    /// Opens the fold under the cursor's row, or else folds the rows
+    /// indented deeper under it, or else the block it is in, moving to the
+    /// row heading that.
+    fn toggle_fold(&mut self) {
+        if !self.editor_rows.folds.open(self.cursor_controller.cursor_y) {
+            self.close_fold();
         }
     }
 
-    fn push(&mut self, ch: char) {
-        self.content.push(ch)
+    /// Opens the fold under the cursor's row.
+    fn open_fold(&mut self) {
+        if !self.editor_rows.folds.open(self.cursor_controller.cursor_y) {
+            self.status_message.set_message("No fold here".into());
+        }
     }
 
-    fn push_str(&mut self, string: &str) {
-        self.content.push_str(string)
+    /// Folds the rows indented deeper under the cursor's row, or if there
+    /// are none or they are folded already, the block it is in, moving to
+    /// the row heading that.
+    fn close_fold(&mut self) {
+        let row = self.cursor_controller.cursor_y;
+        let editor_rows = &mut self.editor_rows;
+        let rows = editor_rows.number_of_rows();
+        let folded = editor_rows.folds.under(row).is_some();
+        let indent = |at| editor_rows.indent_width(at);
+        let Some((header, body)) = fold::body(row, rows, indent)
+            .filter(|_| !folded)
+            .map(|body| (row, body))
+            .or_else(|| {
+                let header = fold::header(row, indent)?;
+                Some((header, fold::body(header, rows, indent)?))
+            })
+        else {
+            self.status_message.set_message("Nothing to fold".into());
+            return;
+        };
+        editor_rows.folds.close(body);
+        let cursor_controller = &mut self.cursor_controller;
+        cursor_controller.cursor_y = header;
+        cursor_controller.cursor_x = editor_rows.get_editor_row(header).snap(cursor_controller.cursor_x);
     }
-}
 
-impl io::Write for EditorContents {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        match std::str::from_utf8(buf) {
-            Ok(s) => {
-                self.content.push_str(s);
-                Ok(s.len())
+    /// Folds every indented block, inner ones inside outer ones, moving to
+    /// the row heading the fold the cursor ends up in.
+    fn close_all_folds(&mut self) {
+        let editor_rows = &mut self.editor_rows;
+        let rows = editor_rows.number_of_rows();
+        editor_rows.folds.clear();
+        for row in 0..rows {
+            if let Some(body) = fold::body(row, rows, |at| editor_rows.indent_width(at)) {
+                editor_rows.folds.close(body);
             }
-            Err(_) => Err(io::ErrorKind::WriteZero.into()),
         }
+        let cursor_controller = &mut self.cursor_controller;
+        let header = editor_rows.folds.shown(cursor_controller.cursor_y);
+        cursor_controller.cursor_y = header;
+        cursor_controller.cursor_x = editor_rows.get_editor_row(header).snap(cursor_controller.cursor_x);
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        let out = write!(stdout(), "{}", self.content);
-        stdout().flush()?;
-        self.content.clear();
-        out
+    /// Grows the focused pane by `delta` rows or columns, `direction`'s
+    /// way, or shrinks it for a negative `delta`.
+    fn resize_pane(&mut self, direction: Direction, delta: isize) {
+        self.pane_rects();
+        self.layout.resize(self.focus, direction, delta);
     }
-}
 
-struct Editor {
-    reader: Reader,
-    output: Output,
-}
+    /// Makes every pane the same size as the others in its split.
+    fn equalize_panes(&mut self) {
+        let area = self.text_area();
+        self.layout.equalize(area);
+    }
 
-impl Editor {
-    fn new() -> Self {
-        Self {
-            reader: Reader,
-            output: Output::new(),
+    /// Moves the keys to the pane `offset` panes on, wrapping around.
+    fn cycle_focus(&mut self, offset: isize) {
+        let panes = self.views.len() as isize;
+        let index = (self.focus as isize + offset).rem_euclid(panes) as usize;
+        if index != self.focus {
+            if self.mode == Mode::Visual {
+                self.set_mode(Mode::Normal);
+            }
+            self.selection_anchor = None;
+            self.extra_cursors.clear();
+            self.focus_view(index);
         }
     }
 
-    fn process_keypress(&mut self) -> std::result::Result<bool, std::io::Error> { /* modify*/
-        match self.reader.read_key()? {
-            KeyEvent {
-                code: KeyCode::Char('q'),
-                modifiers: KeyModifiers::CONTROL,
-                kind: _,
-                state: _
-            } => return Ok(false),
-            /* add the following*/
-            KeyEvent {
-                code: KeyCode::Char(val @ ('w' | 'a' | 's' | 'd')),
-                modifiers: KeyModifiers::NONE,
-                kind: _,
-                state: _
-            } => self.output.move_cursor(val),
-            // end
-            _ => {}
+    /// The open buffer `id`.
+    fn buffer(&self, id: usize) -> &EditorRows {
+        if id == self.editor_rows.id {
+            return &self.editor_rows;
         }
-        Ok(true)
+        self.buffers
+            .iter()
+            .find(|editor_rows| editor_rows.id == id)
+            .expect("buffer is not open")
     }
 
-    fn run(&mut self) -> std::result::Result<bool, std::io::Error> {
-        self.output.refresh_screen()?;
-        self.process_keypress()
+    /// The open buffer showing `path`, if any.
+    fn buffer_for(&self, path: &Path) -> Option<usize> {
+        self.buffer_order
+            .iter()
+            .copied()
+            .find(|&id| self.buffer(id).filename.as_deref() == Some(path))
     }
-}
 
-fn main() -> std::result::Result<(), std::io::Error> {
-    let _clean_up = CleanUp;
-    terminal::enable_raw_mode()?;
+    /// Names of the buffers with unsaved changes, in tab line order.
+    fn modified_buffers(&self) -> Vec<&str> {
+        self.buffer_order
+            .iter()
+            .map(|&id| self.buffer(id))
+            .filter(|editor_rows| editor_rows.dirty > 0)
+            .map(EditorRows::display_name)
+            .collect()
+    }
 
-    let mut editor = Editor::new();
-    while editor.run()? {}
+    /// Adds `editor_rows` to the tab line after the focused pane's buffer
+    /// and shows it there. An untouched unnamed buffer no other pane shows,
+    /// such as the one the editor starts with, is closed instead.
+    fn add_buffer(&mut self, editor_rows: EditorRows) {
+        let id = editor_rows.id;
+        let current = self.editor_rows.id;
+        let at = self.buffer_order.iter().position(|&other| other == current).unwrap();
+        let scratch = self.editor_rows.filename.is_none()
+            && self.editor_rows.dirty == 0
+            && self.views.iter().filter(|view| view.buffer == current).count() == 1;
+        if scratch {
+            self.buffer_order[at] = id;
+            self.editor_rows = editor_rows;
+            self.views[self.focus].buffer = id;
+            self.cursor_controller = CursorController::new(self.win_size);
+            self.selection_anchor = None;
+            self.extra_cursors.clear();
+            return;
+        }
+        self.buffer_order.insert(at + 1, id);
+        self.buffers.push(editor_rows);
+        self.show_buffer(id);
+    }
 
-    Ok(())
-}
\ No newline at end of file
+    /// Shows the open buffer `id` in the focused pane, with the cursor where
+    /// it was when the pane last showed it.
+    fn show_buffer(&mut self, id: usize) {
+        if id == self.editor_rows.id {
+            return;
+        }
+        if self.mode == Mode::Visual {
+            self.set_mode(Mode::Normal);
+        }
+        self.selection_anchor = None;
+        self.extra_cursors.clear();
+        let at = self
+            .buffers
+            .iter()
+            .position(|editor_rows| editor_rows.id == id)
+            .expect("buffer is not open");
+        self.saved_cursors
+            .insert(self.editor_rows.id, self.cursor_controller.clone());
+        std::mem::swap(&mut self.editor_rows, &mut self.buffers[at]);
+        self.views[self.focus].buffer = id;
+        self.cursor_controller = self
+            .saved_cursors
+            .remove(&id)
+            .unwrap_or_else(|| CursorController::new(self.win_size));
+        self.clamp_cursor();
+    }
+
+    /// Closes the open buffer `id`, dropping any changes. Panes showing it
+    /// show the next buffer in the tab line instead, or a new empty one if
+    /// it was the only buffer.
+    fn close_buffer(&mut self, id: usize) {
+        let at = self
+            .buffer_order
+            .iter()
+            .position(|&other| other == id)
+            .expect("buffer is not open");
+        if self.buffer_order.len() == 1 {
+            self.editor_rows = EditorRows::empty(None, &self.config);
+            self.buffer_order = vec![self.editor_rows.id];
+            for view in &mut self.views {
+                view.buffer = self.editor_rows.id;
+                view.cursor_controller = CursorController::new(self.win_size);
+            }
+            self.cursor_controller = CursorController::new(self.win_size);
+            self.saved_cursors.clear();
+            self.selection_anchor = None;
+            self.extra_cursors.clear();
+            return;
+        }
+        self.buffer_order.remove(at);
+        let next = self.buffer_order[at.min(self.buffer_order.len() - 1)];
+        for (index, view) in self.views.iter_mut().enumerate() {
+            if index != self.focus && view.buffer == id {
+                view.buffer = next;
+                view.cursor_controller = self
+                    .saved_cursors
+                    .get(&next)
+                    .cloned()
+                    .unwrap_or_else(|| CursorController::new(self.win_size));
+            }
+        }
+        if self.editor_rows.id == id {
+            self.show_buffer(next);
+        }
+        self.buffers.retain(|editor_rows| editor_rows.id != id);
+        self.saved_cursors.remove(&id);
+    }
+
+    /// Shows the buffer `offset` tabs along in the focused pane, wrapping
+    /// around.
+    fn cycle_buffer(&mut self, offset: isize) {
+        let tabs = self.buffer_order.len() as isize;
+        let current = self
+            .buffer_order
+            .iter()
+            .position(|&id| id == self.editor_rows.id)
+            .unwrap() as isize;
+        self.show_buffer(self.buffer_order[(current + offset).rem_euclid(tabs) as usize]);
+    }
+
+    fn move_cursor(&mut self,direction:KeyCode) {
+        self.cursor_controller.move_cursor(direction, &self.editor_rows);
+    }
+
+    /// Takes git's answers for every buffer waiting on one.
+    fn poll_git(&mut self) {
+        self.editor_rows.poll_git();
+        for editor_rows in &mut self.buffers {
+            editor_rows.poll_git();
+        }
+    }
+
+    /// Whether any buffer is waiting on git.
+    fn git_pending(&self) -> bool {
+        self.editor_rows.git.is_some() || self.buffers.iter().any(|editor_rows| editor_rows.git.is_some())
+    }
+
+    /// Applies what the search worker reported since the last call and
+    /// drops the job once it has finished. Returns whether anything changed.
+    fn poll_search(&mut self, search_job: &mut Option<SearchJob>) -> bool {
+        let Some(job) = search_job else {
+            return false;
+        };
+        let events = job.events();
+        for event in &events {
+            match *event {
+                SearchEvent::Found(row, col, wrapped) if row < self.editor_rows.number_of_rows() => {
+                    if wrapped {
+                        self.status_message.set_message("search wrapped".into());
+                    }
+                    self.search_match = Some((row, col));
+                    self.cursor_controller.cursor_y = row;
+                    self.cursor_controller.cursor_x = self.editor_rows.get_editor_row(row).snap(col);
+                }
+                SearchEvent::Found(..) => {}
+                SearchEvent::Counted(count) => {
+                    self.search_status = Some(format!("searching… {} so far", group_digits(count)))
+                }
+                SearchEvent::Finished { index, total } => {
+                    self.search_status = Some(match index {
+                        Some(index) => format!("match {} of {}", group_digits(index), group_digits(total)),
+                        None => "no matches".into(),
+                    });
+                    *search_job = None;
+                }
+            }
+        }
+        !events.is_empty()
+    }
+
+    /// Stops highlighting the last search.
+    fn clear_search(&mut self) {
+        self.search_query = None;
+        self.search_match = None;
+        self.search_status = None;
+    }
+
+    /// Whether the buffer may be edited, telling the user why not otherwise.
+    fn editable(&mut self) -> bool {
+        if self.editor_rows.loader.is_some() {
+            self.status_message
+                .set_message("File is still loading (read-only)".into());
+            return false;
+        }
+        if self.editor_rows.hex.is_some() {
+            self.status_message.set_message("Hex view is read-only".into());
+            return false;
+        }
+        true
+    }
+
+    fn insert_newline(&mut self) {
+        if !self.editable() {
+            return;
+        }
+        let cursor_x = self.cursor_controller.cursor_x;
+        let cursor_y = self.cursor_controller.cursor_y;
+        let tab_stop = self.editor_rows.tab_stop;
+        let indent = self.indent_of(cursor_y);
+        let current_row = self.editor_rows.get_editor_row_mut(cursor_y);
+        let new_row_content = current_row.row_content.split_off(cursor_x);
+        current_row.update_render(tab_stop);
+        let new_row_content = if indent.is_empty() {
+            new_row_content
+        } else {
+            indent.clone() + new_row_content.trim_start_matches([' ', '\t'])
+        };
+        self.editor_rows.insert_row(cursor_y + 1, new_row_content);
+        self.cursor_controller.cursor_y += 1;
+        self.cursor_controller.cursor_x = indent.len();
+        self.editor_rows.dirty += 1;
+    }
+
+    fn delete_char(&mut self) {
+        if !self.editable() {
+            return;
+        }
+        let cursor_x = self.cursor_controller.cursor_x;
+        let cursor_y = self.cursor_controller.cursor_y;
+        if cursor_x > 0 {
+            let tab_stop = self.editor_rows.tab_stop;
+            let row = self.editor_rows.get_editor_row_mut(cursor_y);
+            let at = row.prev_boundary(cursor_x);
+            row.delete_char(at, tab_stop);
+            self.cursor_controller.cursor_x = at;
+        } else if cursor_y > 0 {
+            self.cursor_controller.cursor_x = self.editor_rows.get_row(cursor_y - 1).len();
+            self.editor_rows.join_adjacent_rows(cursor_y - 1);
+            self.cursor_controller.cursor_y -= 1;
+        } else {
+            return;
+        }
+        self.editor_rows.dirty += 1;
+    }
+
+    fn delete_forward(&mut self) {
+        if !self.editable() {
+            return;
+        }
+        let cursor_x = self.cursor_controller.cursor_x;
+        let cursor_y = self.cursor_controller.cursor_y;
+        if cursor_x < self.editor_rows.get_row(cursor_y).len() {
+            let tab_stop = self.editor_rows.tab_stop;
+            self.editor_rows
+                .get_editor_row_mut(cursor_y)
+                .delete_char(cursor_x, tab_stop);
+        } else if cursor_y + 1 < self.editor_rows.number_of_rows() {
+            self.editor_rows.join_adjacent_rows(cursor_y);
+        } else {
+            return;
+        }
+        self.editor_rows.dirty += 1;
+    }
+
+    /// Deletes `count` lines starting at the cursor's, or as many as there
+    /// are below it. The buffer always keeps at least one (possibly empty)
+    /// row.
+    fn delete_lines(&mut self, count: usize) {
+        if !self.editable() {
+            return;
+        }
+        let cursor_y = self.cursor_controller.cursor_y;
+        let count = count.min(self.editor_rows.number_of_rows() - cursor_y);
+        for _ in 0..count {
+            self.editor_rows.delete_row(cursor_y);
+        }
+        if self.editor_rows.number_of_rows() == 0 {
+            self.editor_rows.insert_row(0, String::new());
+        }
+        self.cursor_controller.cursor_y = cursor_y.min(self.editor_rows.number_of_rows() - 1);
+        self.cursor_controller.cursor_x = 0;
+        self.editor_rows.dirty += 1;
+    }
+
+    /// Joins the selected rows into one, or without a selection `count`
+    /// rows from the cursor's, at least two, as one step to undo. Without
+    /// `spaces` the rows are joined as they are. Returns `false` if there is
+    /// no row to join.
+    fn join_lines(&mut self, count: usize, spaces: bool) -> bool {
+        let (first, last) = match self.selection() {
+            Some((start, end)) => (start.0, end.0.max(start.0 + 1)),
+            None => {
+                let row = self.cursor_controller.cursor_y;
+                (row, row + count.max(2) - 1)
+            }
+        };
+        let last = last.min(self.editor_rows.number_of_rows() - 1);
+        if first == last || !self.editable() {
+            return false;
+        }
+        if self.mode == Mode::Visual {
+            self.set_mode(Mode::Normal);
+        }
+        self.selection_anchor = None;
+        self.undo_step(|output| output.join_rows(first, last, spaces));
+        true
+    }
+
+    /// Joins rows `first` to `last` into one. With `spaces`, each row's
+    /// leading whitespace becomes a single space, left out after a row
+    /// already ending in whitespace and for an empty row. The cursor goes to
+    /// the last join.
+    fn join_rows(&mut self, first: usize, last: usize, spaces: bool) {
+        let tab_stop = self.editor_rows.tab_stop;
+        let mut joined_at = 0;
+        for _ in first..last {
+            if !spaces {
+                joined_at = self.editor_rows.get_row(first).len();
+                self.editor_rows.join_adjacent_rows(first);
+                continue;
+            }
+            let next = self.editor_rows.get_editor_row_mut(first + 1);
+            let indent = next.row_content.len() - next.row_content.trim_start_matches([' ', '\t']).len();
+            next.row_content.drain(..indent);
+            let next_empty = next.row_content.is_empty();
+            let row = self.editor_rows.get_editor_row_mut(first);
+            joined_at = row.row_content.len();
+            if !next_empty && !row.row_content.ends_with(char::is_whitespace) {
+                row.row_content.push(' ');
+                row.update_render(tab_stop);
+            }
+            self.editor_rows.join_adjacent_rows(first);
+        }
+        self.cursor_controller.cursor_y = first;
+        self.cursor_controller.cursor_x = self.editor_rows.get_editor_row(first).snap(joined_at);
+        self.editor_rows.dirty += 1;
+    }
+
+    fn insert_char(&mut self, ch: char) {
+        if !self.editable() {
+            return;
+        }
+        let cursor_x = self.cursor_controller.cursor_x;
+        let cursor_y = self.cursor_controller.cursor_y;
+        let tab_stop = self.editor_rows.tab_stop;
+        self.editor_rows
+            .get_editor_row_mut(cursor_y)
+            .insert_char(cursor_x, ch, tab_stop);
+        self.cursor_controller.cursor_x += ch.len_utf8();
+        self.editor_rows.dirty += 1;
+    }
+
+    /// What typing `ch` at the cursor does, going by the chars around it:
+    /// with `auto_close`, an opening bracket or quote adds the char closing
+    /// it, and a closing char added that way is moved over.
+    fn typed_pair(&mut self, ch: char) -> Typed {
+        if !self.config.auto_close || self.selection().is_some() {
+            return Typed::Insert;
+        }
+        let (row, col) = self.cursor();
+        let text = self.editor_rows.get_row(row);
+        let (before, after) = (text[..col].chars().next_back(), text[col..].chars().next());
+        if self.auto_closed > 0 && after == Some(ch) && pairs::is_closing(ch) {
+            self.auto_closed -= 1;
+            return Typed::Over;
+        }
+        match pairs::closing(ch) {
+            Some(close) if pairs::should_close(ch, before, after) => {
+                self.auto_closed += 1;
+                Typed::Pair(close)
+            }
+            _ => Typed::Insert,
+        }
+    }
+
+    /// Types `ch` at the cursor as [`Output::typed_pair`] decided.
+    fn type_char(&mut self, ch: char, typed: Typed) {
+        let (row, col) = self.cursor();
+        match typed {
+            Typed::Over if self.editor_rows.get_row(row)[col..].starts_with(ch) => {
+                self.cursor_controller.cursor_x += ch.len_utf8();
+            }
+            Typed::Pair(close) => {
+                self.insert_char(ch);
+                self.insert_char(close);
+                self.cursor_controller.cursor_x -= close.len_utf8();
+            }
+            _ => self.insert_char(ch),
+        }
+    }
+
+    /// Whether the cursor is between the chars opening and closing a pair.
+    fn in_empty_pair(&self) -> bool {
+        let (row, col) = self.cursor();
+        let text = self.editor_rows.get_row(row);
+        let after = text[col..].chars().next();
+        text[..col]
+            .chars()
+            .next_back()
+            .and_then(pairs::closing)
+            .is_some_and(|close| after == Some(close))
+    }
+
+    /// Whether Backspace should also delete the closing char after the
+    /// cursor, which `auto_close` added.
+    fn deletes_pair(&mut self) -> bool {
+        if self.auto_closed == 0 || !self.in_empty_pair() {
+            return false;
+        }
+        self.auto_closed -= 1;
+        true
+    }
+
+    /// Overwrites the char under the cursor with `ch`, or adds `ch` at the
+    /// end of the row, and moves past it. Returns the char overwritten.
+    fn replace_char(&mut self, ch: char) -> Option<char> {
+        if !self.editable() {
+            return None;
+        }
+        let cursor_x = self.cursor_controller.cursor_x;
+        let tab_stop = self.editor_rows.tab_stop;
+        let row = self.editor_rows.get_editor_row_mut(self.cursor_controller.cursor_y);
+        let replaced = row.row_content[cursor_x..].chars().next();
+        if replaced.is_some() {
+            row.delete_char(cursor_x, tab_stop);
+        }
+        row.insert_char(cursor_x, ch, tab_stop);
+        self.cursor_controller.cursor_x += ch.len_utf8();
+        self.editor_rows.dirty += 1;
+        replaced
+    }
+
+    /// Replaces `count` chars from the cursor with `ch`, leaving the cursor
+    /// on the last of them, as one step to undo. Returns `false`, changing
+    /// nothing, if fewer are left on the row.
+    fn replace_chars(&mut self, ch: char, count: usize) -> bool {
+        let (row, col) = self.cursor();
+        if self.editor_rows.get_row(row)[col..].chars().count() < count || !self.editable() {
+            return false;
+        }
+        self.undo_step(|output| {
+            (0..count).for_each(|_| {
+                output.replace_char(ch);
+            });
+        });
+        let cursor_x = self.cursor_controller.cursor_x;
+        self.cursor_controller.cursor_x = self.editor_rows.get_editor_row(row).prev_boundary(cursor_x);
+        true
+    }
+
+    /// Puts the selected text in `case`, or without a selection the word
+    /// under the cursor, as one step to undo, leaving the cursor at its
+    /// start. Text before it keeps its length, so the start stays put.
+    fn change_case(&mut self, case: Case) {
+        if !self.editable() {
+            return;
+        }
+        let word = TextObject::Word { big: false };
+        let Some((start, end)) = self.selection().or_else(|| {
+            text_object::range(&self.editor_rows, self.cursor(), word, true)
+                .map(|(start, end, _)| (start, end))
+        }) else {
+            return;
+        };
+        self.undo_step(|output| output.editor_rows.change_case(start, end, case));
+        if self.mode == Mode::Visual {
+            self.set_mode(Mode::Normal);
+        }
+        self.selection_anchor = None;
+        self.cursor_controller.cursor_y = start.0;
+        self.cursor_controller.cursor_x = self.editor_rows.get_editor_row(start.0).snap(start.1);
+    }
+
+    /// Swaps the case of the selected text, leaving the cursor at its start,
+    /// or of `count` chars from the cursor, moving past them.
+    fn toggle_case(&mut self, count: usize) {
+        if !self.editable() {
+            return;
+        }
+        if self.selection().is_some() {
+            return self.change_case(Case::Toggle);
+        }
+        let (row, col) = self.cursor();
+        let line = self.editor_rows.get_row(row);
+        let end = line[col..]
+            .char_indices()
+            .nth(count)
+            .map_or(line.len(), |(at, _)| col + at);
+        let after = line.len() - end;
+        self.editor_rows.toggle_case(row, col, end);
+        // Letters can change length, as `ß` does; the rest of the row can't.
+        let row = self.editor_rows.get_editor_row(row);
+        let end = row.row_content.len() - after;
+        self.cursor_controller.cursor_x = if end == row.row_content.len() {
+            row.prev_boundary(end)
+        } else {
+            end
+        };
+    }
+
+    /// Swaps the two chars before the cursor, turning `teh` into `the`.
+    fn transpose_chars(&mut self) {
+        let (row, col) = self.cursor();
+        let mut before = self.editor_rows.get_row(row)[..col].char_indices().rev();
+        let (Some((_, second)), Some((start, first))) = (before.next(), before.next()) else {
+            return self.status_message.set_message("Nothing to transpose".into());
+        };
+        if !self.editable() {
+            return;
+        }
+        let swapped = format!("{}{}", second, first);
+        self.editor_rows.replace_in_row((row, start), col - start, &swapped);
+        self.editor_rows.dirty += 1;
+    }
+
+    /// Deletes from the cursor over separators and then a word, back with
+    /// Ctrl+Backspace or forward with Ctrl+Delete. At the start or end of a
+    /// row it only joins the row to its neighbour, and otherwise it stops
+    /// at the row's edge.
+    fn delete_word(&mut self, forward: bool) {
+        let (row, col) = self.cursor();
+        let len = self.editor_rows.get_row(row).len();
+        let to = match (forward, col) {
+            (false, 0) if row > 0 => (row - 1, self.editor_rows.get_row(row - 1).len()),
+            (true, _) if col == len && row + 1 < self.editor_rows.number_of_rows() => (row + 1, 0),
+            _ => match word_motion::skip_word(&self.editor_rows, (row, col), forward) {
+                (to_row, to_col) if to_row == row => (row, to_col),
+                _ if forward => (row, len),
+                _ => (row, 0),
+            },
+        };
+        if to == (row, col) || !self.editable() {
+            return;
+        }
+        let (start, end) = if forward { ((row, col), to) } else { (to, (row, col)) };
+        self.editor_rows.delete_range(start, end);
+        self.cursor_controller.cursor_y = start.0;
+        self.cursor_controller.cursor_x = start.1;
+        self.editor_rows.dirty += 1;
+    }
+
+    /// Swaps the word at or before the cursor with the next one on the row,
+    /// keeping what is between them, and moves past both.
+    fn transpose_words(&mut self) {
+        let (row, col) = self.cursor();
+        let line = self.editor_rows.get_row(row);
+        let Some((first, second)) = word_motion::transpose_pair(line, col) else {
+            return self.status_message.set_message("Nothing to transpose".into());
+        };
+        let swapped = format!(
+            "{}{}{}",
+            &line[second.clone()],
+            &line[first.end..second.start],
+            &line[first.clone()]
+        );
+        if !self.editable() {
+            return;
+        }
+        self.editor_rows
+            .replace_in_row((row, first.start), second.end - first.start, &swapped);
+        self.cursor_controller.cursor_x = second.end;
+        self.editor_rows.dirty += 1;
+    }
+
+    /// Inserts a tab, or spaces up to the next tab stop with `soft_tabs`.
+    fn insert_tab(&mut self) {
+        if !self.editor_rows.soft_tabs {
+            return self.insert_char('\t');
+        }
+        let tab_stop = self.editor_rows.tab_stop;
+        let render_x = self
+            .editor_rows
+            .get_editor_row(self.cursor_controller.cursor_y)
+            .get_render_x(self.cursor_controller.cursor_x, tab_stop);
+        (0..tab_stop - render_x % tab_stop).for_each(|_| self.insert_char(' '));
+    }
+
+    /// Leading whitespace of row `at`, for a new row next to it to start
+    /// with. Empty unless `auto_indent` is set.
+    fn indent_of(&self, at: usize) -> String {
+        if !self.config.auto_indent {
+            return String::new();
+        }
+        let row = self.editor_rows.get_row(at);
+        row[..row.len() - row.trim_start_matches([' ', '\t']).len()].to_string()
+    }
+
+    /// Moves the cursor to where Insert mode entered with `entry` starts,
+    /// opening a row for `o` and `O`.
+    fn move_to_insert(&mut self, entry: char) {
+        let cursor = &mut self.cursor_controller;
+        let row = self.editor_rows.get_editor_row(cursor.cursor_y);
+        match entry {
+            'a' => cursor.cursor_x = row.next_boundary(cursor.cursor_x),
+            'I' => {
+                let row = &row.row_content;
+                cursor.cursor_x = row.len() - row.trim_start_matches([' ', '\t']).len();
+            }
+            'A' => cursor.cursor_x = row.row_content.len(),
+            'o' | 'O' => self.open_row(entry == 'o'),
+            _ => {}
+        }
+    }
+
+    /// Opens a row below the cursor's, or above it, and moves there.
+    fn open_row(&mut self, below: bool) {
+        if !self.editable() {
+            return;
+        }
+        let indent = self.indent_of(self.cursor_controller.cursor_y);
+        let at = self.cursor_controller.cursor_y + below as usize;
+        self.cursor_controller.cursor_x = indent.len();
+        self.editor_rows.insert_row(at, indent);
+        self.cursor_controller.cursor_y = at;
+        self.editor_rows.dirty += 1;
+    }
+
+    /// Without modal editing, leaving a mode goes back to Insert mode,
+    /// where keys type.
+    fn set_mode(&mut self, mode: Mode) {
+        let mode = if mode == Mode::Normal && !self.config.modal { Mode::Insert } else { mode };
+        self.selection_anchor = (mode == Mode::Visual).then_some((
+            self.cursor_controller.cursor_y,
+            self.cursor_controller.cursor_x,
+        ));
+        for cursor in &mut self.extra_cursors {
+            cursor.anchor = (mode == Mode::Visual).then_some(cursor.pos);
+        }
+        self.auto_closed = 0;
+        self.mode = mode;
+    }
+
+    /// Ends the step to undo being recorded, so the next change starts
+    /// another, unless changes are being grouped with `undo_grouped`.
+    fn seal_undo(&mut self) {
+        if !self.undo_grouped {
+            let cursor = self.cursor();
+            self.editor_rows.undo.seal(cursor);
+        }
+    }
+
+    /// Runs `edit` as a step to undo of its own, apart from any changes
+    /// made before or after it, as in Insert mode.
+    fn undo_step<T>(&mut self, edit: impl FnOnce(&mut Self) -> T) -> T {
+        self.seal_undo();
+        let result = edit(self);
+        self.seal_undo();
+        result
+    }
+
+    /// Moves `(row, col)` back inside the buffer, onto a char boundary.
+    fn clamp_pos(&self, (row, col): (usize, usize)) -> (usize, usize) {
+        let row = row.min(self.editor_rows.number_of_rows() - 1);
+        (row, self.editor_rows.get_editor_row(row).snap(col))
+    }
+
+    /// `(row, byte column)` counted back from the end of the buffer, which
+    /// edits before it leave alone.
+    fn offset_from_end(&self, (row, col): (usize, usize)) -> (usize, usize) {
+        let rows = self.editor_rows.number_of_rows();
+        (rows - 1 - row, self.editor_rows.get_row(row).len() - col)
+    }
+
+    fn pos_from_end_offset(&self, (rows_after, cols_after): (usize, usize)) -> (usize, usize) {
+        let row = (self.editor_rows.number_of_rows() - 1).saturating_sub(rows_after);
+        let len = self.editor_rows.get_row(row).len();
+        self.clamp_pos((row, len.saturating_sub(cols_after)))
+    }
+
+    /// Runs `edit` with each cursor in turn as the main one, starting from
+    /// the end of the buffer so the cursors still to go stay put. Cursors
+    /// that end up in the same place merge, and the changes made at all of
+    /// them are undone as one step.
+    fn at_each_cursor(&mut self, mut edit: impl FnMut(&mut Self)) {
+        if self.extra_cursors.is_empty() {
+            return edit(self);
+        }
+        let grouped = std::mem::replace(&mut self.undo_grouped, true);
+        let main = ExtraCursor {
+            pos: self.cursor(),
+            anchor: self.selection_anchor,
+        };
+        let mut cursors: Vec<(ExtraCursor, bool)> = self
+            .extra_cursors
+            .drain(..)
+            .map(|cursor| (cursor, false))
+            .chain(std::iter::once((main, true)))
+            .collect();
+        cursors.sort_by_key(|(cursor, _)| {
+            cmp::Reverse(cursor.anchor.map_or(cursor.pos, |anchor| anchor.min(cursor.pos)))
+        });
+        let mode = self.mode;
+        let mut main_mode = mode;
+        let mut done = Vec::new();
+        for (cursor, is_main) in cursors {
+            let (row, col) = self.clamp_pos(cursor.pos);
+            (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x) = (row, col);
+            self.selection_anchor = cursor.anchor;
+            self.mode = mode;
+            edit(self);
+            if is_main {
+                main_mode = self.mode;
+            }
+            let anchor = self
+                .selection_anchor
+                .map(|anchor| self.offset_from_end(self.clamp_pos(anchor)));
+            done.push((self.offset_from_end(self.cursor()), anchor, is_main));
+        }
+        self.mode = main_mode;
+        let (main, _, _) = *done.iter().find(|(_, _, is_main)| *is_main).unwrap();
+        for (pos, anchor, is_main) in done {
+            let cursor = ExtraCursor {
+                pos: self.pos_from_end_offset(pos),
+                anchor: anchor.map(|anchor| self.pos_from_end_offset(anchor)),
+            };
+            if is_main {
+                (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x) = cursor.pos;
+                self.selection_anchor = cursor.anchor;
+            } else if pos != main && !self.extra_cursors.contains(&cursor) {
+                self.extra_cursors.push(cursor);
+            }
+        }
+        self.undo_grouped = grouped;
+    }
+
+    /// Selects the word under the cursor, or with text already selected,
+    /// adds a cursor selecting the next place that text occurs.
+    fn select_next_occurrence(&mut self) {
+        let Some((start, end)) = self.selection() else {
+            let (row, col) = self.cursor();
+            let on_word = self.editor_rows.get_row(row)[col..]
+                .chars()
+                .next()
+                .is_some_and(word_motion::is_word_char);
+            if !on_word {
+                self.status_message.set_message("No word under the cursor".into());
+                return;
+            }
+            let word = TextObject::Word { big: false };
+            let Some(((_, start), (_, end), _)) =
+                text_object::range(&self.editor_rows, (row, col), word, true)
+            else {
+                return;
+            };
+            self.cursor_controller.cursor_x = start;
+            self.start_selection();
+            self.cursor_controller.cursor_x = self.selection_end(row, end);
+            return;
+        };
+        if start.0 != end.0 {
+            self.status_message
+                .set_message("Only text on one row can be looked for".into());
+            return;
+        }
+        let text = self.editor_rows.get_row(start.0)[start.1..end.1].to_string();
+        let selections: Vec<_> = self
+            .extra_cursors
+            .iter()
+            .filter_map(|cursor| self.selection_between(cursor.anchor?, cursor.pos))
+            .collect();
+        let taken = |pos| pos == start || selections.iter().any(|&(other, _)| other == pos);
+        // Look on from the cursor added last, wrapping around the buffer.
+        let (from_row, from_col) = selections.last().map_or(end, |&(_, end)| end);
+        let rows = self.editor_rows.number_of_rows();
+        let found = (0..=rows).find_map(|step| {
+            let row = (from_row + step) % rows;
+            let line = self.editor_rows.get_row(row);
+            line.match_indices(text.as_str())
+                .map(|(col, _)| (row, col))
+                .filter(|&(_, col)| step > 0 || col >= from_col)
+                .find(|&pos| !taken(pos))
+        });
+        let Some((row, col)) = found else {
+            self.status_message.set_message("No more occurrences".into());
+            return;
+        };
+        self.extra_cursors.push(ExtraCursor {
+            pos: (row, self.selection_end(row, col + text.len())),
+            anchor: Some((row, col)),
+        });
+        self.status_message
+            .set_message(format!("{} cursors", self.extra_cursors.len() + 1));
+    }
+
+    /// Turns an Alt+drag into a block selection from where it started to
+    /// screen cell `(column, row)`, in place of the cursor the Alt+Click
+    /// added.
+    fn drag_block(&mut self, column: usize, row: usize) {
+        let Some(start) = self.drag_start else {
+            return;
+        };
+        let Some((index, rect)) = self.pane_at(column, row) else {
+            return;
+        };
+        if index != self.focus {
+            return;
+        }
+        if self.block.is_none() {
+            self.start_block();
+            self.block = Some(Block::at(start));
+        }
+        let corner = self.text_at(column, row, rect);
+        self.set_block_corner(corner);
+    }
+
+    /// Drops other kinds of selection and extra cursors, for a block
+    /// selection to take over.
+    fn start_block(&mut self) {
+        if self.mode == Mode::Visual {
+            self.set_mode(Mode::Normal);
+        }
+        self.selection_anchor = None;
+        self.extra_cursors.clear();
+    }
+
+    /// Moves the corner of the block selection a row or column by `step`,
+    /// starting a block at the cursor if there is none.
+    fn move_block_corner(&mut self, step: Step) {
+        if self.block.is_none() {
+            self.start_block();
+            let (row, col) = self.cursor();
+            let render_x = self
+                .editor_rows
+                .get_editor_row(row)
+                .get_render_x(col, self.editor_rows.tab_stop);
+            self.block = Some(Block::at((row, render_x)));
+        }
+        let (row, column) = self.block.unwrap().corner;
+        let corner = match step {
+            Step::Up => (row.saturating_sub(1), column),
+            Step::Down => ((row + 1).min(self.editor_rows.number_of_rows() - 1), column),
+            Step::Left => (row, column.saturating_sub(1)),
+            Step::Right => (row, column + 1),
+        };
+        self.set_block_corner(corner);
+    }
+
+    /// Moves the block's corner to `corner` and the cursor with it, as far
+    /// as the row allows.
+    fn set_block_corner(&mut self, corner: (usize, usize)) {
+        let Some(block) = &mut self.block else {
+            return;
+        };
+        block.corner = corner;
+        let row = self.editor_rows.get_editor_row(corner.0);
+        self.cursor_controller.cursor_y = corner.0;
+        self.cursor_controller.cursor_x =
+            row.byte_range(corner.1..corner.1, self.editor_rows.tab_stop).start;
+    }
+
+    /// Drops the block selection, leaving the cursor at its top left.
+    fn end_block(&mut self) {
+        let Some(block) = self.block.take() else {
+            return;
+        };
+        let (top, column) = (*block.rows().start(), block.columns().start);
+        let row = self.editor_rows.get_editor_row(top);
+        self.cursor_controller.cursor_y = top;
+        self.cursor_controller.cursor_x =
+            row.byte_range(column..column, self.editor_rows.tab_stop).start;
+    }
+
+    /// Copies the block's text to the clipboard, one line per row.
+    fn copy_block(&mut self) {
+        let Some(block) = self.block.filter(|block| !block.columns().is_empty()) else {
+            self.status_message.set_message("Nothing selected".into());
+            return;
+        };
+        let tab_stop = self.editor_rows.tab_stop;
+        let text = block
+            .rows()
+            .map(|row| {
+                let row = self.editor_rows.get_editor_row(row);
+                &row.row_content[row.byte_range(block.columns(), tab_stop)]
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.set_clipboard(Register {
+            text,
+            kind: RegisterKind::Block,
+        });
+    }
+
+    /// Deletes the block's text from each of its rows, leaving it zero
+    /// columns wide.
+    fn delete_block(&mut self) {
+        let Some(mut block) = self.block.filter(|block| !block.columns().is_empty()) else {
+            return;
+        };
+        if !self.editable() {
+            return;
+        }
+        let tab_stop = self.editor_rows.tab_stop;
+        for row in block.rows() {
+            let row = self.editor_rows.get_editor_row_mut(row);
+            let range = row.byte_range(block.columns(), tab_stop);
+            row.row_content.replace_range(range, "");
+            row.update_render(tab_stop);
+        }
+        block.collapse_to(block.columns().start);
+        self.block = Some(block);
+        self.set_block_corner(block.corner);
+        self.editor_rows.dirty += 1;
+    }
+
+    /// Types `ch` in place of the block's text on each of its rows, padding
+    /// short rows with spaces up to the block.
+    fn insert_at_block(&mut self, ch: char) {
+        if !self.editable() {
+            return;
+        }
+        self.delete_block();
+        let Some(mut block) = self.block else {
+            return;
+        };
+        let column = block.columns().start;
+        let tab_stop = self.editor_rows.tab_stop;
+        let mut end = column;
+        for row in block.rows() {
+            let row = self.editor_rows.get_editor_row_mut(row);
+            row.pad_to(column, tab_stop);
+            let at = row.byte_range(column..column, tab_stop).start;
+            row.insert_char(at, ch, tab_stop);
+            end = row.get_render_x(at + ch.len_utf8(), tab_stop);
+        }
+        block.collapse_to(end);
+        self.block = Some(block);
+        self.set_block_corner(block.corner);
+        self.editor_rows.dirty += 1;
+    }
+
+    /// Deletes the block's text, or if it is zero columns wide, the char
+    /// before it (or with `forward`, after it) on each of its rows.
+    fn delete_at_block(&mut self, forward: bool) {
+        let Some(mut block) = self.block else {
+            return;
+        };
+        if !block.columns().is_empty() {
+            return self.delete_block();
+        }
+        if !self.editable() {
+            return;
+        }
+        let column = block.columns().start;
+        if !forward && column == 0 {
+            return;
+        }
+        let tab_stop = self.editor_rows.tab_stop;
+        let target = if forward { column } else { column - 1 };
+        let mut end = column;
+        for row in block.rows() {
+            let row = self.editor_rows.get_editor_row_mut(row);
+            let at = row.get_row_x(target, tab_stop);
+            if at < row.row_content.len() {
+                end = end.min(row.get_render_x(at, tab_stop));
+                row.row_content.remove(at);
+                row.update_render(tab_stop);
+            }
+        }
+        if !forward {
+            block.collapse_to(end.min(column - 1));
+        }
+        self.block = Some(block);
+        self.set_block_corner(block.corner);
+        self.editor_rows.dirty += 1;
+    }
+
+    /// Pastes a block from the clipboard with its top left at the cursor,
+    /// one line per row, adding rows at the end of the buffer as needed.
+    /// Lines are padded so text to their right stays in line.
+    fn paste_block(&mut self) {
+        let tab_stop = self.editor_rows.tab_stop;
+        let (cursor_y, cursor_x) = self.cursor();
+        let column = self
+            .editor_rows
+            .get_editor_row(cursor_y)
+            .get_render_x(cursor_x, tab_stop);
+        let lines: Vec<&str> = self.clipboard.text.split('\n').collect();
+        let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        for (offset, line) in lines.into_iter().enumerate() {
+            let at = cursor_y + offset;
+            if at == self.editor_rows.number_of_rows() {
+                self.editor_rows.insert_row(at, String::new());
+            }
+            let row = self.editor_rows.get_editor_row_mut(at);
+            row.pad_to(column, tab_stop);
+            let byte = row.byte_range(column..column, tab_stop).start;
+            let padding = if byte < row.row_content.len() {
+                width - line.chars().count()
+            } else {
+                0
+            };
+            let text = format!("{}{}", line, " ".repeat(padding));
+            self.editor_rows.insert_text((at, byte), &text);
+        }
+        self.editor_rows.dirty += 1;
+    }
+
+    /// Where the cursor goes to select up to byte `end` of `row`: on the
+    /// last char in Visual mode, which selects the char under the cursor.
+    fn selection_end(&self, row: usize, end: usize) -> usize {
+        if self.mode == Mode::Visual {
+            self.editor_rows.get_editor_row(row).prev_boundary(end)
+        } else {
+            end
+        }
+    }
+
+    /// The pane shown at screen cell `(column, row)`, and where it is.
+    fn pane_at(&mut self, column: usize, row: usize) -> Option<(usize, Rect)> {
+        self.pane_rects()
+            .into_iter()
+            .enumerate()
+            .find(|(_, rect)| rect.contains(column, row))
+    }
+
+    /// The `(row, render column)` of the focused pane's text at screen cell
+    /// `(column, row)`, in a pane at `rect`.
+    fn text_at(&self, column: usize, row: usize, rect: Rect) -> (usize, usize) {
+        let lines = self.display_lines(self.cursor_controller.screen_rows);
+        // Below the end of the buffer counts as its last row.
+        let (file_row, columns) = lines.get(row - rect.top).or(lines.last()).cloned().unwrap();
+        let column = (column - rect.left).saturating_sub(self.gutter_width());
+        (file_row, columns.start + column)
+    }
+
+    /// Moves the cursor to the text at screen cell `(column, row)`, focusing
+    /// the pane it is in. With `add`, a cursor is added there instead, in
+    /// the focused pane only.
+    fn click(&mut self, column: usize, row: usize, add: bool) {
+        self.block = None;
+        let Some((index, rect)) = self.pane_at(column, row) else {
+            return;
+        };
+        if add && index != self.focus {
+            return;
+        }
+        self.cycle_focus(index as isize - self.focus as isize);
+        let (file_row, render_x) = self.text_at(column, row, rect);
+        self.drag_start = add.then_some((file_row, render_x));
+        let col = self
+            .editor_rows
+            .get_editor_row(file_row)
+            .get_row_x(render_x, self.editor_rows.tab_stop);
+        let pos = (file_row, col);
+        if !add {
+            self.extra_cursors.clear();
+            if self.mode == Mode::Visual {
+                self.set_mode(Mode::Normal);
+            }
+            self.selection_anchor = None;
+            (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x) = pos;
+        } else if pos != self.cursor() && self.extra_cursors.iter().all(|cursor| cursor.pos != pos) {
+            let anchor = (self.mode == Mode::Visual).then_some(pos);
+            self.extra_cursors.push(ExtraCursor { pos, anchor });
+        }
+    }
+
+    /// Undoes up to `count` steps, or redoes them with `redo`, leaving the
+    /// cursor where it was before the last one. Returns how many there were.
+    fn undo(&mut self, count: usize, redo: bool) -> usize {
+        let mut done = 0;
+        while done < count {
+            let cursor = if redo {
+                self.editor_rows.redo()
+            } else {
+                self.editor_rows.undo()
+            };
+            let Some(cursor) = cursor else {
+                break;
+            };
+            let (row, col) = self.clamp_pos(cursor);
+            self.cursor_controller.cursor_y = row;
+            self.cursor_controller.cursor_x = col;
+            done += 1;
+        }
+        if done > 0 {
+            self.extra_cursors.clear();
+            if self.mode == Mode::Visual {
+                self.set_mode(Mode::Normal);
+            }
+        }
+        done
+    }
+
+    /// The cursor as `(row, byte column)`.
+    fn cursor(&self) -> (usize, usize) {
+        (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x)
+    }
+
+    /// The cursor position, for the jump list.
+    fn jump_here(&self) -> Jump {
+        Jump {
+            buffer: self.editor_rows.id,
+            row: self.cursor_controller.cursor_y,
+            col: self.cursor_controller.cursor_x,
+        }
+    }
+
+    /// Moves the cursor to `jump`, or as close as the rows left allow,
+    /// showing its buffer if it is another one. Returns `false` if that
+    /// buffer has been closed.
+    fn jump_to(&mut self, jump: Jump) -> bool {
+        if !self.buffer_order.contains(&jump.buffer) {
+            return false;
+        }
+        self.show_buffer(jump.buffer);
+        let row = jump.row.min(self.editor_rows.number_of_rows() - 1);
+        self.cursor_controller.cursor_y = row;
+        self.cursor_controller.cursor_x = self.editor_rows.get_editor_row(row).snap(jump.col);
+        true
+    }
+
+    /// Starts selecting at the cursor unless a selection is already active.
+    /// Normal mode becomes Visual mode.
+    fn start_selection(&mut self) {
+        if self.mode == Mode::Normal {
+            self.set_mode(Mode::Visual);
+        } else if self.selection_anchor.is_none() {
+            self.selection_anchor =
+                Some((self.cursor_controller.cursor_y, self.cursor_controller.cursor_x));
+        }
+    }
+
+    /// Moves the cursor to the start of its row, or with `end` past its
+    /// last char. Outside Visual mode the selection is dropped.
+    fn move_to_row_edge(&mut self, end: bool) {
+        if self.mode != Mode::Visual {
+            self.selection_anchor = None;
+        }
+        self.cursor_controller.cursor_x = if end {
+            self.editor_rows.get_row(self.cursor_controller.cursor_y).len()
+        } else {
+            0
+        };
+    }
+
+    fn select_all(&mut self) {
+        self.select_rows(0..=self.editor_rows.number_of_rows() - 1);
+    }
+
+    /// Selects rows `rows` whole, leaving the cursor at the end.
+    fn select_rows(&mut self, rows: RangeInclusive<usize>) {
+        let (first, last) = rows.into_inner();
+        self.selection_anchor = None;
+        self.cursor_controller.cursor_y = first;
+        self.cursor_controller.cursor_x = 0;
+        self.start_selection();
+        self.cursor_controller.cursor_y = last;
+        self.cursor_controller.cursor_x = self.editor_rows.get_row(last).len();
+    }
+
+    /// The selected text as `(row, byte column)` bounds, start inclusive and
+    /// end exclusive, or `None` if nothing is selected. In Visual mode the
+    /// char under the cursor is part of the selection.
+    fn selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        self.selection_between(self.selection_anchor?, self.cursor())
+    }
+
+    /// Comments out the selected rows, or `count` rows from the cursor's,
+    /// or uncomments them if they all are already.
+    fn toggle_comment(&mut self, count: usize) {
+        let Some(token) = self.editor_rows.comment_token() else {
+            self.status_message
+                .set_message("No line comments known for this file type".into());
+            return;
+        };
+        let cursor = self.cursor();
+        let rows = match self.selection() {
+            // A selection ending at the start of a row leaves it out.
+            Some((start, end)) if end.1 == 0 && end.0 > start.0 => start.0..=end.0 - 1,
+            Some((start, end)) => start.0..=end.0,
+            None => {
+                let last = self.editor_rows.number_of_rows() - 1;
+                cursor.0..=(cursor.0 + count - 1).min(last)
+            }
+        };
+        if self.editable() {
+            self.editor_rows.toggle_comment(rows, token);
+            self.clamp_cursor();
+        }
+    }
+
+    /// Indents the rows of a selection spanning several, or with `outdent`
+    /// dedents them or else the cursor's row, for Tab and Shift-Tab. The
+    /// selection stays, moving with the text. A selection ending at the
+    /// start of a row leaves that row alone. Returns `false`, doing
+    /// nothing, if Tab should insert as usual.
+    fn indent_selection(&mut self, outdent: bool) -> bool {
+        let cursor = self.cursor();
+        let rows = match self.selection() {
+            Some((start, end)) if start.0 != end.0 => {
+                start.0..=if end.1 == 0 { end.0 - 1 } else { end.0 }
+            }
+            _ if outdent => cursor.0..=cursor.0,
+            _ => return false,
+        };
+        if !self.editable() {
+            return true;
+        }
+        let ends: Vec<_> = self.selection_anchor.into_iter().chain([cursor]).collect();
+        let lens: Vec<_> = ends.iter().map(|&(row, _)| self.editor_rows.get_row(row).len()).collect();
+        self.editor_rows.indent_rows(rows.clone(), outdent);
+        let moved: Vec<_> = ends
+            .into_iter()
+            .zip(lens)
+            .map(|((row, col), len)| {
+                if !rows.contains(&row) {
+                    return (row, col);
+                }
+                let new_len = self.editor_rows.get_row(row).len();
+                (row, (col + new_len).saturating_sub(len).min(new_len))
+            })
+            .collect();
+        if let [anchor, _] = moved[..] {
+            self.selection_anchor = Some(anchor);
+        }
+        (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x) = moved[moved.len() - 1];
+        true
+    }
+
+    /// The text selected from `anchor` to `cursor`, bounded as in
+    /// [`Output::selection`].
+    fn selection_between(
+        &self,
+        anchor: (usize, usize),
+        cursor: (usize, usize),
+    ) -> Option<((usize, usize), (usize, usize))> {
+        // Rows may have been deleted from under the anchor.
+        let anchor = self.clamp_pos(anchor);
+        let (start, end) = if anchor <= cursor {
+            (anchor, cursor)
+        } else {
+            (cursor, anchor)
+        };
+        if self.mode == Mode::Visual {
+            let end_row = self.editor_rows.get_editor_row(end.0);
+            return Some((start, (end.0, end_row.next_boundary(end.1))));
+        }
+        (start != end).then_some((start, end))
+    }
+
+    /// Copies the selected text to the clipboard. Visual mode ends, as with
+    /// a yank. Returns whether there was a selection.
+    fn copy_selection(&mut self) -> bool {
+        let Some((start, end)) = self.selection() else {
+            self.status_message.set_message("Nothing selected".into());
+            return false;
+        };
+        self.set_clipboard(Register {
+            text: self.editor_rows.text_range(start, end),
+            kind: RegisterKind::Chars,
+        });
+        if self.mode == Mode::Visual {
+            self.set_mode(Mode::Normal);
+        }
+        true
+    }
+
+    /// Moves the selected text to the clipboard.
+    fn cut_selection(&mut self) {
+        if !self.editable() {
+            return;
+        }
+        let Some((start, end)) = self.selection() else {
+            self.status_message.set_message("Nothing selected".into());
+            return;
+        };
+        self.set_clipboard(Register {
+            text: self.editor_rows.text_range(start, end),
+            kind: RegisterKind::Chars,
+        });
+        self.delete_selection();
+    }
+
+    /// Fills the clipboard, also handing its text to the system clipboard
+    /// with `osc52_clipboard`.
+    fn set_clipboard(&mut self, register: Register) {
+        if self.config.osc52_clipboard {
+            let mut text = register.text.clone();
+            if register.kind == RegisterKind::Lines {
+                text.push('\n');
+            }
+            self.editor_contents.push_str(&osc52::copy_sequence(&text));
+        }
+        self.clipboard = register;
+    }
+
+    /// Inserts the clipboard at the cursor, replacing the selection or the
+    /// block selection. Whole rows go below the cursor's row instead.
+    fn paste(&mut self) {
+        if !self.editable() {
+            return;
+        }
+        self.delete_selection();
+        // Paste in place of the block, from its top left corner.
+        self.delete_block();
+        self.end_block();
+        let cursor_y = self.cursor_controller.cursor_y;
+        if self.clipboard.kind == RegisterKind::Block {
+            self.paste_block();
+        } else if self.clipboard.kind == RegisterKind::Lines {
+            for (at, line) in self.clipboard.text.split('\n').enumerate() {
+                self.editor_rows.insert_row(cursor_y + 1 + at, line.to_string());
+            }
+            self.cursor_controller.cursor_y += 1;
+            self.cursor_controller.cursor_x = 0;
+        } else {
+            let cursor = (cursor_y, self.cursor_controller.cursor_x);
+            (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x) =
+                self.editor_rows.insert_text(cursor, &self.clipboard.text);
+        }
+        self.editor_rows.dirty += 1;
+    }
+
+    /// `p` and `P`: pastes rows below or above the cursor's row, and other
+    /// text after or before the char under the cursor.
+    fn put(&mut self, after: bool) {
+        if !self.editable() {
+            return;
+        }
+        let (cursor_y, cursor_x) = self.cursor();
+        match self.clipboard.kind {
+            RegisterKind::Lines if !after => {
+                for (at, line) in self.clipboard.text.split('\n').enumerate() {
+                    self.editor_rows.insert_row(cursor_y + at, line.to_string());
+                }
+                self.cursor_controller.cursor_x = 0;
+                self.editor_rows.dirty += 1;
+            }
+            RegisterKind::Lines => self.paste(),
+            _ => {
+                let row = self.editor_rows.get_editor_row(cursor_y);
+                if after && cursor_x < row.row_content.len() {
+                    self.cursor_controller.cursor_x = row.next_boundary(cursor_x);
+                }
+                self.paste()
+            }
+        }
+    }
+
+    /// Applies `op` to the text from `start` up to but not including `end`.
+    /// Indenting acts on all the rows it touches.
+    fn operate_on_text(&mut self, op: Op, start: (usize, usize), end: (usize, usize)) {
+        if let Op::Indent | Op::Outdent = op {
+            return self.operate_on_rows(op, start.0, end.0);
+        }
+        if let Op::Surround(key) = op {
+            return self.surround_text(key, start, end);
+        }
+        if op != Op::Yank && !self.editable() {
+            return self.set_mode(Mode::Normal);
+        }
+        self.set_clipboard(Register {
+            text: self.editor_rows.text_range(start, end),
+            kind: RegisterKind::Chars,
+        });
+        if op != Op::Yank && start != end {
+            self.editor_rows.delete_range(start, end);
+            self.editor_rows.dirty += 1;
+        }
+        (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x) = start;
+        self.set_mode(if op == Op::Change { Mode::Insert } else { Mode::Normal });
+    }
+
+    /// Puts the delimiters `key` stands for around the text from `start` to
+    /// `end`, leaving the cursor on the opening one.
+    fn surround_text(&mut self, key: Option<char>, start: (usize, usize), end: (usize, usize)) {
+        self.set_mode(Mode::Normal);
+        let Some((open, close)) = key.and_then(surround::delimiters) else {
+            return;
+        };
+        if !self.editable() {
+            return;
+        }
+        self.editor_rows
+            .surround_region(start.0, start.1, end.0, end.1, &open, &close);
+        self.editor_rows.dirty += 1;
+        (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x) = start;
+    }
+
+    /// Replaces the delimiters `old` stands for around the cursor with the
+    /// ones for `new`, or deletes them without `new`, for `cs` and `ds`. An
+    /// opening bracket for `old` takes the whitespace inside it too.
+    /// Returns `false` if there are none.
+    fn change_surround(&mut self, old: char, new: Option<char>) -> bool {
+        let Some(object) = TextObject::for_key(old) else {
+            return false;
+        };
+        let rows = &self.editor_rows;
+        let Some((start, end)) = text_object::delimiters(rows, self.cursor(), object) else {
+            return false;
+        };
+        let (open, close) = match new.map(surround::delimiters) {
+            Some(Some(delimiters)) => delimiters,
+            Some(None) => return false,
+            None => Default::default(),
+        };
+        let char_len = |(row, col): (usize, usize)| {
+            rows.get_row(row)[col..].chars().next().map_or(0, char::len_utf8)
+        };
+        let mut open_end = start.1 + char_len(start);
+        let mut close_start = end.1;
+        if "([{".contains(old) {
+            let line = rows.get_row(start.0);
+            open_end = line.len() - line[open_end..].trim_start_matches([' ', '\t']).len();
+            let line = rows.get_row(end.0);
+            close_start = line[..close_start].trim_end_matches([' ', '\t']).len();
+            if start.0 == end.0 {
+                open_end = open_end.min(end.1);
+                close_start = close_start.max(open_end);
+            }
+        }
+        let close_end = end.1 + char_len(end);
+        if !self.editable() {
+            return false;
+        }
+        self.editor_rows.replace_surround(
+            (start.0, start.1..open_end),
+            (end.0, close_start..close_end),
+            &open,
+            &close,
+        );
+        self.editor_rows.dirty += 1;
+        (self.cursor_controller.cursor_y, self.cursor_controller.cursor_x) = start;
+        true
+    }
+
+    /// Applies `op` to rows `first` to `last`. Changing rows leaves one row
+    /// holding just the first row's indentation.
+    fn operate_on_rows(&mut self, op: Op, first: usize, last: usize) {
+        if op != Op::Yank && !self.editable() {
+            return self.set_mode(Mode::Normal);
+        }
+        let last_len = self.editor_rows.get_row(last).len();
+        if let Op::Yank | Op::Delete | Op::Change = op {
+            self.set_clipboard(Register {
+                text: self.editor_rows.text_range((first, 0), (last, last_len)),
+                kind: RegisterKind::Lines,
+            });
+        }
+        self.cursor_controller.cursor_y = first;
+        match op {
+            Op::Yank => {
+                let row = self.editor_rows.get_editor_row(first);
+                self.cursor_controller.cursor_x = row.snap(self.cursor_controller.cursor_x);
+            }
+            Op::Delete => self.delete_lines(last - first + 1),
+            Op::Indent | Op::Outdent => {
+                self.editor_rows.indent_rows(first..=last, op == Op::Outdent);
+                // As in vim, the cursor goes to the first non-blank.
+                let row = self.editor_rows.get_row(first);
+                self.cursor_controller.cursor_x = row.len() - row.trim_start_matches([' ', '\t']).len();
+            }
+            Op::Change => {
+                let indent = self.indent_of(first);
+                self.editor_rows.delete_range((first, 0), (last, last_len));
+                self.cursor_controller.cursor_x = indent.len();
+                self.editor_rows.insert_text((first, 0), &indent);
+                self.editor_rows.dirty += 1;
+            }
+            Op::Surround(key) => {
+                // Rows are surrounded from their indentation on.
+                let row = self.editor_rows.get_row(first);
+                let start = row.len() - row.trim_start_matches([' ', '\t']).len();
+                let end = self.editor_rows.get_row(last).len();
+                return self.surround_text(key, (first, start), (last, end));
+            }
+        }
+        self.set_mode(if op == Op::Change { Mode::Insert } else { Mode::Normal });
+    }
+
+    /// Deletes the selected text, if any, leaving Visual mode. Returns
+    /// whether there was a selection.
+    fn delete_selection(&mut self) -> bool {
+        let Some((start, end)) = self.selection() else {
+            return false;
+        };
+        if !self.editable() {
+            return true;
+        }
+        self.editor_rows.delete_range(start, end);
+        self.cursor_controller.cursor_y = start.0;
+        self.cursor_controller.cursor_x = start.1;
+        self.editor_rows.dirty += 1;
+        self.selection_anchor = None;
+        if self.mode == Mode::Visual {
+            self.mode = Mode::Normal;
+        }
+        true
+    }
+}
+
+#[derive(Default)]
+struct Reader {
+    /// Keys queued by macro replay, read before any terminal input.
+    pending: VecDeque<KeyEvent>,
+    macro_recording: bool,
+    /// Terminal keys read while `macro_recording` is set.
+    macro_buffer: Vec<KeyEvent>,
+    /// Keys tests type, read in place of the terminal's.
+    #[cfg(test)]
+    typed: VecDeque<KeyEvent>,
+}
+
+impl Reader {
+    /// Waits up to `timeout` for input without consuming it.
+    fn key_ready(&self, timeout: Duration) -> std::result::Result<bool, std::io::Error> {
+        if !self.pending.is_empty() {
+            return Ok(true);
+        }
+        event::poll(timeout)
+    }
+
+    fn read_key(&mut self) -> std::result::Result<KeyEvent, std::io::Error> {
+        loop {
+            if let Input::Key(key) = self.read_input()? {
+                return Ok(key);
+            }
+        }
+    }
+
+    /// Reads a key, or a click or drag of the left mouse button. The mouse
+    /// is not recorded in macros.
+    fn read_input(&mut self) -> std::result::Result<Input, std::io::Error> {
+        if let Some(key) = self.pending.pop_front() {
+            return Ok(Input::Key(key));
+        }
+        loop {
+            if let Some(event) = self.next_event()? {
+                match event {
+                    Event::Key(event) => {
+                        if self.macro_recording {
+                            self.macro_buffer.push(event);
+                        }
+                        return Ok(Input::Key(event));
+                    }
+                    Event::Mouse(
+                        event @ MouseEvent {
+                            kind:
+                                MouseEventKind::Down(MouseButton::Left)
+                                | MouseEventKind::Drag(MouseButton::Left)
+                                | MouseEventKind::ScrollUp
+                                | MouseEventKind::ScrollDown,
+                            ..
+                        },
+                    ) => return Ok(Input::Mouse(event)),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// The terminal's next event, if one comes within half a second.
+    #[cfg(not(test))]
+    fn next_event(&mut self) -> std::result::Result<Option<Event>, std::io::Error> {
+        event::poll(Duration::from_millis(500))?.then(event::read).transpose()
+    }
+
+    #[cfg(test)]
+    fn next_event(&mut self) -> std::result::Result<Option<Event>, std::io::Error> {
+        match self.typed.pop_front() {
+            Some(key) => Ok(Some(Event::Key(key))),
+            None => Err(io::Error::other("no more keys typed")),
+        }
+    }
+
+    /// Queues `keys` to be read next, ahead of anything already queued.
+    fn replay(&mut self, keys: &[KeyEvent]) {
+        keys.iter().rev().for_each(|key| self.pending.push_front(*key));
+    }
+}
+
+enum Input {
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum HighlightType {
+    Normal,
+    TrailingWhitespace,
+    Match,
+    CurrentMatch,
+    Selection,
+    /// An extra cursor, drawn reversed.
+    Cursor,
+    /// A control char, in caret notation.
+    Control,
+    /// Indentation with a space before a tab.
+    MixedIndent,
+    /// Whitespace made visible with `show_whitespace`.
+    Whitespace,
+}
+
+impl HighlightType {
+    fn to_color(self) -> Color {
+        match self {
+            HighlightType::Normal => Color::Reset,
+            HighlightType::TrailingWhitespace => Color::DarkRed,
+            HighlightType::Match => Color::DarkYellow,
+            HighlightType::CurrentMatch => Color::DarkBlue,
+            HighlightType::Selection => Color::DarkGrey,
+            HighlightType::Cursor => Color::Reset,
+            HighlightType::Control => Color::Red,
+            HighlightType::MixedIndent => Color::DarkYellow,
+            HighlightType::Whitespace => Color::DarkGrey,
+        }
+    }
+}
+
+/// Columns of `render` `ch` takes at render column `render_x`: a tab
+/// reaches the next tab stop, and a control char shows as `^` and
+/// [`caret_char`].
+fn char_width(ch: char, render_x: usize, tab_stop: usize) -> usize {
+    if ch == '\t' {
+        tab_stop - render_x % tab_stop
+    } else if ch.is_control() {
+        2
+    } else {
+        1
+    }
+}
+
+/// The char after `^` standing for control char `ch`, as `A` for `\x01`
+/// and `?` for DEL. Control chars beyond ASCII are all `?`.
+fn caret_char(ch: char) -> char {
+    match ch {
+        '\0'..='\x7f' => char::from(ch as u8 ^ 0x40),
+        _ => '?',
+    }
+}
+
+/// The two letter names of the ASCII control chars, as RFC 1345 gives
+/// them, `DT` being DEL.
+const CONTROL_NAMES: [&str; 33] = [
+    "NU", "SH", "SX", "EX", "ET", "EQ", "AK", "BL", "BS", "HT", "LF", "VT", "FF", "CR", "SO",
+    "SI", "DL", "D1", "D2", "D3", "D4", "NK", "SY", "EB", "CN", "EM", "SB", "EC", "FS", "GS",
+    "RS", "US", "DT",
+];
+
+/// Describes `ch` as `ga` does in vim: the char, then its codepoint in
+/// decimal, hex and octal, e.g. `<A> 65, Hex 41, Octal 101`. Control
+/// chars show in caret notation with their name, and chars beyond ASCII
+/// with their UTF-8 bytes.
+fn char_info(ch: char) -> String {
+    let code = ch as u32;
+    let shown = if ch.is_control() {
+        format!("^{}", caret_char(ch))
+    } else {
+        ch.to_string()
+    };
+    let mut info = if code < 0x100 {
+        format!("<{}> {}, Hex {:02x}, Octal {:03o}", shown, code, code, code)
+    } else {
+        format!("<{}> {}, Hex {:04x}, Octal {:o}", shown, code, code, code)
+    };
+    match ch {
+        '\0'..='\x1f' => info.push_str(&format!(", {}", CONTROL_NAMES[code as usize])),
+        '\x7f' => info.push_str(&format!(", {}", CONTROL_NAMES[32])),
+        _ if !ch.is_ascii() => {
+            let mut buf = [0; 4];
+            let bytes: Vec<String> = ch
+                .encode_utf8(&mut buf)
+                .bytes()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            info.push_str(&format!(", UTF-8 {}", bytes.join(" ")));
+        }
+        _ => {}
+    }
+    info
+}
+
+#[derive(Clone)]
+struct Row {
+    row_content: String,
+    render: String,
+    /// One entry per char of `render`.
+    highlight: Vec<HighlightType>,
+    /// Whether the indentation has a space before a tab.
+    mixed_indent: bool,
+}
+
+impl Row {
+    fn new(row_content: String, tab_stop: usize) -> Self {
+        let mut row = Self {
+            row_content,
+            render: String::new(),
+            highlight: Vec::new(),
+            mixed_indent: false,
+        };
+        row.update_render(tab_stop);
+        row
+    }
+
+    fn update_render(&mut self, tab_stop: usize) {
+        self.render.clear();
+        let mut index = 0;
+        for ch in self.row_content.chars() {
+            let width = char_width(ch, index, tab_stop);
+            if ch == '\t' {
+                self.render.extend(std::iter::repeat_n(' ', width));
+            } else if ch.is_control() {
+                // Drawn as is, a control char would act on the terminal.
+                self.render.push('^');
+                self.render.push(caret_char(ch));
+            } else {
+                self.render.push(ch);
+            }
+            index += width;
+        }
+        self.update_syntax(tab_stop);
+    }
+
+    fn update_syntax(&mut self, tab_stop: usize) {
+        let render_len = self.render.chars().count();
+        self.highlight = vec![HighlightType::Normal; render_len];
+        let mut render_x = 0;
+        for ch in self.row_content.chars() {
+            let width = char_width(ch, render_x, tab_stop);
+            if ch != '\t' && ch.is_control() {
+                self.highlight[render_x..render_x + width].fill(HighlightType::Control);
+            }
+            render_x += width;
+        }
+        let trailing_start = self.row_content.trim_end_matches([' ', '\t']).len();
+        let trailing_start = self.get_render_x(trailing_start, tab_stop);
+        self.highlight[trailing_start..].fill(HighlightType::TrailingWhitespace);
+        let content = self.row_content.trim_start_matches([' ', '\t']);
+        let indent = &self.row_content[..self.row_content.len() - content.len()];
+        self.mixed_indent = indent.contains(" \t");
+    }
+
+    /// `render` with whitespace made visible: tabs start with `→`, trailing
+    /// spaces (or with `all_spaces` every space) are `·` and non-breaking
+    /// spaces `°`. Each char keeps its columns, and the highlight of the
+    /// ones changed becomes `Whitespace` where it was `Normal`.
+    fn visible_render(
+        &self,
+        highlight: &mut [HighlightType],
+        tab_stop: usize,
+        all_spaces: bool,
+    ) -> String {
+        let trailing_start = if all_spaces {
+            0
+        } else {
+            self.row_content.trim_end_matches([' ', '\t']).len()
+        };
+        let mut render: Vec<char> = self.render.chars().collect();
+        let mut render_x = 0;
+        for (at, ch) in self.row_content.char_indices() {
+            let visible = match ch {
+                '\t' => Some('→'),
+                ' ' if at >= trailing_start => Some('·'),
+                '\u{a0}' => Some('°'),
+                _ => None,
+            };
+            if let Some(visible) = visible {
+                render[render_x] = visible;
+                if highlight[render_x] == HighlightType::Normal {
+                    highlight[render_x] = HighlightType::Whitespace;
+                }
+            }
+            render_x += char_width(ch, render_x, tab_stop);
+        }
+        render.into_iter().collect()
+    }
+
+    /// Bytes of the word under `at`, or else the first one after it.
+    fn word_at(&self, at: usize) -> Option<Range<usize>> {
+        let line = &self.row_content;
+        let (found, _) = line[at..].char_indices().find(|&(_, ch)| is_word_char(ch))?;
+        let found = at + found;
+        let start = line[..found]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, ch)| is_word_char(ch))
+            .last()
+            .map_or(found, |(start, _)| start);
+        let end = line[found..]
+            .char_indices()
+            .find(|&(_, ch)| !is_word_char(ch))
+            .map_or(line.len(), |(end, _)| found + end);
+        Some(start..end)
+    }
+
+    /// Converts a byte offset into `row_content` to a column of `render`.
+    fn get_render_x(&self, cursor_x: usize, tab_stop: usize) -> usize {
+        self.row_content[..cursor_x]
+            .chars()
+            .fold(0, |render_x, ch| render_x + char_width(ch, render_x, tab_stop))
+    }
+
+    /// Converts a column of `render` to the byte offset of the char drawn
+    /// there, or the end of the row past it.
+    fn get_row_x(&self, render_x: usize, tab_stop: usize) -> usize {
+        let mut current = 0;
+        for (at, ch) in self.row_content.char_indices() {
+            current += char_width(ch, current, tab_stop);
+            if current > render_x {
+                return at;
+            }
+        }
+        self.row_content.len()
+    }
+
+    /// Bytes of the chars that start in render `columns`, empty at the end
+    /// of the row if it is shorter.
+    fn byte_range(&self, columns: Range<usize>, tab_stop: usize) -> Range<usize> {
+        let mut render_x = 0;
+        let mut start = None;
+        for (at, ch) in self.row_content.char_indices() {
+            if render_x >= columns.end {
+                return start.unwrap_or(at)..at;
+            }
+            if start.is_none() && render_x >= columns.start {
+                start = Some(at);
+            }
+            render_x += char_width(ch, render_x, tab_stop);
+        }
+        let len = self.row_content.len();
+        start.unwrap_or(len)..len
+    }
+
+    /// Appends spaces until the row reaches render column `column`.
+    fn pad_to(&mut self, column: usize, tab_stop: usize) {
+        let width = self.render.chars().count();
+        if width < column {
+            self.row_content.push_str(&" ".repeat(column - width));
+            self.update_render(tab_stop);
+        }
+    }
+
+    fn next_boundary(&self, at: usize) -> usize {
+        at + self.row_content[at..]
+            .chars()
+            .next()
+            .map_or(0, char::len_utf8)
+    }
+
+    fn prev_boundary(&self, at: usize) -> usize {
+        at - self.row_content[..at]
+            .chars()
+            .next_back()
+            .map_or(0, char::len_utf8)
+    }
+
+    /// Clamps `at` to the row and moves it back onto a char boundary.
+    fn snap(&self, at: usize) -> usize {
+        let mut at = cmp::min(at, self.row_content.len());
+        while !self.row_content.is_char_boundary(at) {
+            at -= 1;
+        }
+        at
+    }
+
+    fn insert_char(&mut self, at: usize, ch: char, tab_stop: usize) {
+        self.row_content.insert(at, ch);
+        self.update_render(tab_stop)
+    }
+
+    fn delete_char(&mut self, at: usize, tab_stop: usize) {
+        self.row_content.remove(at);
+        self.update_render(tab_stop)
+    }
+}
+
+/// Where the rows a buffer is compared with come from.
+#[derive(Copy, Clone, PartialEq)]
+enum DiffSource {
+    /// The file as last saved, as `:diff` asks for.
+    Saved,
+    /// The file as of git's `HEAD`, shown by default for files in git.
+    Head,
+    /// The other of two files `--diff` compares, `old` in the first of
+    /// them, whose rows only it has were removed rather than added.
+    File { old: bool },
+}
+
+/// Rows a buffer is compared with, for the gutter to show what changed.
+struct DiffBase {
+    source: DiffSource,
+    rows: Vec<String>,
+    hunks: Vec<Hunk>,
+    /// `EditorRows::dirty` when `hunks` were worked out, or `None` if they
+    /// are out of date.
+    computed: Option<u64>,
+}
+
+/// Source of `EditorRows::id`.
+static NEXT_BUFFER_ID: AtomicUsize = AtomicUsize::new(0);
+
+struct EditorRows {
+    /// Tells buffers apart, even ones showing the same file.
+    id: usize,
+    row_contents: Box<dyn TextBuffer>,
+    filename: Option<PathBuf>,
+    /// Whether the file ended with a newline when it was read.
+    final_newline: bool,
+    /// Reads the rest of a large file while the first rows are on screen.
+    loader: Option<Loader>,
+    tab_stop: usize,
+    soft_tabs: bool,
+    marks: Marks,
+    /// Rows folded away out of sight, under the row before them.
+    folds: Folds,
+    /// Edits since the buffer was last saved.
+    dirty: u64,
+    /// Edits to the rows, for undo and redo.
+    undo: UndoStack,
+    /// When the file was last modified as far as the buffer knows, from
+    /// reading or saving it, to tell when another program changes it.
+    disk_modified: Option<SystemTime>,
+    /// Whether the file has been copied to a backup this session.
+    backed_up: bool,
+    /// What the rows are compared with, if anything.
+    diff: Option<DiffBase>,
+    /// git reading the file as committed, to compare the rows with.
+    git: Option<HeadJob>,
+    /// The file's bytes, while it is shown in hex rather than as text.
+    hex: Option<HexView>,
+}
+
+impl EditorRows {
+    /// Reads each of `files`, leaving out those that can't be read with a
+    /// message saying why.
+    fn from_files(files: Vec<String>, config: &Config) -> (Vec<Self>, Vec<String>) {
+        let mut opened = Vec::new();
+        let mut errors = Vec::new();
+        for file in files {
+            match Self::from_file(file.as_str().into(), config) {
+                Ok(editor_rows) => opened.push(editor_rows),
+                Err(err) => errors.push(format!("Can't open {}: {}", file, err)),
+            }
+        }
+        (opened, errors)
+    }
+
+    fn empty(filename: Option<PathBuf>, config: &Config) -> Self {
+        Self::with_rows(filename, vec![String::new()], config)
+    }
+
+    /// A buffer holding `rows`, at least one, as if read from a file.
+    fn with_rows(filename: Option<PathBuf>, rows: Vec<String>, config: &Config) -> Self {
+        let mut row_contents = text_buffer::for_size(0);
+        for (at, row) in rows.into_iter().enumerate() {
+            row_contents.insert(at, Row::new(row, config.tab_stop));
+        }
+        Self {
+            id: NEXT_BUFFER_ID.fetch_add(1, Ordering::Relaxed),
+            row_contents,
+            filename,
+            final_newline: false,
+            loader: None,
+            tab_stop: config.tab_stop,
+            soft_tabs: config.soft_tabs,
+            marks: Marks::default(),
+            folds: Folds::default(),
+            dirty: 0,
+            undo: UndoStack::default(),
+            disk_modified: None,
+            backed_up: false,
+            diff: None,
+            git: None,
+            hex: None,
+        }
+    }
+
+    /// Reads `file`, or starts an empty buffer named `file` if it does not
+    /// exist yet. Indentation settings come from `config` unless the file's
+    /// own indentation can be detected.
+    fn from_file(file: PathBuf, config: &Config) -> io::Result<Self> {
+        let handle = match fs::File::open(&file) {
+            Ok(handle) => handle,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::empty(Some(file), config))
+            }
+            Err(err) => return Err(err),
+        };
+        let metadata = handle.metadata().ok();
+        let size = metadata.as_ref().map_or(0, |metadata| metadata.len());
+        let mut row_contents = text_buffer::for_size(size);
+        let lazy = size >= loader::LAZY_THRESHOLD;
+        let mut final_newline = false;
+        let mut reader = BufReader::new(handle);
+        // Binary files too large to load at once are shown as text anyway.
+        let binary = !lazy && hex::is_binary(reader.fill_buf()?);
+        let mut line = Vec::new();
+        let mut loaded = 0;
+        while let Some((row, newline)) = loader::read_row(&mut reader, &mut line, config.tab_stop)? {
+            final_newline = newline;
+            loaded += line.len() as u64 + newline as u64;
+            let at = row_contents.line_count();
+            row_contents.insert(at, row);
+            if lazy && at + 1 == loader::FIRST_ROWS {
+                break;
+            }
+        }
+        if row_contents.line_count() == 0 {
+            row_contents.insert(0, Row::new(String::new(), config.tab_stop));
+        }
+        let hex = if binary {
+            Some(HexView::new(fs::read(&file)?))
+        } else {
+            None
+        };
+        let mut editor_rows = Self {
+            id: NEXT_BUFFER_ID.fetch_add(1, Ordering::Relaxed),
+            row_contents,
+            filename: Some(file),
+            final_newline,
+            loader: None,
+            tab_stop: config.tab_stop,
+            soft_tabs: config.soft_tabs,
+            marks: Marks::default(),
+            folds: Folds::default(),
+            dirty: 0,
+            undo: UndoStack::default(),
+            disk_modified: metadata.and_then(|metadata| metadata.modified().ok()),
+            backed_up: false,
+            diff: None,
+            git: None,
+            hex,
+        };
+        editor_rows.detect_indentation();
+        if lazy && editor_rows.number_of_rows() == loader::FIRST_ROWS {
+            editor_rows.loader = Some(Loader::spawn(
+                reader,
+                loaded,
+                size,
+                final_newline,
+                editor_rows.tab_stop,
+            ));
+        }
+        editor_rows.refresh_git();
+        Ok(editor_rows)
+    }
+
+    /// Adopts the indentation style the rows use, if it is clear.
+    fn detect_indentation(&mut self) {
+        let lines = self.row_contents.lines().map(|row| row.row_content.as_str());
+        match indent::detect(lines) {
+            Some(Indent::Tabs) => self.soft_tabs = false,
+            Some(Indent::Spaces(width)) => {
+                self.soft_tabs = true;
+                self.set_tab_stop(width);
+            }
+            None => {}
+        }
+    }
+
+    fn set_tab_stop(&mut self, tab_stop: usize) {
+        if tab_stop == self.tab_stop {
+            return;
+        }
+        self.tab_stop = tab_stop;
+        for at in 0..self.number_of_rows() {
+            self.row_contents.line_mut(at).update_render(tab_stop);
+        }
+    }
+
+    /// Appends whatever the background loader has read since the last call.
+    fn poll_loader(&mut self) -> io::Result<()> {
+        let Some(loader) = self.loader.as_mut() else {
+            return Ok(());
+        };
+        let row_contents = &mut self.row_contents;
+        let result = loader.receive(|row| {
+            let at = row_contents.line_count();
+            row_contents.insert(at, row)
+        });
+        match result {
+            Ok(None) => {}
+            Ok(Some(final_newline)) => {
+                self.final_newline = final_newline;
+                self.loader = None;
+            }
+            Err(err) => {
+                self.loader = None;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// The file name shown for the buffer.
+    fn display_name(&self) -> &str {
+        self.filename
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or("[No Name]")
+    }
+
+    fn loading_percent(&self) -> Option<u64> {
+        self.loader.as_ref().map(Loader::percent)
+    }
+
+    /// Whether trailing whitespace and mixed indentation are left alone in
+    /// this buffer, for its extension being in `whitespace_exempt`.
+    fn whitespace_exempt(&self, config: &Config) -> bool {
+        let extension = self.filename.as_ref().and_then(|path| path.extension());
+        extension.is_some_and(|extension| {
+            config
+                .whitespace_exempt
+                .iter()
+                .any(|exempt| extension.eq_ignore_ascii_case(exempt.as_str()))
+        })
+    }
+
+    /// An unnamed buffer nobody has typed into yet.
+    fn is_new(&self) -> bool {
+        self.filename.is_none()
+            && self.number_of_rows() == 1
+            && self.get_row(0).is_empty()
+    }
+
+    fn number_of_rows(&self) -> usize {
+        self.row_contents.line_count()
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        self.row_contents.snapshot()
+    }
+
+    /// Asks git for the file as committed, to compare the rows with once it
+    /// answers, unless they are being compared with something else. Files
+    /// still loading are left alone.
+    fn refresh_git(&mut self) {
+        let other = self.diff.as_ref().is_some_and(|diff| diff.source != DiffSource::Head);
+        if let Some(file) = self.filename.as_ref().filter(|_| !other && self.loader.is_none()) {
+            self.git = Some(HeadJob::spawn(file));
+        }
+    }
+
+    /// Compares the rows with the file as committed if git has answered.
+    /// A file git doesn't know isn't compared with anything.
+    fn poll_git(&mut self) {
+        let Some(rows) = self.git.as_ref().and_then(HeadJob::poll) else {
+            return;
+        };
+        self.git = None;
+        if self.diff.as_ref().is_none_or(|diff| diff.source == DiffSource::Head) {
+            self.set_diff_base(DiffSource::Head, rows);
+        }
+    }
+
+    /// Starts comparing the rows with `base`, or stops with `None`.
+    fn set_diff_base(&mut self, source: DiffSource, base: Option<Vec<String>>) {
+        self.diff = base.map(|rows| DiffBase {
+            source,
+            rows,
+            hunks: Vec::new(),
+            computed: None,
+        });
+        self.update_diff();
+    }
+
+    /// Whether edits have left the diff out of date.
+    fn diff_stale(&self) -> bool {
+        self.loader.is_none()
+            && self
+                .diff
+                .as_ref()
+                .is_some_and(|diff| diff.computed != Some(self.dirty))
+    }
+
+    /// Compares the rows with the diff base again.
+    fn update_diff(&mut self) {
+        let Some(diff) = &mut self.diff else {
+            return;
+        };
+        let base: Vec<&str> = diff.rows.iter().map(String::as_str).collect();
+        let rows: Vec<&str> = self.row_contents.lines().map(|row| row.row_content.as_str()).collect();
+        diff.hunks = diff::diff(&base, &rows);
+        diff.computed = Some(self.dirty);
+    }
+
+    /// How row `at` differs from the diff base, if it is compared with one.
+    fn line_change(&self, at: usize) -> Option<LineChange> {
+        let diff = self.diff.as_ref()?;
+        diff::change_at(&diff.hunks, at, self.number_of_rows())
+    }
+
+    /// The rows the diff's hunks show on, in order.
+    fn hunk_rows(&self) -> Vec<usize> {
+        let rows = self.number_of_rows();
+        let mut hunk_rows: Vec<usize> = self
+            .diff
+            .iter()
+            .flat_map(|diff| &diff.hunks)
+            .map(|hunk| diff::hunk_row(hunk, rows))
+            .collect();
+        hunk_rows.dedup();
+        hunk_rows
+    }
+
+    /// The width of row `at`'s indentation on screen, or `None` if it is
+    /// blank.
+    fn indent_width(&self, at: usize) -> Option<usize> {
+        let row = self.get_editor_row(at);
+        let content = row.row_content.trim_start_matches([' ', '\t']);
+        if content.is_empty() {
+            return None;
+        }
+        Some(row.get_render_x(row.row_content.len() - content.len(), self.tab_stop))
+    }
+
+    fn get_row(&self, at: usize) -> &str {
+        &self.row_contents.line(at).row_content
+    }
+
+    fn get_editor_row(&self, at: usize) -> &Row {
+        self.row_contents.line(at)
+    }
+
+    /// Row `at` for editing. Its content is kept for undo first, so only
+    /// ask for a row that is going to change.
+    fn get_editor_row_mut(&mut self, at: usize) -> &mut Row {
+        self.undo.record(Change::Set {
+            at,
+            content: self.get_row(at).to_string(),
+        });
+        self.row_contents.line_mut(at)
+    }
+
+    fn insert_row(&mut self, at: usize, contents: String) {
+        self.row_contents.insert(at, Row::new(contents, self.tab_stop));
+        self.marks.row_inserted(at);
+        self.folds.row_inserted(at);
+        self.undo.record(Change::Inserted { at });
+    }
+
+    fn delete_row(&mut self, at: usize) {
+        let row = self.row_contents.delete(at);
+        self.marks.row_deleted(at);
+        self.folds.row_deleted(at);
+        self.undo.record(Change::Deleted {
+            at,
+            content: row.row_content,
+        });
+    }
+
+    /// Reverts the last group of edits. Returns the cursor from before
+    /// them, or `None` if there is nothing to undo.
+    fn undo(&mut self) -> Option<(usize, usize)> {
+        let group = self.undo.pop_undo()?;
+        let redo = self.revert(group);
+        let cursor = redo.cursor;
+        self.undo.push_redo(redo);
+        self.undo.seal(cursor);
+        self.reverted();
+        Some(cursor)
+    }
+
+    /// Makes the last group of edits undone again. Returns the cursor from
+    /// before them, or `None` if there is nothing to redo.
+    fn redo(&mut self) -> Option<(usize, usize)> {
+        let group = self.undo.pop_redo()?;
+        let undo = self.revert(group);
+        let cursor = undo.cursor;
+        self.undo.push_undo(undo);
+        self.undo.seal(cursor);
+        self.reverted();
+        Some(cursor)
+    }
+
+    /// Counts an undo or redo as an edit, unless it went back to the rows
+    /// as they were saved.
+    fn reverted(&mut self) {
+        self.dirty = if self.undo.at_saved() { 0 } else { self.dirty + 1 };
+        // `dirty` may now repeat a count the diff was worked out at.
+        if let Some(diff) = &mut self.diff {
+            diff.computed = None;
+        }
+    }
+
+    /// Applies `group`'s changes, last first, without recording them.
+    /// Returns the group that puts the rows back as they were.
+    fn revert(&mut self, group: Group) -> Group {
+        let tab_stop = self.tab_stop;
+        let changes = group
+            .changes
+            .into_iter()
+            .rev()
+            .map(|change| match change {
+                Change::Set { at, content } => {
+                    let row = self.row_contents.line_mut(at);
+                    let content = std::mem::replace(&mut row.row_content, content);
+                    row.update_render(tab_stop);
+                    Change::Set { at, content }
+                }
+                Change::Inserted { at } => {
+                    let row = self.row_contents.delete(at);
+                    self.marks.row_deleted(at);
+                    self.folds.row_deleted(at);
+                    Change::Deleted {
+                        at,
+                        content: row.row_content,
+                    }
+                }
+                Change::Deleted { at, content } => {
+                    self.row_contents.insert(at, Row::new(content, tab_stop));
+                    self.marks.row_inserted(at);
+                    self.folds.row_inserted(at);
+                    Change::Inserted { at }
+                }
+            })
+            .collect();
+        Group {
+            changes,
+            cursor: group.cursor,
+        }
+    }
+
+    /// Sorts rows `rows` as `options` say. Returns how many duplicates
+    /// were removed from the end of them.
+    fn sort_rows(&mut self, rows: RangeInclusive<usize>, options: SortOptions) -> usize {
+        let mut sorted: Vec<String> = rows.clone().map(|at| self.get_row(at).to_string()).collect();
+        sort::sort(&mut sorted, options);
+        let (first, last) = rows.into_inner();
+        let removed = last + 1 - first - sorted.len();
+        self.set_rows(first..=last - removed, sorted);
+        for _ in 0..removed {
+            self.delete_row(last + 1 - removed);
+        }
+        if removed > 0 {
+            self.dirty += 1;
+        }
+        removed
+    }
+
+    /// Pads rows `rows` so the first `delimiter` in each lines up. Returns
+    /// whether any row changed.
+    fn align_rows(&mut self, rows: RangeInclusive<usize>, delimiter: char) -> bool {
+        let mut aligned: Vec<String> = rows.clone().map(|at| self.get_row(at).to_string()).collect();
+        align::align(&mut aligned, delimiter, self.tab_stop);
+        self.set_rows(rows, aligned)
+    }
+
+    /// Reverses the order of rows `rows`. Returns whether it changed.
+    fn reverse_rows(&mut self, rows: RangeInclusive<usize>) -> bool {
+        let reversed = rows.clone().rev().map(|at| self.get_row(at).to_string()).collect();
+        self.set_rows(rows, reversed)
+    }
+
+    /// Puts `contents` in rows `rows`, as many as there are of each.
+    /// Returns whether any row changed.
+    fn set_rows(&mut self, rows: RangeInclusive<usize>, contents: Vec<String>) -> bool {
+        let tab_stop = self.tab_stop;
+        let mut changed = false;
+        for (at, contents) in rows.zip(contents) {
+            if self.get_row(at) != contents {
+                let row = self.get_editor_row_mut(at);
+                row.row_content = contents;
+                row.update_render(tab_stop);
+                changed = true;
+            }
+        }
+        if changed {
+            self.dirty += 1;
+        }
+        changed
+    }
+
+    /// Comments out rows `rows`, or uncomments them if they all are, with
+    /// `token`. Returns whether any changed.
+    fn toggle_comment(&mut self, rows: RangeInclusive<usize>, token: &str) -> bool {
+        let mut toggled: Vec<String> = rows.clone().map(|at| self.get_row(at).to_string()).collect();
+        comment::toggle(&mut toggled, token);
+        self.set_rows(rows, toggled)
+    }
+
+    /// The token starting a line comment in the file, going by its
+    /// extension.
+    fn comment_token(&self) -> Option<&'static str> {
+        let extension = self.filename.as_ref()?.extension()?.to_str()?;
+        comment::token_for(extension)
+    }
+
+    /// Deletes each of rows `rows` that is the same as the row before it,
+    /// or without `adjacent_only` as any row before it among them. Returns
+    /// how many were deleted.
+    fn dedup_rows(&mut self, rows: RangeInclusive<usize>, adjacent_only: bool) -> usize {
+        let (first, mut last) = rows.into_inner();
+        let mut seen: HashSet<String> = HashSet::from([self.get_row(first).to_string()]);
+        let mut at = first + 1;
+        let mut removed = 0;
+        while at <= last {
+            let duplicate = if adjacent_only {
+                self.get_row(at) == self.get_row(at - 1)
+            } else {
+                !seen.insert(self.get_row(at).to_string())
+            };
+            if duplicate {
+                self.delete_row(at);
+                last -= 1;
+                removed += 1;
+            } else {
+                at += 1;
+            }
+        }
+        if removed > 0 {
+            self.dirty += 1;
+        }
+        removed
+    }
+
+    /// Indents rows `rows` by a level, a tab or `tab_stop` spaces with
+    /// `soft_tabs`, or with `outdent` takes a leading tab or up to
+    /// `tab_stop` spaces off them. Empty rows aren't indented. Returns
+    /// whether any row changed.
+    fn indent_rows(&mut self, rows: RangeInclusive<usize>, outdent: bool) -> bool {
+        let level = if self.soft_tabs {
+            " ".repeat(self.tab_stop)
+        } else {
+            "\t".to_string()
+        };
+        let mut changed = false;
+        for at in rows {
+            let line = self.get_row(at);
+            if outdent {
+                let spaces = line.len() - line.trim_start_matches(' ').len();
+                let len = if line.starts_with('\t') { 1 } else { spaces.min(self.tab_stop) };
+                if len > 0 {
+                    self.replace_in_row((at, 0), len, "");
+                    changed = true;
+                }
+            } else if !line.is_empty() {
+                self.replace_in_row((at, 0), 0, &level);
+                changed = true;
+            }
+        }
+        if changed {
+            self.dirty += 1;
+        }
+        changed
+    }
+
+    /// Puts the letters from `start` up to `end` in `case`, row by row so
+    /// rows stay as they are. Returns whether any changed.
+    fn change_case(&mut self, start: (usize, usize), end: (usize, usize), case: Case) -> bool {
+        let mut changed = false;
+        for row in start.0..=end.0 {
+            let line = self.get_row(row);
+            let from = if row == start.0 { start.1 } else { 0 };
+            let to = if row == end.0 { end.1 } else { line.len() };
+            let in_word = line[..from].chars().next_back().is_some_and(is_word_char);
+            let text = &line[from..to];
+            let converted = case::convert(text, case, in_word);
+            if converted != text {
+                self.replace_in_row((row, from), to - from, &converted);
+                changed = true;
+            }
+        }
+        if changed {
+            self.dirty += 1;
+        }
+        changed
+    }
+
+    /// Swaps the case of the letters in `row` from `col_start` up to
+    /// `col_end`. Returns whether any changed.
+    fn toggle_case(&mut self, row: usize, col_start: usize, col_end: usize) -> bool {
+        self.change_case((row, col_start), (row, col_end), Case::Toggle)
+    }
+
+    /// Finds the first match of `query` at or after `from` that ends at or
+    /// before `end`, both `(row, byte column)`.
+    fn find_in(&self, query: &str, from: (usize, usize), end: (usize, usize)) -> Option<(usize, usize)> {
+        for row in from.0..=end.0 {
+            let line = self.get_row(row);
+            let start = if row == from.0 { from.1 } else { 0 };
+            let limit = if row == end.0 { end.1 } else { line.len() };
+            if let Some(col) = line[start..].find(query).map(|col| start + col) {
+                if col + query.len() <= limit {
+                    return Some((row, col));
+                }
+            }
+        }
+        None
+    }
+
+    /// Replaces `len` bytes at `at` with `with`.
+    fn replace_in_row(&mut self, (row, col): (usize, usize), len: usize, with: &str) {
+        let tab_stop = self.tab_stop;
+        let row = self.get_editor_row_mut(row);
+        row.row_content.replace_range(col..col + len, with);
+        row.update_render(tab_stop);
+    }
+
+    /// The text from `start` up to but not including `end`, with rows
+    /// separated by newlines.
+    fn text_range(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        if start.0 == end.0 {
+            return self.get_row(start.0)[start.1..end.1].to_string();
+        }
+        let mut text = self.get_row(start.0)[start.1..].to_string();
+        for row in start.0 + 1..end.0 {
+            text.push('\n');
+            text.push_str(self.get_row(row));
+        }
+        text.push('\n');
+        text.push_str(&self.get_row(end.0)[..end.1]);
+        text
+    }
+
+    /// Inserts `text` at `at`, splitting rows at its newlines. Returns the
+    /// position just after the inserted text.
+    fn insert_text(&mut self, (row, col): (usize, usize), text: &str) -> (usize, usize) {
+        let tab_stop = self.tab_stop;
+        let mut pieces = text.split('\n');
+        let current = self.get_editor_row_mut(row);
+        let tail = current.row_content.split_off(col);
+        current.row_content.push_str(pieces.next().unwrap_or_default());
+        let mut end = (row, current.row_content.len());
+        current.update_render(tab_stop);
+        for piece in pieces {
+            end = (end.0 + 1, piece.len());
+            self.insert_row(end.0, piece.to_string());
+        }
+        let last = self.get_editor_row_mut(end.0);
+        last.row_content.push_str(&tail);
+        last.update_render(tab_stop);
+        end
+    }
+
+    /// Puts `open` before `(row_start, col_start)` and `close` before
+    /// `(row_end, col_end)`, around the text between them.
+    fn surround_region(
+        &mut self,
+        row_start: usize,
+        col_start: usize,
+        row_end: usize,
+        col_end: usize,
+        open: &str,
+        close: &str,
+    ) {
+        self.insert_text((row_end, col_end), close);
+        self.insert_text((row_start, col_start), open);
+    }
+
+    /// Replaces the delimiters at byte ranges `open_at` and `close_at` of
+    /// their rows with `open` and `close`. `close_at` comes after
+    /// `open_at`.
+    fn replace_surround(
+        &mut self,
+        open_at: (usize, Range<usize>),
+        close_at: (usize, Range<usize>),
+        open: &str,
+        close: &str,
+    ) {
+        for ((row, columns), text) in [(close_at, close), (open_at, open)] {
+            self.delete_range((row, columns.start), (row, columns.end));
+            self.insert_text((row, columns.start), text);
+        }
+    }
+
+    /// Removes the text from `start` up to but not including `end`, both
+    /// `(row, byte column)`, joining the rows at either end.
+    fn delete_range(&mut self, start: (usize, usize), end: (usize, usize)) {
+        let tail = self.get_row(end.0)[end.1..].to_string();
+        for _ in start.0..end.0 {
+            self.delete_row(start.0 + 1);
+        }
+        let tab_stop = self.tab_stop;
+        let row = self.get_editor_row_mut(start.0);
+        row.row_content.truncate(start.1);
+        row.row_content.push_str(&tail);
+        row.update_render(tab_stop);
+    }
+
+    /// Appends row `at + 1` to row `at`.
+    fn join_adjacent_rows(&mut self, at: usize) {
+        let len = self.get_row(at).len();
+        self.get_editor_row_mut(at);
+        let next = self.row_contents.delete(at + 1);
+        self.marks.rows_joined(at, len);
+        self.folds.row_deleted(at + 1);
+        self.undo.record(Change::Deleted {
+            at: at + 1,
+            content: next.row_content.clone(),
+        });
+        let tab_stop = self.tab_stop;
+        let row = self.row_contents.line_mut(at);
+        row.row_content.push_str(&next.row_content);
+        row.update_render(tab_stop);
+    }
+
+    /// Removes trailing spaces and tabs from every row. Returns whether any
+    /// row changed.
+    fn strip_trailing_whitespace(&mut self) -> bool {
+        let mut changed = false;
+        let tab_stop = self.tab_stop;
+        for at in 0..self.number_of_rows() {
+            let len = self.get_row(at).trim_end_matches([' ', '\t']).len();
+            if len < self.get_row(at).len() {
+                let row = self.get_editor_row_mut(at);
+                row.row_content.truncate(len);
+                row.update_render(tab_stop);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Makes the file end with a single newline, optionally dropping blank
+    /// rows at the end first. Returns whether any row was removed.
+    fn ensure_final_newline(&mut self, collapse_blank_lines: bool) -> bool {
+        let mut changed = false;
+        while collapse_blank_lines
+            && self.number_of_rows() > 1
+            && self.get_row(self.number_of_rows() - 1).is_empty()
+        {
+            self.delete_row(self.number_of_rows() - 1);
+            changed = true;
+        }
+        // An empty file stays empty rather than becoming a lone newline.
+        self.final_newline = self.number_of_rows() > 1 || !self.get_row(0).is_empty();
+        changed
+    }
+
+    /// When the file was last modified, if it can be told.
+    fn modified_on_disk(&self) -> Option<SystemTime> {
+        let metadata = fs::metadata(self.filename.as_ref()?).ok()?;
+        metadata.modified().ok()
+    }
+
+    /// Whether another program has modified the file since it was read or
+    /// last saved.
+    fn changed_on_disk(&self) -> bool {
+        self.loader.is_none()
+            && self
+                .modified_on_disk()
+                .is_some_and(|modified| Some(modified) != self.disk_modified)
+    }
+
+    /// Streams the rows to the file, returning the number of bytes written.
+    /// They go to a temporary file next to it first, renamed over it once
+    /// complete, so a failed save leaves the file as it was. The file keeps
+    /// its permissions, and a link stays a link. With `backup`, the file is
+    /// first copied to `<name>~`, once a session.
+    fn save(&mut self, backup: bool) -> io::Result<usize> {
+        let Some(name) = &self.filename else {
+            return Err(io::Error::other("no file name specified"));
+        };
+        let target = fs::canonicalize(name).unwrap_or_else(|_| name.clone());
+        let temp = target.with_file_name(format!(
+            ".{}.{}.tmp",
+            target.file_name().unwrap_or_default().to_string_lossy(),
+            std::process::id()
+        ));
+        if backup && !self.backed_up && target.exists() {
+            let mut name = target.clone().into_os_string();
+            name.push("~");
+            fs::copy(&target, name)?;
+            self.backed_up = true;
+        }
+        let result = self.write_to(&temp).and_then(|written| {
+            if let Ok(metadata) = fs::metadata(&target) {
+                fs::set_permissions(&temp, metadata.permissions())?;
+            }
+            fs::rename(&temp, &target)?;
+            Ok(written)
+        });
+        match result {
+            // Not a change by another program.
+            Ok(_) => {
+                self.disk_modified = self.modified_on_disk();
+                // The file saved is what the rows are compared with now.
+                if self.diff.as_ref().is_some_and(|diff| diff.source == DiffSource::Saved) {
+                    self.set_diff_base(DiffSource::Saved, Some(self.row_strings()));
+                } else {
+                    self.refresh_git();
+                }
+            }
+            Err(_) => {
+                let _ = fs::remove_file(&temp);
+            }
+        }
+        result
+    }
+
+    /// Writes the rows to a new file at `path`, returning the bytes written.
+    fn write_to(&self, path: &Path) -> io::Result<usize> {
+        let mut file = BufWriter::new(fs::File::create(path)?);
+        let written = self.write_rows(&mut file)?;
+        file.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()?;
+        Ok(written)
+    }
+
+    /// Writes the rows to `out` as saving them would, returning the bytes
+    /// written.
+    fn write_rows(&self, out: &mut impl Write) -> io::Result<usize> {
+        let last = self.number_of_rows() - 1;
+        let mut written = 0;
+        for (i, row) in self.row_contents.lines().enumerate() {
+            out.write_all(row.row_content.as_bytes())?;
+            written += row.row_content.len();
+            if i < last || self.final_newline {
+                out.write_all(b"\n")?;
+                written += 1;
+            }
+        }
+        Ok(written)
+    }
+
+    /// Each row's text.
+    fn row_strings(&self) -> Vec<String> {
+        self.row_contents.lines().map(|row| row.row_content.clone()).collect()
+    }
+
+    /// The rows as saving them would write them.
+    fn bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_rows(&mut bytes).expect("Unable to write to memory");
+        bytes
+    }
+}
+
+impl Lines for EditorRows {
+    fn line_count(&self) -> usize {
+        self.number_of_rows()
+    }
+
+    fn line(&self, at: usize) -> &str {
+        self.get_row(at)
+    }
+}
+
+/// A cursor besides the main one, edited along with it.
+#[derive(Copy, Clone, PartialEq)]
+struct ExtraCursor {
+    /// `(row, byte column)`.
+    pos: (usize, usize),
+    /// Where its selection started, like [`Output::selection_anchor`].
+    anchor: Option<(usize, usize)>,
+}
+
+#[derive(Clone)]
+struct CursorController {
+    cursor_x: usize,
+    cursor_y: usize,
+    screen_columns: usize,
+    screen_rows: usize,
+    row_offset: usize,
+    column_offset: usize,
+    render_x: usize,
+    /// Whether long rows wrap, from the `wrap` option.
+    wrap: bool,
+    /// Whether Up and Down go by screen row when wrapping.
+    by_screen_row: bool,
+    /// Rows kept on screen above and below the cursor, from `scroll_off`.
+    scroll_off: usize,
+    /// Columns kept on screen left and right of the cursor, from
+    /// `side_scroll_off`.
+    side_scroll_off: usize,
+}
+
+impl CursorController {
+    fn new(win_size: (usize, usize)) -> CursorController {
+        Self {
+            cursor_x: 0,
+            cursor_y: 0,
+            screen_columns: win_size.0,
+            screen_rows: win_size.1,
+            row_offset: 0,
+            column_offset: 0,
+            render_x: 0,
+            wrap: false,
+            by_screen_row: true,
+            scroll_off: 0,
+            side_scroll_off: 0,
+        }
+    }
+
+    fn scroll(&mut self, editor_rows: &EditorRows) {
+        self.render_x = editor_rows
+            .get_editor_row(self.cursor_y)
+            .get_render_x(self.cursor_x, editor_rows.tab_stop);
+        // Margins are kept only where they leave the cursor a row or column
+        // between them, and not past the end of the buffer.
+        let margin = self.scroll_off.min(self.screen_rows.saturating_sub(1) / 2);
+        let last_row = editor_rows.number_of_rows() - 1;
+        let folds = &editor_rows.folds;
+        self.row_offset = cmp::min(self.row_offset, self.cursor_y.saturating_sub(margin));
+        let bottom = cmp::min(self.cursor_y + margin, last_row);
+        if bottom >= self.row_offset + self.screen_rows {
+            self.row_offset = bottom - self.screen_rows + 1;
+        }
+        self.row_offset = folds.shown(self.row_offset);
+        if self.wrap || !folds.is_empty() {
+            // Drop rows off the top until the cursor's screen row, and the
+            // margin below it, fit.
+            let (wrap, columns) = (self.wrap, self.screen_columns);
+            let lines_of = |row| {
+                if folds.hidden(row) {
+                    0
+                } else if wrap {
+                    wrap::breaks(&editor_rows.get_editor_row(row).render, columns).len()
+                } else {
+                    1
+                }
+            };
+            let cursor_breaks = if wrap {
+                self.wrap_breaks(editor_rows, self.cursor_y)
+            } else {
+                vec![0]
+            };
+            let cursor_line = wrap::line_of(&cursor_breaks, self.render_x);
+            let mut below = cursor_breaks.len() - 1 - cursor_line;
+            let mut next = self.cursor_y + 1;
+            while below < margin && next <= last_row {
+                below += lines_of(next);
+                next += 1;
+            }
+            let mut lines = (self.row_offset..self.cursor_y).map(lines_of).sum::<usize>()
+                + cursor_line
+                + 1
+                + below.min(margin);
+            while lines > self.screen_rows && self.row_offset < self.cursor_y {
+                lines -= lines_of(self.row_offset);
+                self.row_offset += 1;
+            }
+        }
+        if self.wrap {
+            self.column_offset = 0;
+            return;
+        }
+        let margin = self.side_scroll_off.min(self.screen_columns.saturating_sub(1) / 2);
+        let width = editor_rows.get_editor_row(self.cursor_y).render.chars().count();
+        self.column_offset = cmp::min(self.column_offset, self.render_x.saturating_sub(margin));
+        let right = cmp::max(cmp::min(self.render_x + margin, width), self.render_x);
+        if right >= self.column_offset + self.screen_columns {
+            self.column_offset = right - self.screen_columns + 1;
+        }
+    }
+
+    /// Scrolls half a screen down, or up without `down`, taking the cursor
+    /// as many rows along so it stays where it was on screen. The view
+    /// stops with the last row at the bottom, the cursor on the last row.
+    fn scroll_half_page(&mut self, down: bool, editor_rows: &EditorRows) {
+        let half = (self.screen_rows / 2).max(1);
+        let last_row = editor_rows.number_of_rows() - 1;
+        if down {
+            let max_offset = (last_row + 1).saturating_sub(self.screen_rows);
+            self.row_offset = (self.row_offset + half).min(max_offset.max(self.row_offset));
+            self.cursor_y = (self.cursor_y + half).min(last_row);
+        } else {
+            self.row_offset = self.row_offset.saturating_sub(half);
+            self.cursor_y = self.cursor_y.saturating_sub(half);
+        }
+        self.cursor_x = editor_rows.get_editor_row(self.cursor_y).snap(self.cursor_x);
+    }
+
+    /// Scrolls so the cursor's row is at `place` on the screen, as far as
+    /// the scroll margin and the ends of the buffer let it. The cursor
+    /// stays where it is in the buffer.
+    fn scroll_cursor_to(&mut self, place: ScreenPlace, editor_rows: &EditorRows) {
+        let margin = self.scroll_off.min(self.screen_rows.saturating_sub(1) / 2);
+        let offset = match place {
+            ScreenPlace::Top => self.cursor_y.saturating_sub(margin),
+            ScreenPlace::Middle => self.cursor_y.saturating_sub(self.screen_rows / 2),
+            ScreenPlace::Bottom => (self.cursor_y + margin + 1).saturating_sub(self.screen_rows),
+        };
+        let max_offset = editor_rows.number_of_rows().saturating_sub(self.screen_rows);
+        self.row_offset = offset.min(max_offset);
+    }
+
+    /// Scrolls `lines` rows down, or up without `down`, leaving the cursor
+    /// on its row unless that would leave the screen or enter the scroll
+    /// margin, when the cursor is dragged along at the edge.
+    fn scroll_lines(&mut self, down: bool, lines: usize, editor_rows: &EditorRows) {
+        let last_row = editor_rows.number_of_rows() - 1;
+        if down {
+            let max_offset = (last_row + 1).saturating_sub(self.screen_rows);
+            self.row_offset = (self.row_offset + lines).min(max_offset.max(self.row_offset));
+        } else {
+            self.row_offset = self.row_offset.saturating_sub(lines);
+        }
+        let margin = self.scroll_off.min(self.screen_rows.saturating_sub(1) / 2);
+        let top = if self.row_offset == 0 { 0 } else { self.row_offset + margin };
+        let bottom = if self.row_offset + self.screen_rows > last_row {
+            last_row
+        } else {
+            (self.row_offset + self.screen_rows).saturating_sub(margin + 1)
+        };
+        self.cursor_y = self.cursor_y.max(top).min(bottom);
+        self.cursor_x = editor_rows.get_editor_row(self.cursor_y).snap(self.cursor_x);
+    }
+
+    /// Scrolls the cursor's row to the middle of the screen, for `zz`.
+    fn center_on_cursor(&mut self, editor_rows: &EditorRows) {
+        self.scroll_cursor_to(ScreenPlace::Middle, editor_rows)
+    }
+
+    /// Where the screen rows of wrapped row `row` start.
+    fn wrap_breaks(&self, editor_rows: &EditorRows, row: usize) -> Vec<usize> {
+        wrap::breaks(&editor_rows.get_editor_row(row).render, self.screen_columns)
+    }
+
+    /// Moves to the screen row above or below, which may be part of the
+    /// same wrapped row, keeping the column on screen.
+    fn move_wrapped(&mut self, down: bool, editor_rows: &EditorRows) {
+        let tab_stop = editor_rows.tab_stop;
+        let render_x = editor_rows
+            .get_editor_row(self.cursor_y)
+            .get_render_x(self.cursor_x, tab_stop);
+        let breaks = self.wrap_breaks(editor_rows, self.cursor_y);
+        let line = wrap::line_of(&breaks, render_x);
+        let column = render_x - breaks[line];
+        let folds = &editor_rows.folds;
+        let (row, breaks, line) = if down {
+            let next = folds.next_shown(self.cursor_y);
+            if line + 1 < breaks.len() {
+                (self.cursor_y, breaks, line + 1)
+            } else if next < editor_rows.number_of_rows() {
+                (next, self.wrap_breaks(editor_rows, next), 0)
+            } else {
+                return;
+            }
+        } else if line > 0 {
+            (self.cursor_y, breaks, line - 1)
+        } else if self.cursor_y > 0 {
+            let previous = folds.shown(self.cursor_y - 1);
+            let breaks = self.wrap_breaks(editor_rows, previous);
+            let last = breaks.len() - 1;
+            (previous, breaks, last)
+        } else {
+            return;
+        };
+        let mut target = breaks[line] + column;
+        if let Some(&next) = breaks.get(line + 1) {
+            target = target.min(next - 1);
+        }
+        self.cursor_y = row;
+        self.cursor_x = editor_rows.get_editor_row(row).get_row_x(target, tab_stop);
+    }
+
+    /// Moves past the next word, or back to the start of the previous one,
+    /// for Ctrl+Right and Ctrl+Left.
+    fn move_word(&mut self, forward: bool, editor_rows: &EditorRows) {
+        (self.cursor_y, self.cursor_x) =
+            word_motion::skip_word(editor_rows, (self.cursor_y, self.cursor_x), forward);
+    }
+
+    /// Moves a char or row `direction`'s way, stepping over folded rows.
+    fn move_cursor(&mut self, direction: KeyCode, editor_rows: &EditorRows) {
+        let number_of_rows = editor_rows.number_of_rows();
+        if self.wrap && self.by_screen_row && matches!(direction, KeyCode::Up | KeyCode::Down) {
+            return self.move_wrapped(direction == KeyCode::Down, editor_rows);
+        }
+        let folds = &editor_rows.folds;
+        let next = folds.next_shown(self.cursor_y);
+        match direction {
+            KeyCode::Up => {
+                self.cursor_y = folds.shown(self.cursor_y.saturating_sub(1));
+            }
+            KeyCode::Left => {
+                if self.cursor_x != 0 {
+                    self.cursor_x = editor_rows
+                        .get_editor_row(self.cursor_y)
+                        .prev_boundary(self.cursor_x);
+                } else if self.cursor_y > 0 {
+                    self.cursor_y = folds.shown(self.cursor_y - 1);
+                    self.cursor_x = editor_rows.get_row(self.cursor_y).len();
+                }
+            }
+            KeyCode::Down => {
+                if next < number_of_rows {
+                    self.cursor_y = next;
+                }
+            }
+            KeyCode::Right => {
+                let row = editor_rows.get_editor_row(self.cursor_y);
+                if self.cursor_x < row.row_content.len() {
+                    self.cursor_x = row.next_boundary(self.cursor_x);
+                } else if next < number_of_rows {
+                    self.cursor_y = next;
+                    self.cursor_x = 0;
+                }
+            }
+            _ => unimplemented!(),
+        }
+        self.cursor_x = editor_rows
+            .get_editor_row(self.cursor_y)
+            .snap(self.cursor_x);
+    }
+
+    fn goto_line(&mut self, line: usize, editor_rows: &EditorRows) {
+        self.cursor_y = cmp::min(line.saturating_sub(1), editor_rows.number_of_rows() - 1);
+        self.cursor_x = 0;
+    }
+}
+
+struct EditorContents {
+    content: String,
+}
+
+impl EditorContents {
+    fn new() -> Self {
+        Self {
+            content: String::new(),
+        }
+    }
+
+    fn push(&mut self, ch: char) {
+        self.content.push(ch)
+    }
+
+    fn push_str(&mut self, string: &str) {
+        self.content.push_str(string)
+    }
+}
+
+impl io::Write for EditorContents {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match std::str::from_utf8(buf) {
+            Ok(s) => {
+                self.content.push_str(s);
+                Ok(s.len())
+            }
+            Err(_) => Err(io::ErrorKind::WriteZero.into()),
+        }
+    }
+
+    #[cfg(not(test))]
+    fn flush(&mut self) -> io::Result<()> {
+        let out = write!(stdout(), "{}", self.content);
+        stdout().flush()?;
+        self.content.clear();
+        out
+    }
+
+    /// Tests draw the screen without showing it.
+    #[cfg(test)]
+    fn flush(&mut self) -> io::Result<()> {
+        self.content.clear();
+        Ok(())
+    }
+}
+
+/// Formats `n` with thousands separators, e.g. `1,204`.
+fn group_digits(n: usize) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(ch);
+    }
+    grouped
+}
+
+/// Completion hook for [`prompt`]: given the current input, returns the
+/// completed input, or `None` to leave it untouched.
+type Completion<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// Hook called by [`prompt`] with the prompt's message and current input
+/// after every key that doesn't end the prompt, and with `None` whenever no
+/// key arrived for a moment. It may change the message. Returns whether the
+/// screen needs redrawing.
+type PromptCallback<'a> =
+    &'a mut dyn FnMut(&mut Output, &mut String, &str, Option<&KeyEvent>) -> bool;
+
+/// Reads a line of input in the message row, prefixed with `message`.
+///
+/// Up/Down scroll through `history`, keeping what was typed as the newest
+/// entry, Tab asks `completion` (if any) to complete the input, and Escape
+/// or Ctrl-C cancel the prompt by returning `None`. Accepted input is
+/// appended to `history`. Only prompts with a `callback` accept empty
+/// input, which means something to them, such as the last search.
+fn prompt(
+    reader: &mut Reader,
+    output: &mut Output,
+    message: &str,
+    history: &mut History,
+    completion: Option<Completion>,
+    mut callback: Option<PromptCallback>,
+) -> std::result::Result<Option<String>, std::io::Error> {
+    let mut message = message.to_string();
+    let mut input = String::new();
+    let mut history_index = history.len();
+    // What was typed before moving into the history.
+    let mut draft = String::new();
+    let mut redraw = true;
+    loop {
+        if redraw {
+            output
+                .status_message
+                .set_message(format!("{}{}", message, input));
+            output.refresh_screen()?;
+            execute!(
+                stdout(),
+                cursor::MoveTo(
+                    (message.chars().count() + input.chars().count()) as u16,
+                    output.win_size.1 as u16 + 1
+                )
+            )?;
+        }
+        if let Some(callback) = callback.as_mut() {
+            if !reader.key_ready(Duration::from_millis(50))? {
+                redraw = callback(output, &mut message, &input, None);
+                continue;
+            }
+        }
+        redraw = true;
+        let key = reader.read_key()?;
+        match key {
+            KeyEvent {
+                code: KeyCode::Enter,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } if !input.is_empty() || callback.is_some() => {
+                output.status_message.set_message(String::new());
+                if !input.is_empty() {
+                    history.push(input.clone());
+                }
+                return Ok(Some(input));
+            }
+            KeyEvent {
+                code: KeyCode::Esc, ..
+            }
+            | KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                output.status_message.set_message(String::new());
+                return Ok(None);
+            }
+            KeyEvent {
+                code: KeyCode::Up, ..
+            } if history_index > 0 => {
+                if history_index == history.len() {
+                    draft = input.clone();
+                }
+                history_index -= 1;
+                input = history.get(history_index).unwrap_or_default().into();
+            }
+            KeyEvent {
+                code: KeyCode::Down,
+                ..
+            } if history_index < history.len() => {
+                history_index += 1;
+                input = history.get(history_index).unwrap_or(&draft).into();
+            }
+            KeyEvent {
+                code: KeyCode::Tab, ..
+            } => {
+                if let Some(completed) = completion.and_then(|complete| complete(&input)) {
+                    input = completed;
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Backspace,
+                ..
+            } => {
+                input.pop();
+            }
+            KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } => input.push(ch),
+            _ => {}
+        }
+        if let Some(callback) = callback.as_mut() {
+            callback(output, &mut message, &input, Some(&key));
+        }
+    }
+}
+
+/// Matches of a search across files, kept after their list is closed.
+struct GrepResults {
+    text: String,
+    job: GrepJob,
+    /// Sorted by file and position.
+    matches: Vec<GrepMatch>,
+    /// Index in `matches` of the one last opened.
+    current: Option<usize>,
+}
+
+impl GrepResults {
+    /// Takes the matches found since the last call. Returns whether
+    /// anything changed.
+    fn receive(&mut self) -> bool {
+        if !self.job.receive(&mut self.matches) {
+            return false;
+        }
+        self.matches
+            .sort_by(|a, b| (&a.path, a.row, a.col).cmp(&(&b.path, b.row, b.col)));
+        true
+    }
+
+    /// How many matches there are, noting when there may be more.
+    fn summary(&self) -> String {
+        let searching = if self.job.finished() { "" } else { "searching… " };
+        if self.matches.len() >= grep::MAX_MATCHES {
+            format!("{}showing first {} matches", searching, grep::MAX_MATCHES)
+        } else {
+            format!("{}{} matches", searching, self.matches.len())
+        }
+    }
+}
+
+struct Editor {
+    reader: Reader,
+    output: Output,
+    history: Histories,
+    search_job: Option<SearchJob>,
+    macros: HashMap<char, Vec<KeyEvent>>,
+    /// Register the macro being recorded will be stored in.
+    macro_register: Option<char>,
+    /// Register replayed by `@@`.
+    last_macro: Option<char>,
+    jumps: JumpList,
+    /// Count typed before a pending operator, as the 2 of `2d3w`.
+    operator_count: Option<usize>,
+    /// The last `f`, `F`, `t` or `T`, repeated by `;` and `,`.
+    last_char_search: Option<CharSearch>,
+    /// The last search and its direction, for `n` and `N` to continue.
+    last_search: Option<(Query, SearchDirection)>,
+    /// The last search across files, walked with `]q` and `[q`.
+    grep: Option<GrepResults>,
+    /// The edit `.` repeats.
+    last_edit: Option<Edit>,
+    /// An insert or change still being typed, which becomes `last_edit`
+    /// when Insert mode ends.
+    pending_edit: Option<Edit>,
+    /// Whether the last key was a quit refused over unsaved changes, so
+    /// pressing it again quits anyway.
+    quit_warned: bool,
+    /// Chars overwritten in Replace mode, most recent last, for Backspace
+    /// to put back. `None` for chars added past the end of a row.
+    replaced: Vec<Option<char>>,
+    /// When the last key was read, for auto-saving.
+    last_key: Instant,
+    /// `EditorRows::id` of the scratch buffer `q:` lists the command
+    /// history in, while it is open.
+    history_window: Option<usize>,
+}
+
+impl Editor {
+    fn new() -> Self {
+        let output = Output::new();
+        let history = if output.config.persist_history {
+            Histories::load()
+        } else {
+            Histories::default()
+        };
+        Self::with_output(output, history)
+    }
+
+    fn with_output(output: Output, history: Histories) -> Self {
+        Self {
+            reader: Reader::default(),
+            output,
+            history,
+            search_job: None,
+            macros: HashMap::new(),
+            macro_register: None,
+            last_macro: None,
+            jumps: JumpList::default(),
+            operator_count: None,
+            last_char_search: None,
+            last_search: None,
+            grep: None,
+            quit_warned: false,
+            replaced: Vec::new(),
+            last_key: Instant::now(),
+            last_edit: None,
+            pending_edit: None,
+            history_window: None,
+        }
+    }
+
+    /// Called when something the keys asked for could not be done. A macro
+    /// being replayed stops there rather than carrying on from the wrong
+    /// place.
+    fn abort_replay(&mut self) {
+        if !self.reader.pending.is_empty() {
+            self.reader.pending.clear();
+            let message = self.output.status_message.message().cloned().unwrap_or_default();
+            self.output
+                .status_message
+                .set_message(format!("{} (macro stopped)", message).trim_start().into());
+        }
+    }
+
+    /// Reads the key following a prefix such as `q` or `@`, returning it if
+    /// it names a register `a`-`z`.
+    fn read_register(&mut self) -> std::result::Result<Option<char>, std::io::Error> {
+        Ok(Self::register(self.reader.read_key()?))
+    }
+
+    /// The macro register `key` names, if it names one.
+    fn register(key: KeyEvent) -> Option<char> {
+        match key {
+            KeyEvent {
+                code: KeyCode::Char(register @ 'a'..='z'),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => Some(register),
+            _ => None,
+        }
+    }
+
+    /// `q{register}` starts recording keys into a macro; the next `q` stops.
+    /// `q:` lists the command history instead.
+    fn toggle_macro_recording(&mut self) -> std::result::Result<bool, std::io::Error> {
+        if self.macro_register.is_some() {
+            self.stop_macro_recording();
+            return Ok(true);
+        }
+        let key = self.reader.read_key()?;
+        if key.code == KeyCode::Char(':') {
+            return self.command_history_window();
+        }
+        if let Some(register) = Self::register(key) {
+            self.start_macro_recording(register);
+        }
+        Ok(true)
+    }
+
+    /// Opens the commands run at the `:` prompt in a pane below the focused
+    /// one, the latest at the bottom, as a scratch buffer they can be
+    /// edited in. Enter in Normal mode runs the row under the cursor and
+    /// Escape closes the pane, dropping the buffer.
+    fn command_history_window(&mut self) -> std::result::Result<bool, std::io::Error> {
+        let history = &self.history.command;
+        let entries: Vec<String> = (0..history.len())
+            .filter_map(|at| history.get(at))
+            .map(String::from)
+            .collect();
+        if entries.is_empty() {
+            self.output.status_message.set_message("No command history".into());
+            return Ok(true);
+        }
+        self.close_history_window();
+        let output = &mut self.output;
+        if !output.split(Direction::Rows) {
+            return Ok(true);
+        }
+        output.focus_view(output.focus + 1);
+        let rows = entries.len();
+        let window = EditorRows::with_rows(None, entries, &output.config);
+        self.history_window = Some(window.id);
+        output.add_buffer(window);
+        let height = output.pane_rects()[output.focus].height;
+        output.resize_pane(Direction::Rows, rows.min(MENU_ROWS) as isize - height as isize);
+        output.cursor_controller.cursor_y = rows - 1;
+        output
+            .status_message
+            .set_message("Command history: Enter runs a row, Escape closes".into());
+        Ok(true)
+    }
+
+    /// Handles Enter and Escape in Normal mode in the `q:` window. Returns
+    /// `None` for other keys, or when the window isn't focused.
+    fn history_window_key(&mut self, key: KeyEvent) -> Option<std::result::Result<bool, std::io::Error>> {
+        if self.history_window != Some(self.output.editor_rows.id)
+            || self.output.mode != Mode::Normal
+            || key.modifiers != KeyModifiers::NONE
+        {
+            return None;
+        }
+        match key.code {
+            KeyCode::Enter => {
+                let row = self.output.cursor_controller.cursor_y;
+                let command = self.output.editor_rows.get_row(row).to_string();
+                self.close_history_window();
+                self.output.status_message.set_message(String::new());
+                self.history.command.push(command.clone());
+                Some(self.execute_command(&command, None))
+            }
+            KeyCode::Esc => {
+                self.close_history_window();
+                self.output.status_message.set_message(String::new());
+                Some(Ok(true))
+            }
+            _ => None,
+        }
+    }
+
+    /// Closes the `q:` window's buffer, and its pane if focused.
+    fn close_history_window(&mut self) {
+        let Some(id) = self.history_window.take() else {
+            return;
+        };
+        if !self.output.buffer_order.contains(&id) {
+            return;
+        }
+        if self.output.editor_rows.id == id && self.output.views.len() > 1 {
+            self.output.close_pane();
+        }
+        self.output.close_buffer(id);
+    }
+
+    fn start_macro_recording(&mut self, register: char) {
+        self.macro_register = Some(register);
+        self.reader.macro_buffer.clear();
+        self.reader.macro_recording = true;
+        self.output
+            .status_message
+            .set_message(format!("Recording @{}", register));
+    }
+
+    /// Stores the keys recorded so far, which become the macro replayed by
+    /// `@@`.
+    fn stop_macro_recording(&mut self) {
+        let Some(register) = self.macro_register.take() else {
+            return;
+        };
+        self.reader.macro_recording = false;
+        let mut keys = std::mem::take(&mut self.reader.macro_buffer);
+        // Drop the key that stopped the recording.
+        keys.pop();
+        self.macros.insert(register, keys);
+        self.last_macro = Some(register);
+        self.output
+            .status_message
+            .set_message(format!("Recorded @{}", register));
+    }
+
+    /// `@{register}` replays a macro `count` times, `@@` the last one used.
+    fn read_and_play_macro(&mut self, count: usize) -> std::result::Result<(), std::io::Error> {
+        let register = match self.reader.read_key()? {
+            KeyEvent {
+                code: KeyCode::Char('@'),
+                ..
+            } => self.last_macro,
+            KeyEvent {
+                code: KeyCode::Char(register @ 'a'..='z'),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => Some(register),
+            _ => None,
+        };
+        self.play_macro(register, count);
+        Ok(())
+    }
+
+    fn play_macro(&mut self, register: Option<char>, count: usize) {
+        let Some(keys) = register.and_then(|register| self.macros.get(&register)) else {
+            self.output
+                .status_message
+                .set_message("No such macro".into());
+            return self.abort_replay();
+        };
+        if self.reader.pending.len() + keys.len() * count > MAX_REPLAY_KEYS {
+            self.reader.pending.clear();
+            self.output
+                .status_message
+                .set_message("Macro replay too long, stopped".into());
+            return;
+        }
+        let keys = keys.repeat(count);
+        // The replay is a step to undo of its own, even from Insert mode.
+        self.output.seal_undo();
+        self.reader.replay(&keys);
+        self.last_macro = register;
+    }
+
+    /// Sets the mark named by the next key at the cursor, or deletes it if
+    /// it is already there.
+    fn set_mark(&mut self) -> std::result::Result<(), std::io::Error> {
+        self.output.status_message.set_message("Set mark: ".into());
+        self.output.refresh_screen()?;
+        let Some(letter) = self.read_register()? else {
+            self.output.status_message.set_message(String::new());
+            return Ok(());
+        };
+        let cursor = &self.output.cursor_controller;
+        let pos = (cursor.cursor_y, cursor.cursor_x);
+        let message = if self.output.editor_rows.marks.toggle(letter, pos) {
+            format!("Mark {} set at {}:{}", letter, pos.0 + 1, pos.1 + 1)
+        } else {
+            format!("Mark {} deleted", letter)
+        };
+        self.output.status_message.set_message(message);
+        Ok(())
+    }
+
+    /// `Alt-P` followed by `s` splits the focused pane and `v` splits it
+    /// side by side, `w`, `j` or `l` moves to the next pane and `W`, `k` or
+    /// `h` to the one before, `count` panes away, `c` or `q` closes the
+    /// pane and `o` closes the others. `+` and `-` make the pane `count`
+    /// rows taller or shorter, `>` and `<` `count` columns wider or
+    /// narrower, and `=` makes all panes the same size.
+    fn window_command(&mut self, count: usize) -> std::result::Result<(), std::io::Error> {
+        let count = count as isize;
+        let output = &mut self.output;
+        match self.reader.read_key()?.code {
+            KeyCode::Char('s' | 'S') => {
+                output.split(Direction::Rows);
+            }
+            KeyCode::Char('v' | 'V') => {
+                output.split(Direction::Columns);
+            }
+            KeyCode::Char('w' | 'j' | 'l') | KeyCode::Down | KeyCode::Right => {
+                output.cycle_focus(count)
+            }
+            KeyCode::Char('W' | 'k' | 'h') | KeyCode::Up | KeyCode::Left => {
+                output.cycle_focus(-count)
+            }
+            KeyCode::Char('c' | 'q') => output.close_pane(),
+            KeyCode::Char('o') => output.only_pane(),
+            KeyCode::Char('+') => output.resize_pane(Direction::Rows, count),
+            KeyCode::Char('-') => output.resize_pane(Direction::Rows, -count),
+            KeyCode::Char('>') => output.resize_pane(Direction::Columns, count),
+            KeyCode::Char('<') => output.resize_pane(Direction::Columns, -count),
+            KeyCode::Char('=') => output.equalize_panes(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Lists the marks and jumps to the one named by the next key, or with
+    /// `linewise` to the first non-blank of its row, as `'` does in vim.
+    fn jump_to_mark(&mut self, linewise: bool) -> std::result::Result<(), std::io::Error> {
+        let marks = &self.output.editor_rows.marks;
+        if marks.is_empty() {
+            self.output.status_message.set_message("No marks set".into());
+            self.abort_replay();
+            return Ok(());
+        }
+        let message = format!("Jump to mark: {}", marks.summary());
+        self.output.status_message.set_message(message);
+        self.output.refresh_screen()?;
+        let Some(letter) = self.read_register()? else {
+            self.output.status_message.set_message(String::new());
+            return Ok(());
+        };
+        match self.output.editor_rows.marks.get(letter) {
+            Some((row, col)) => {
+                let col = if linewise {
+                    let row = self.output.editor_rows.get_row(row);
+                    row.len() - row.trim_start_matches([' ', '\t']).len()
+                } else {
+                    col
+                };
+                self.output.status_message.set_message(String::new());
+                self.jumps.push(self.output.jump_here());
+                self.output.jump_to(Jump {
+                    buffer: self.output.editor_rows.id,
+                    row,
+                    col,
+                });
+            }
+            None => {
+                self.output
+                    .status_message
+                    .set_message(format!("Mark {} not set", letter));
+                self.abort_replay()
+            }
+        }
+        Ok(())
+    }
+
+    /// Prompts for an action by name, listing the best matches with the key
+    /// bound to each, and runs the one chosen with Up, Down and Enter.
+    fn command_palette(&mut self) -> std::result::Result<bool, std::io::Error> {
+        let keymap = &self.output.config.keymap;
+        let actions: Vec<Action> = Action::all()
+            .filter(|&action| action != Action::CommandPalette)
+            .collect();
+        let titles: Vec<String> = actions
+            .iter()
+            .map(|action| action.name().replace('_', " "))
+            .collect();
+        let keys: Vec<String> = actions
+            .iter()
+            .map(|&action| keymap.key_for(action).unwrap_or_default())
+            .collect();
+        let menu = |shown: &[usize], selected| Menu {
+            items: shown
+                .iter()
+                .take(MENU_ROWS)
+                .map(|&index| (titles[index].clone(), keys[index].clone()))
+                .collect(),
+            selected,
+            marked: Vec::new(),
+        };
+        let mut shown = fuzzy::rank("", titles.iter().map(String::as_str));
+        self.output.menu = Some(menu(&shown, 0));
+        let mut callback = |output: &mut Output,
+                            _: &mut String,
+                            input: &str,
+                            key: Option<&KeyEvent>| {
+            let Some(key) = key else {
+                return false;
+            };
+            let selected = output.menu.as_ref().map_or(0, |menu| menu.selected);
+            let selected = match key.code {
+                KeyCode::Up => (selected + 1).min(shown.len().min(MENU_ROWS).saturating_sub(1)),
+                KeyCode::Down => selected.saturating_sub(1),
+                _ => {
+                    shown = fuzzy::rank(input, titles.iter().map(String::as_str));
+                    0
+                }
+            };
+            output.menu = Some(menu(&shown, selected));
+            true
+        };
+        // The palette keeps no history, so Up and Down move in the menu.
+        let input = prompt(
+            &mut self.reader,
+            &mut self.output,
+            "Command: ",
+            &mut History::default(),
+            None,
+            Some(&mut callback),
+        )?;
+        let selected = self.output.menu.take().map_or(0, |menu| menu.selected);
+        match input.and_then(|_| shown.get(selected)) {
+            Some(&index) => self.dispatch_action(actions[index], 1),
+            None => Ok(true),
+        }
+    }
+
+    /// Prompts for a file under the working directory, listing the paths
+    /// that best match what is typed as they are found, and opens the one
+    /// chosen with Up, Down and Enter.
+    fn find_file(&mut self) -> std::result::Result<(), std::io::Error> {
+        let mut walk = FileWalk::spawn(PathBuf::from("."));
+        let mut paths: Vec<String> = Vec::new();
+        let mut shown: Vec<usize> = Vec::new();
+        let mut callback = |output: &mut Output,
+                            message: &mut String,
+                            input: &str,
+                            key: Option<&KeyEvent>| {
+            let selected = output.menu.as_ref().map_or(0, |menu| menu.selected);
+            let selected = match key.map(|key| key.code) {
+                Some(KeyCode::Up) => {
+                    (selected + 1).min(shown.len().min(MENU_ROWS).saturating_sub(1))
+                }
+                Some(KeyCode::Down) => selected.saturating_sub(1),
+                None if !walk.receive(&mut paths) => return false,
+                _ => {
+                    *message = format!(
+                        "Open file ({}{}): ",
+                        group_digits(paths.len()),
+                        if walk.finished() { "" } else { "…" }
+                    );
+                    shown = fuzzy::rank(input, paths.iter().map(String::as_str));
+                    0
+                }
+            };
+            output.menu = Some(Menu {
+                items: shown
+                    .iter()
+                    .take(MENU_ROWS)
+                    .map(|&index| (paths[index].clone(), String::new()))
+                    .collect(),
+                selected,
+                marked: Vec::new(),
+            });
+            true
+        };
+        let input = prompt(
+            &mut self.reader,
+            &mut self.output,
+            "Open file: ",
+            &mut History::default(),
+            None,
+            Some(&mut callback),
+        )?;
+        let selected = self.output.menu.take().map_or(0, |menu| menu.selected);
+        if let Some(&index) = input.and(shown.get(selected)) {
+            self.open_file(&paths[index]);
+        }
+        Ok(())
+    }
+
+    /// Searches the files under the current directory for `text`, prompting
+    /// for it if not given, and lists the matches.
+    fn find_in_files(&mut self, text: Option<String>) -> std::result::Result<(), std::io::Error> {
+        let text = match text {
+            Some(text) => text,
+            None => {
+                let typed = prompt(
+                    &mut self.reader,
+                    &mut self.output,
+                    "Find in files: ",
+                    &mut self.history.search,
+                    None,
+                    None,
+                )?;
+                match typed {
+                    Some(text) => text,
+                    None => return Ok(()),
+                }
+            }
+        };
+        self.grep = Some(GrepResults {
+            job: GrepJob::spawn(PathBuf::from("."), Query::new(text.clone())),
+            text,
+            matches: Vec::new(),
+            current: None,
+        });
+        self.grep_list()
+    }
+
+    /// Lists the matches of the last search across files as they are
+    /// found, `path:line: row` with the match highlighted, and opens the
+    /// one chosen with Up, Down and Enter. Escape closes the list, keeping
+    /// the matches for `]q` and `[q`.
+    fn grep_list(&mut self) -> std::result::Result<(), std::io::Error> {
+        let rows = MENU_ROWS.min(self.output.win_size.1);
+        let mut selected = self.grep.as_ref().and_then(|grep| grep.current).unwrap_or(0);
+        loop {
+            let Some(grep) = &mut self.grep else {
+                return Ok(());
+            };
+            grep.receive();
+            let count = grep.matches.len();
+            if count == 0 && grep.job.finished() {
+                self.output.menu = None;
+                let message = format!("No matches for {}", grep.text);
+                self.output.status_message.set_message(message);
+                return Ok(());
+            }
+            selected = selected.min(count.saturating_sub(1));
+            let bottom = selected.saturating_sub(rows - 1);
+            let (items, marked) = grep.matches[bottom.min(count)..]
+                .iter()
+                .take(rows)
+                .map(|found| {
+                    let prefix = format!("{}:{}: ", found.path, found.row + 1);
+                    // Tabs and other control chars would upset the list.
+                    let line: String = found
+                        .line
+                        .chars()
+                        .map(|ch| if ch.is_control() { ' ' } else { ch })
+                        .collect();
+                    let trimmed = line.trim_start();
+                    let indent = line.chars().count() - trimmed.chars().count();
+                    let start = prefix.chars().count()
+                        + found.line[..found.col].chars().count().saturating_sub(indent);
+                    let marked = start..start + grep.text.chars().count();
+                    ((format!("{}{}", prefix, trimmed), String::new()), marked)
+                })
+                .unzip();
+            self.output.menu = Some(Menu {
+                items,
+                selected: selected - bottom,
+                marked,
+            });
+            let message = format!("{}: Enter opens, Escape closes", grep.summary());
+            self.output.status_message.set_message(message);
+            self.output.refresh_screen()?;
+            if !grep.job.finished() && !self.reader.key_ready(Duration::from_millis(50))? {
+                continue;
+            }
+            match self.reader.read_key()? {
+                KeyEvent {
+                    code: KeyCode::Enter,
+                    ..
+                } if count > 0 => {
+                    self.output.menu = None;
+                    self.output.status_message.set_message(String::new());
+                    self.open_match(selected);
+                    return Ok(());
+                }
+                KeyEvent {
+                    code: KeyCode::Esc, ..
+                }
+                | KeyEvent {
+                    code: KeyCode::Char('c'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                } => {
+                    self.output.menu = None;
+                    self.output.status_message.set_message(String::new());
+                    return Ok(());
+                }
+                KeyEvent {
+                    code: KeyCode::Up, ..
+                } => selected = (selected + 1).min(count.saturating_sub(1)),
+                KeyEvent {
+                    code: KeyCode::Down,
+                    ..
+                } => selected = selected.saturating_sub(1),
+                KeyEvent {
+                    code: KeyCode::PageUp,
+                    ..
+                } => selected = (selected + rows).min(count.saturating_sub(1)),
+                KeyEvent {
+                    code: KeyCode::PageDown,
+                    ..
+                } => selected = selected.saturating_sub(rows),
+                _ => {}
+            }
+        }
+    }
+
+    /// Opens the file of match `index` of the last search across files at
+    /// the match.
+    fn open_match(&mut self, index: usize) {
+        let Some(grep) = &mut self.grep else {
+            return;
+        };
+        grep.current = Some(index);
+        let found = grep.matches[index].clone();
+        let capped = grep.matches.len() >= grep::MAX_MATCHES;
+        let message = format!(
+            "Match {} of {}{}",
+            index + 1,
+            grep.matches.len(),
+            if capped { "+" } else { "" }
+        );
+        self.open_file(&found.path);
+        if let Some(buffer) = self.output.buffer_for(Path::new(&found.path)) {
+            self.output.jump_to(Jump {
+                buffer,
+                row: found.row,
+                col: found.col,
+            });
+            self.output.status_message.set_message(message);
+        }
+    }
+
+    /// Opens the match `count` after the one last opened from the last
+    /// search across files, or before it with `back`.
+    fn step_match(&mut self, back: bool, count: usize) {
+        let Some(grep) = &mut self.grep else {
+            self.output
+                .status_message
+                .set_message("No search in files; find_in_files runs one".into());
+            self.abort_replay();
+            return;
+        };
+        grep.receive();
+        let target = match grep.current {
+            None => Some(0),
+            Some(current) if back => current.checked_sub(count),
+            Some(current) => Some(current + count),
+        };
+        match target.filter(|&index| index < grep.matches.len()) {
+            Some(index) => self.open_match(index),
+            None => {
+                self.output.status_message.set_message("No more matches".into());
+                self.abort_replay();
+            }
+        }
+    }
+
+    /// Lists the open buffers with their sizes, narrowed down by a fuzzy
+    /// match of their names, and shows the one chosen with Up, Down and
+    /// Enter. Ctrl-D closes the highlighted buffer, if it has unsaved
+    /// changes only when pressed twice.
+    fn list_buffers(&mut self) -> std::result::Result<(), std::io::Error> {
+        let label = "Buffer (Ctrl-D closes): ";
+        let mut shown: Vec<usize> = Vec::new();
+        let mut close_warned = None;
+        let mut callback = |output: &mut Output,
+                            message: &mut String,
+                            input: &str,
+                            key: Option<&KeyEvent>| {
+            let Some(key) = key else {
+                return false;
+            };
+            let selected = output.menu.as_ref().map_or(0, |menu| menu.selected);
+            let closing = key.code == KeyCode::Char('d') && key.modifiers == KeyModifiers::CONTROL;
+            let mut selected = match key.code {
+                KeyCode::Up => (selected + 1).min(shown.len().min(MENU_ROWS).saturating_sub(1)),
+                KeyCode::Down => selected.saturating_sub(1),
+                _ if closing && shown.is_empty() => return false,
+                _ if closing => {
+                    let id = output.buffer_order[shown[selected]];
+                    if output.buffer(id).dirty > 0 && close_warned != Some(id) {
+                        *message = format!(
+                            "{} has unsaved changes. Ctrl-D again to close it anyway: ",
+                            output.buffer(id).display_name()
+                        );
+                        close_warned = Some(id);
+                        return true;
+                    }
+                    output.close_buffer(id);
+                    selected
+                }
+                _ => 0,
+            };
+            if !closing {
+                close_warned = None;
+                *message = label.into();
+            }
+            let names: Vec<String> = output
+                .buffer_order
+                .iter()
+                .map(|&id| output.buffer(id).display_name().to_string())
+                .collect();
+            shown = if input.is_empty() {
+                (0..names.len()).collect()
+            } else {
+                fuzzy::rank(input, names.iter().map(String::as_str))
+            };
+            selected = selected.min(shown.len().min(MENU_ROWS).saturating_sub(1));
+            output.menu = Some(Menu {
+                items: shown
+                    .iter()
+                    .take(MENU_ROWS)
+                    .map(|&index| {
+                        let editor_rows = output.buffer(output.buffer_order[index]);
+                        let label = format!(
+                            "{}: {}{}",
+                            index + 1,
+                            names[index],
+                            if editor_rows.dirty > 0 { " (modified)" } else { "" }
+                        );
+                        (label, format!("{} lines", editor_rows.number_of_rows()))
+                    })
+                    .collect(),
+                selected,
+                marked: Vec::new(),
+            });
+            true
+        };
+        // Draws the list before the first key.
+        callback(&mut self.output, &mut String::new(), "", Some(&KeyEvent::from(KeyCode::Null)));
+        let input = prompt(
+            &mut self.reader,
+            &mut self.output,
+            label,
+            &mut History::default(),
+            None,
+            Some(&mut callback),
+        )?;
+        let selected = self.output.menu.take().map_or(0, |menu| menu.selected);
+        self.forget_closed_buffers();
+        if let Some(&index) = input.and(shown.get(selected)) {
+            let id = self.output.buffer_order[index];
+            if id != self.output.editor_rows.id {
+                self.jumps.push(self.output.jump_here());
+                self.output.show_buffer(id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes the focused pane's buffer. Unless `force` discards them, its
+    /// unsaved changes are saved or discarded as the user answers, or the
+    /// buffer is kept open.
+    fn close_buffer(&mut self, force: bool) -> std::result::Result<(), std::io::Error> {
+        if self.output.editor_rows.dirty > 0 && !force {
+            let message = format!(
+                "Save changes to {}? (y)es, (n)o, (c)ancel",
+                self.output.editor_rows.display_name()
+            );
+            self.output.status_message.set_message(message);
+            self.output.refresh_screen()?;
+            match self.reader.read_key()?.code {
+                KeyCode::Char('y') => {
+                    if !self.save()? {
+                        return Ok(());
+                    }
+                }
+                KeyCode::Char('n') => {}
+                _ => {
+                    self.output.status_message.set_message(String::new());
+                    return Ok(());
+                }
+            }
+        }
+        let name = self.output.editor_rows.display_name().to_string();
+        self.output.close_buffer(self.output.editor_rows.id);
+        self.forget_closed_buffers();
+        self.output.status_message.set_message(format!("Closed {}", name));
+        Ok(())
+    }
+
+    /// Drops what refers to buffers that have been closed.
+    fn forget_closed_buffers(&mut self) {
+        let open = &self.output.buffer_order;
+        self.jumps.retain(|buffer| open.contains(&buffer));
+    }
+
+    fn goto_line(&mut self) -> std::result::Result<(), std::io::Error> {
+        let input = prompt(
+            &mut self.reader,
+            &mut self.output,
+            "Goto line: ",
+            &mut self.history.goto,
+            None,
+            None,
+        )?;
+        if let Some(input) = input {
+            match input.trim().parse::<usize>() {
+                Ok(line) => {
+                    self.jumps.push(self.output.jump_here());
+                    self.output
+                        .cursor_controller
+                        .goto_line(line, &self.output.editor_rows)
+                }
+                Err(_) => {
+                    self.output
+                        .status_message
+                        .set_message(format!("Invalid line number: {}", input));
+                    self.abort_replay()
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves the buffer, asking for a file name if it has none. Returns
+    /// whether the file was written.
+    fn save(&mut self) -> std::result::Result<bool, std::io::Error> {
+        if !self.output.editable() {
+            return Ok(false);
+        }
+        if self.output.editor_rows.filename.is_none() {
+            match prompt(&mut self.reader, &mut self.output, "Save as: ", &mut self.history.file, None, None)? {
+                None => {
+                    self.output
+                        .status_message
+                        .set_message("Save Aborted".into());
+                    return Ok(false);
+                }
+                Some(name) => self.output.editor_rows.filename = Some(name.into()),
+            }
+        }
+        if self.output.config.strip_trailing_whitespace_on_save
+            && self.output.editor_rows.strip_trailing_whitespace()
+        {
+            self.output.editor_rows.dirty += 1;
+            let cursor_controller = &mut self.output.cursor_controller;
+            cursor_controller.cursor_x = cmp::min(
+                cursor_controller.cursor_x,
+                self.output.editor_rows.get_row(cursor_controller.cursor_y).len(),
+            );
+        }
+        if self.output.config.final_newline
+            && self
+                .output
+                .editor_rows
+                .ensure_final_newline(self.output.config.collapse_trailing_blank_lines)
+        {
+            self.output.editor_rows.dirty += 1;
+            let cursor_controller = &mut self.output.cursor_controller;
+            let last_row = self.output.editor_rows.number_of_rows() - 1;
+            if cursor_controller.cursor_y > last_row {
+                cursor_controller.cursor_y = last_row;
+                cursor_controller.cursor_x = self.output.editor_rows.get_row(last_row).len();
+            }
+        }
+        match self.output.editor_rows.save(self.output.config.make_backup) {
+            Ok(len) => {
+                self.output
+                    .status_message
+                    .set_message(format!("{} bytes written to disk", len));
+                self.output.editor_rows.dirty = 0;
+                let cursor = self.output.cursor();
+                self.output.editor_rows.undo.mark_saved(cursor);
+                Ok(true)
+            }
+            Err(err) => {
+                self.output
+                    .status_message
+                    .set_message(format!("Can't save! I/O error: {}", err));
+                Ok(false)
+            }
+        }
+    }
+
+    /// Incremental search, forward from the cursor or backward, prompting
+    /// with `label`. The scan runs on a worker thread which is restarted
+    /// whenever the query changes; Right/Left move to the next or previous
+    /// match and Escape returns to where the search started. An empty query
+    /// looks for the last one again.
+    fn find(
+        &mut self,
+        label: &str,
+        direction: SearchDirection,
+    ) -> std::result::Result<(), std::io::Error> {
+        let cursor_controller = &self.output.cursor_controller;
+        let saved_cursor = (cursor_controller.cursor_y, cursor_controller.cursor_x);
+        let saved_offsets = (cursor_controller.row_offset, cursor_controller.column_offset);
+        let search_job = &mut self.search_job;
+        self.output.clear_search();
+        let mut last_query = Query::default();
+        let mut whole_word = false;
+        let mut callback = |output: &mut Output,
+                            message: &mut String,
+                            input: &str,
+                            key: Option<&KeyEvent>| {
+            if let Some(KeyEvent {
+                code: KeyCode::Char('w'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            }) = key
+            {
+                whole_word = !whole_word;
+                *message = if whole_word {
+                    format!("{} [word]: ", label)
+                } else {
+                    format!("{}: ", label)
+                };
+            }
+            let query = Query {
+                text: input.to_string(),
+                whole_word,
+            };
+            let restart = match key {
+                Some(KeyEvent {
+                    code: KeyCode::Right,
+                    ..
+                }) => output
+                    .search_match
+                    .map(|(row, col)| (query, (row, col + 1), SearchDirection::Forward)),
+                Some(KeyEvent {
+                    code: KeyCode::Left,
+                    ..
+                }) => output
+                    .search_match
+                    .map(|found| (query, found, SearchDirection::Backward)),
+                Some(_) if query != last_query => {
+                    last_query = query.clone();
+                    Some((query, saved_cursor, direction))
+                }
+                _ => None,
+            };
+            if let Some((query, from, direction)) = restart {
+                *search_job = None;
+                output.clear_search();
+                if !query.text.is_empty() {
+                    let snapshot = output.editor_rows.snapshot();
+                    *search_job = Some(SearchJob::spawn(snapshot, query.clone(), from, direction));
+                    output.search_query = Some(query);
+                    output.search_status = Some("searching…".into());
+                }
+                return true;
+            }
+            output.poll_search(search_job)
+        };
+        let query = prompt(
+            &mut self.reader,
+            &mut self.output,
+            &format!("{}: ", label),
+            &mut self.history.search,
+            None,
+            Some(&mut callback),
+        )?;
+        if query.as_deref() == Some("") {
+            match self.last_search.clone() {
+                Some((query, _)) => {
+                    let from = match direction {
+                        SearchDirection::Forward => (saved_cursor.0, saved_cursor.1 + 1),
+                        SearchDirection::Backward => saved_cursor,
+                    };
+                    self.search_from(query, from, direction);
+                }
+                None => self.output.status_message.set_message("No previous search".into()),
+            }
+            return Ok(());
+        }
+        if !self.output.config.persist_search_highlight {
+            self.output.search_query = None;
+        }
+        if query.is_none() {
+            self.search_job = None;
+            self.output.clear_search();
+            let cursor_controller = &mut self.output.cursor_controller;
+            (cursor_controller.cursor_y, cursor_controller.cursor_x) = saved_cursor;
+            (cursor_controller.row_offset, cursor_controller.column_offset) = saved_offsets;
+        } else {
+            self.last_search = Some((last_query, direction));
+            self.jumps.push(Jump {
+                buffer: self.output.editor_rows.id,
+                row: saved_cursor.0,
+                col: saved_cursor.1,
+            });
+            // Keys replayed after the search expect the cursor on the match.
+            if !self.reader.pending.is_empty() {
+                while self.search_job.is_some() {
+                    self.output.poll_search(&mut self.search_job);
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+                if self.output.search_match.is_none() {
+                    self.abort_replay();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Searches for the word under or after the cursor as a whole word,
+    /// forward for `*` or backward for `#`, highlighting every match.
+    fn search_word(&mut self, forward: bool) {
+        let (row, col) = self.output.cursor();
+        let editor_row = self.output.editor_rows.get_editor_row(row);
+        let Some(word) = editor_row.word_at(col) else {
+            self.output.status_message.set_message("No word under the cursor".into());
+            self.abort_replay();
+            return;
+        };
+        let query = Query {
+            text: editor_row.row_content[word.clone()].to_string(),
+            whole_word: true,
+        };
+        let (from, direction) = if forward {
+            ((row, word.start + 1), SearchDirection::Forward)
+        } else {
+            ((row, word.start), SearchDirection::Backward)
+        };
+        self.search_from(query, from, direction);
+    }
+
+    /// Looks for the last search's query again from the cursor, the same way
+    /// for `n` or the other way for `N`.
+    fn search_again(&mut self, same_way: bool) {
+        let Some((query, direction)) = self.last_search.clone() else {
+            self.output.status_message.set_message("No previous search".into());
+            self.abort_replay();
+            return;
+        };
+        let (row, col) = self.output.cursor();
+        let way = match (direction, same_way) {
+            (SearchDirection::Forward, true) | (SearchDirection::Backward, false) => {
+                SearchDirection::Forward
+            }
+            _ => SearchDirection::Backward,
+        };
+        let from = match way {
+            SearchDirection::Forward => (row, col + 1),
+            SearchDirection::Backward => (row, col),
+        };
+        self.search_from(query, from, way);
+        // `n` keeps going the way the search first went.
+        if let Some((_, last)) = self.last_search.as_mut() {
+            *last = direction;
+        }
+    }
+
+    /// Starts looking for `query` from `from`, moving the cursor to the
+    /// match when it is found and remembering the search for `n` and `N`.
+    fn search_from(&mut self, query: Query, from: (usize, usize), direction: SearchDirection) {
+        self.jumps.push(self.output.jump_here());
+        self.output.clear_search();
+        let snapshot = self.output.editor_rows.snapshot();
+        self.search_job = Some(SearchJob::spawn(snapshot, query.clone(), from, direction));
+        self.output.search_query = Some(query.clone());
+        self.output.search_status = Some("searching…".into());
+        self.last_search = Some((query, direction));
+        // Keys replayed after the search expect the cursor on the match.
+        if !self.reader.pending.is_empty() {
+            while self.search_job.is_some() {
+                self.output.poll_search(&mut self.search_job);
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            if self.output.search_match.is_none() {
+                self.abort_replay();
+            }
+        }
+    }
+
+    /// Replaces matches of a query one by one after asking for each, within
+    /// the selection if there is one and in the whole buffer otherwise. The
+    /// selection is kept covering the same text afterwards.
+    fn replace(&mut self) -> std::result::Result<(), std::io::Error> {
+        if !self.output.editable() {
+            return Ok(());
+        }
+        let rows = &self.output.editor_rows;
+        let last_row = rows.number_of_rows() - 1;
+        let selection = self.output.selection();
+        let (start, mut end) =
+            selection.unwrap_or(((0, 0), (last_row, rows.get_row(last_row).len())));
+        let Some(query) = prompt(
+            &mut self.reader,
+            &mut self.output,
+            "Replace: ",
+            &mut self.history.search,
+            None,
+            None,
+        )?
+        else {
+            return Ok(());
+        };
+        let Some(with) = prompt(
+            &mut self.reader,
+            &mut self.output,
+            "Replace with: ",
+            &mut self.history.replace,
+            None,
+            None,
+        )?
+        else {
+            return Ok(());
+        };
+        let mut total = 0;
+        let mut from = start;
+        while let Some((row, col)) = self.output.editor_rows.find_in(&query, from, end) {
+            total += 1;
+            from = (row, col + query.len());
+        }
+        if total == 0 {
+            self.output.status_message.set_message(format!("No matches for {}", query));
+            self.abort_replay();
+            return Ok(());
+        }
+        // The cursor visits each match, so stop drawing the selection.
+        self.output.selection_anchor = None;
+        self.search_job = None;
+        self.output.clear_search();
+        self.output.search_query = Some(Query::new(query.clone()));
+        let (mut index, mut replaced, mut all) = (0, 0, false);
+        let mut from = start;
+        while let Some((row, col)) = self.output.editor_rows.find_in(&query, from, end) {
+            index += 1;
+            from = (row, col + query.len());
+            if !all {
+                self.output.search_match = Some((row, col));
+                self.output.cursor_controller.cursor_y = row;
+                self.output.cursor_controller.cursor_x = col;
+                self.output.status_message.set_message(format!(
+                    "Replace this match? (y/n/a/q) {} of {}",
+                    index, total
+                ));
+                self.output.refresh_screen()?;
+                match self.reader.read_key()?.code {
+                    KeyCode::Char('y') => {}
+                    KeyCode::Char('a') => all = true,
+                    KeyCode::Char('n') => continue,
+                    _ => break,
+                }
+            }
+            self.output.editor_rows.replace_in_row((row, col), query.len(), &with);
+            if row == end.0 {
+                end.1 = end.1 + with.len() - query.len();
+            }
+            from = (row, col + with.len());
+            replaced += 1;
+            self.output.editor_rows.dirty += 1;
+        }
+        self.output.search_match = None;
+        if !self.output.config.persist_search_highlight {
+            self.output.search_query = None;
+        }
+        if selection.is_some() {
+            let cursor_controller = &mut self.output.cursor_controller;
+            cursor_controller.cursor_y = end.0;
+            cursor_controller.cursor_x = self.output.editor_rows.get_editor_row(end.0).prev_boundary(end.1);
+            self.output.selection_anchor = Some(start);
+        }
+        self.output
+            .status_message
+            .set_message(format!("Replaced {} of {} matches", replaced, total));
+        Ok(())
+    }
+
+    /// Shows `file` in the focused pane, opening a buffer for it unless
+    /// one is already open.
+    fn open_file(&mut self, file: &str) {
+        if let Some(id) = self.output.buffer_for(Path::new(file)) {
+            self.jumps.push(self.output.jump_here());
+            self.output.show_buffer(id);
+            return;
+        }
+        match EditorRows::from_file(file.into(), &self.output.config) {
+            Ok(editor_rows) => {
+                self.jumps.push(self.output.jump_here());
+                self.output.add_buffer(editor_rows);
+            }
+            Err(err) => self
+                .output
+                .status_message
+                .set_message(format!("Can't open {}: {}", file, err)),
+        }
+    }
+
+    fn reload(&mut self) {
+        if let Err(err) = self.output.reload() {
+            self.output
+                .status_message
+                .set_message(format!("Can't reload: {}", err))
+        }
+    }
+
+    fn command_line(&mut self) -> std::result::Result<bool, std::io::Error> {
+        let selected = self.output.selection().map(|(start, end)| start.0..=end.0);
+        self.output.set_mode(Mode::Command);
+        let input = prompt(
+            &mut self.reader,
+            &mut self.output,
+            ":",
+            &mut self.history.command,
+            None,
+            None,
+        )?;
+        self.output.set_mode(Mode::Normal);
+        match input {
+            Some(cmd) => self.execute_command(&cmd, selected),
+            None => Ok(true),
+        }
+    }
+
+    /// Runs an ex-style command typed after `:`, with the rows that were
+    /// `selected` for those acting on rows. Returns `false` when the editor
+    /// should quit.
+    fn execute_command(
+        &mut self,
+        cmd: &str,
+        selected: Option<RangeInclusive<usize>>,
+    ) -> std::result::Result<bool, std::io::Error> {
+        match command::parse(cmd) {
+            Ok(command) => self.run_command(command, selected),
+            Err(err) => {
+                self.output.status_message.set_message(err);
+                self.abort_replay();
+                Ok(true)
+            }
+        }
+    }
+
+    fn run_command(
+        &mut self,
+        command: Command,
+        selected: Option<RangeInclusive<usize>>,
+    ) -> std::result::Result<bool, std::io::Error> {
+        // Commands acting on rows take the selected ones, or else all.
+        let rows = selected
+            .clone()
+            .unwrap_or(0..=self.output.editor_rows.number_of_rows() - 1);
+        match command {
+            Command::Write => {
+                self.save()?;
+            }
+            Command::Quit { force: false } if !self.output.modified_buffers().is_empty() => {
+                let message = format!(
+                    "No write since last change to {} (add ! to override)",
+                    self.output.modified_buffers().join(", ")
+                );
+                self.output.status_message.set_message(message)
+            }
+            Command::Quit { .. } => return Ok(false),
+            Command::WriteQuit => {
+                if !self.save()? {
+                    return Ok(true);
+                }
+                let modified = self.output.modified_buffers();
+                if modified.is_empty() {
+                    return Ok(false);
+                }
+                let message = format!("No write since last change to {}", modified.join(", "));
+                self.output.status_message.set_message(message)
+            }
+            Command::Edit { path } => self.open_file(&path),
+            Command::Reload { force: false } if self.output.editor_rows.dirty > 0 => self
+                .output
+                .status_message
+                .set_message("No write since last change (add ! to override)".into()),
+            Command::Reload { .. } => self.reload(),
+            Command::NextBuffer => self.output.cycle_buffer(1),
+            Command::PreviousBuffer => self.output.cycle_buffer(-1),
+            Command::ListBuffers => self.list_buffers()?,
+            Command::Grep { text: None } if self.grep.is_some() => self.grep_list()?,
+            Command::Grep { text } => self.find_in_files(text)?,
+            Command::ClosePane => self.output.close_pane(),
+            Command::OnlyPane => self.output.only_pane(),
+            Command::CloseBuffer { force } => self.close_buffer(force)?,
+            Command::Split { direction, path } => {
+                if let Some(path) = path.filter(|_| self.output.split(direction)) {
+                    self.open_file(&path);
+                }
+            }
+            Command::Set { option, value } => {
+                match self.output.config.set(&option, &value) {
+                    // Indentation settings also apply to the open buffer.
+                    Ok(()) if option == "tab_stop" => {
+                        let tab_stop = self.output.config.tab_stop;
+                        self.output.editor_rows.set_tab_stop(tab_stop);
+                    }
+                    Ok(()) if option == "soft_tabs" => {
+                        self.output.editor_rows.soft_tabs = self.output.config.soft_tabs
+                    }
+                    Ok(()) if option == "mouse" => {
+                        if self.output.config.mouse {
+                            execute!(stdout(), event::EnableMouseCapture)?
+                        } else {
+                            execute!(stdout(), event::DisableMouseCapture)?
+                        }
+                    }
+                    Ok(()) => {}
+                    Err(err) => self.output.status_message.set_message(err),
+                }
+            }
+            Command::Sort(_) | Command::Reverse | Command::Dedup { .. } | Command::Align(_)
+                if self.output.editable() =>
+            {
+                let (first, last) = rows.clone().into_inner();
+                // However many rows change, they are undone together.
+                let remaining = self.output.undo_step(|output| match command {
+                    Command::Sort(options) => {
+                        let removed = output.editor_rows.sort_rows(rows, options);
+                        if options.unique {
+                            output
+                                .status_message
+                                .set_message(format!("{} duplicate rows removed", removed));
+                        }
+                        last - removed
+                    }
+                    Command::Reverse => {
+                        output.editor_rows.reverse_rows(rows);
+                        last
+                    }
+                    Command::Align(delimiter) => {
+                        output.editor_rows.align_rows(rows, delimiter);
+                        last
+                    }
+                    _ => {
+                        let all = matches!(command, Command::Dedup { all: true });
+                        let removed = output.editor_rows.dedup_rows(rows, !all);
+                        output
+                            .status_message
+                            .set_message(format!("{} duplicate rows removed", removed));
+                        last - removed
+                    }
+                });
+                self.output.clamp_cursor();
+                // The selection still covers the same rows.
+                if selected.is_some() {
+                    self.output.select_rows(first..=remaining);
+                }
+            }
+            Command::Sort(_) | Command::Reverse | Command::Dedup { .. } | Command::Align(_) => {}
+            Command::Diff => self.output.toggle_diff(),
+            Command::Hex { off } => {
+                if !off || self.output.editor_rows.hex.is_some() {
+                    self.output.toggle_hex();
+                }
+            }
+            Command::Marks => {
+                let marks = &self.output.editor_rows.marks;
+                let message = if marks.is_empty() {
+                    "No marks set".into()
+                } else {
+                    format!("Marks: {}", marks.summary())
+                };
+                self.output.status_message.set_message(message)
+            }
+            Command::Jumps => {
+                let message = if self.jumps.is_empty() {
+                    "No jumps yet".into()
+                } else {
+                    let output = &self.output;
+                    let summary = self.jumps.summary(|jump| {
+                        let position = format!("{}:{}", jump.row + 1, jump.col + 1);
+                        if jump.buffer == output.editor_rows.id {
+                            position
+                        } else {
+                            format!("{}:{}", output.buffer(jump.buffer).display_name(), position)
+                        }
+                    });
+                    format!("Jumps: {}", summary)
+                };
+                self.output.status_message.set_message(message)
+            }
+            Command::Goto(line) => {
+                self.jumps.push(self.output.jump_here());
+                self.output
+                    .cursor_controller
+                    .goto_line(line, &self.output.editor_rows)
+            }
+        }
+        Ok(true)
+    }
+
+    fn process_keypress(&mut self) -> std::result::Result<bool, std::io::Error> { /* modify*/
+        let replaying = !self.reader.pending.is_empty();
+        let key = match self.reader.read_input()? {
+            Input::Key(key) => key,
+            Input::Mouse(event) => {
+                self.quit_warned = false;
+                self.mouse(event);
+                return Ok(true);
+            }
+        };
+        self.last_key = Instant::now();
+        if self.output.config.keymap.resolve(&key) != Some(Action::Quit) {
+            self.quit_warned = false;
+        }
+        if !self.keeps_block(&key) {
+            self.output.block = None;
+        }
+        // Each key typed outside of Insert mode starts a new step to undo.
+        // Keys being replayed, as for `.` or a macro, belong to the step of
+        // the key that replays them, even edits that are otherwise steps of
+        // their own.
+        self.output.undo_grouped = replaying;
+        if let Mode::Normal | Mode::Visual | Mode::Command = self.output.mode {
+            if !replaying {
+                self.output.seal_undo();
+            }
+        }
+        if self.output.editor_rows.hex.is_some() {
+            return self.process_hex_key(key);
+        }
+        if let Some(result) = self.history_window_key(key) {
+            return result;
+        }
+        match self.output.mode {
+            Mode::Insert => self.process_insert_key(key),
+            Mode::Replace => self.process_replace_key(key),
+            Mode::OperatorPending(op) => self.process_operator_key(op, key),
+            Mode::Normal | Mode::Visual | Mode::Command => self.process_normal_key(key),
+        }
+    }
+
+    /// Moves about the hex view by byte. Keys that would edit are ignored,
+    /// as are bound actions other than those leaving the buffer or the
+    /// editor.
+    fn process_hex_key(&mut self, key: KeyEvent) -> std::result::Result<bool, std::io::Error> {
+        let screen_rows = self.output.cursor_controller.screen_rows as isize;
+        let Some(hex) = &mut self.output.editor_rows.hex else {
+            return Ok(true);
+        };
+        let row = hex::BYTES_PER_ROW as isize;
+        let index = (hex.cursor % hex::BYTES_PER_ROW) as isize;
+        let plain = matches!(key.modifiers, KeyModifiers::NONE | KeyModifiers::SHIFT);
+        let delta = match key.code {
+            KeyCode::Char('h') | KeyCode::Left if plain => -1,
+            KeyCode::Char('l') | KeyCode::Right if plain => 1,
+            KeyCode::Char('k') | KeyCode::Up if plain => -row,
+            KeyCode::Char('j') | KeyCode::Down if plain => row,
+            KeyCode::PageUp if plain => -row * screen_rows,
+            KeyCode::PageDown if plain => row * screen_rows,
+            KeyCode::Char('0') | KeyCode::Home if plain => -index,
+            KeyCode::Char('$') | KeyCode::End if plain => row - 1 - index,
+            KeyCode::Char('g') if plain => -(hex.cursor as isize),
+            KeyCode::Char('G') if plain => isize::MAX,
+            KeyCode::Char(':') if plain => return self.command_line(),
+            _ => {
+                return match self.output.config.keymap.resolve(&key) {
+                    Some(
+                        action @ (Action::Quit
+                        | Action::ToggleHex
+                        | Action::NextBuffer
+                        | Action::PreviousBuffer
+                        | Action::ListBuffers
+                        | Action::CloseBuffer
+                        | Action::FindFile
+                        | Action::Window
+                        | Action::CommandPalette),
+                    ) => self.dispatch_action(action, 1),
+                    _ => Ok(true),
+                };
+            }
+        };
+        hex.move_by(delta);
+        Ok(true)
+    }
+
+    /// Adds `key` to the pending count if it is a digit that continues it.
+    /// A leading `0` is a motion rather than a count.
+    fn take_count_digit(&mut self, key: &KeyEvent) -> bool {
+        let KeyEvent {
+            code: KeyCode::Char(digit @ '0'..='9'),
+            modifiers: KeyModifiers::NONE,
+            ..
+        } = *key
+        else {
+            return false;
+        };
+        if digit == '0' && self.output.pending_count.is_none() {
+            return false;
+        }
+        let digit = digit.to_digit(10).unwrap() as usize;
+        let count = self.output.pending_count.unwrap_or(0);
+        self.output.pending_count = Some(count.saturating_mul(10).saturating_add(digit));
+        true
+    }
+
+    /// The motion a char key stands for, outside of `g` prefixed ones.
+    fn motion_for(ch: char) -> Option<Motion> {
+        let motion = match ch {
+            'h' => Motion::Left,
+            'l' => Motion::Right,
+            'j' => Motion::Down,
+            'k' => Motion::Up,
+            'w' | 'W' => Motion::Word(WordMotion::NextStart, ch == 'W'),
+            'b' | 'B' => Motion::Word(WordMotion::PrevStart, ch == 'B'),
+            'e' | 'E' => Motion::Word(WordMotion::NextEnd, ch == 'E'),
+            '0' => Motion::LineStart,
+            '$' => Motion::LineEnd,
+            'G' => Motion::LastLine,
+            '%' => Motion::MatchBracket,
+            '{' | '}' => Motion::Paragraph(ch == '}'),
+            _ => return None,
+        };
+        Some(motion)
+    }
+
+    /// Reads the char to find for `f`, `F`, `t` or `T`, or repeats the last
+    /// such search for `;` and reverses it for `,`. Returns `None` when there
+    /// is nothing to search for.
+    fn read_char_search(&mut self, key: char) -> std::result::Result<Option<Motion>, std::io::Error> {
+        let search = match key {
+            ';' => self.last_char_search,
+            ',' => self.last_char_search.map(CharSearch::reversed),
+            _ => {
+                let KeyEvent {
+                    code: KeyCode::Char(ch),
+                    modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                    ..
+                } = self.reader.read_key()?
+                else {
+                    return Ok(None);
+                };
+                let search = CharSearch {
+                    ch,
+                    forward: key == 'f' || key == 't',
+                    till: key == 't' || key == 'T',
+                };
+                self.last_char_search = Some(search);
+                Some(search)
+            }
+        };
+        Ok(search.map(Motion::FindChar))
+    }
+
+    /// Moves the cursor by `motion`, as far as the rows allow. A motion
+    /// that cannot move at all stops a macro being replayed.
+    fn move_by(&mut self, motion: Motion, count: Option<usize>) {
+        if self.output.mode != Mode::Visual {
+            self.output.selection_anchor = None;
+        }
+        let before = self.output.cursor();
+        let cursor = &mut self.output.cursor_controller;
+        (cursor.cursor_y, cursor.cursor_x) =
+            motion::target(&self.output.editor_rows, before, motion, count);
+        if self.output.cursor() == before {
+            self.abort_replay();
+        }
+    }
+
+    /// Moves to the row `count` down from the top of the screen for `H`,
+    /// `count` up from the bottom for `L`, or the middle row shown for `M`,
+    /// staying out of the scroll margin so the view stays where it is.
+    fn move_on_screen(&mut self, key: char, count: usize) {
+        let from = self.output.jump_here();
+        let output = &mut self.output;
+        let screen_rows = output.cursor_controller.screen_rows;
+        let lines = output.display_lines(screen_rows);
+        let (Some(&(first, _)), Some(&(last, _))) = (lines.first(), lines.last()) else {
+            return;
+        };
+        let margin = output.config.scroll_off.min(screen_rows.saturating_sub(1) / 2);
+        let top = if first == 0 { first } else { first + margin };
+        let bottom = if last + 1 == output.editor_rows.number_of_rows() {
+            last
+        } else {
+            last.saturating_sub(margin)
+        };
+        let row = match key {
+            'H' => first + count - 1,
+            'L' => last.saturating_sub(count - 1),
+            _ => (first + last) / 2,
+        };
+        let row = row.max(top).min(bottom);
+        if output.mode != Mode::Visual {
+            output.selection_anchor = None;
+        }
+        let col = if output.config.start_of_line { 0 } else { from.col };
+        let (row, col) = output.clamp_pos((row, col));
+        (output.cursor_controller.cursor_y, output.cursor_controller.cursor_x) = (row, col);
+        if row != from.row {
+            self.jumps.push(from);
+        }
+    }
+
+    /// Like [`Editor::move_by`], remembering where the cursor was in the
+    /// jump list if it moved.
+    fn jump_by(&mut self, motion: Motion, count: Option<usize>) {
+        let from = self.output.jump_here();
+        self.move_by(motion, count);
+        if self.output.cursor() != (from.row, from.col) {
+            self.jumps.push(from);
+        }
+    }
+
+    fn process_operator_key(
+        &mut self,
+        op: Op,
+        key: KeyEvent,
+    ) -> std::result::Result<bool, std::io::Error> {
+        if self.take_count_digit(&key) {
+            return Ok(true);
+        }
+        let KeyEvent {
+            code: KeyCode::Char(ch),
+            modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+            ..
+        } = key
+        else {
+            return Ok(self.cancel_operator());
+        };
+        let g_pending = self.output.pending_keys.ends_with('g');
+        if ch == 'g' && !g_pending {
+            self.output.pending_keys.push('g');
+            return Ok(true);
+        }
+        // `ys`, `cs` and `ds` add, change and delete delimiters, as in
+        // vim-surround.
+        if ch == 's' && !g_pending {
+            match op {
+                Op::Yank => {
+                    self.output.pending_keys = "ys".into();
+                    self.output.set_mode(Mode::OperatorPending(Op::Surround(None)));
+                    return Ok(true);
+                }
+                Op::Delete | Op::Change => return self.read_surround_change(op == Op::Change),
+                _ => {}
+            }
+        }
+        // Counts typed before and after the operator multiply, as in `2d3w`.
+        let count = match (self.operator_count.take(), self.output.pending_count.take()) {
+            (Some(before), Some(after)) => Some(before.saturating_mul(after)),
+            (before, after) => before.or(after),
+        };
+        self.output.pending_keys.clear();
+        let op_key = match op {
+            Op::Delete => 'd',
+            Op::Yank => 'y',
+            Op::Change => 'c',
+            Op::Indent => '>',
+            Op::Outdent => '<',
+            Op::Surround(_) => 's',
+        };
+        let target = match ch {
+            'g' if g_pending => OperatorTarget::Motion(Motion::FirstLine),
+            _ if g_pending => return Ok(self.cancel_operator()),
+            _ if ch == op_key => OperatorTarget::Rows,
+            'f' | 'F' | 't' | 'T' | ';' | ',' => match self.read_char_search(ch)? {
+                Some(motion) => OperatorTarget::Motion(motion),
+                None => return Ok(self.cancel_operator()),
+            },
+            'i' | 'a' => {
+                let object = match self.reader.read_key()? {
+                    KeyEvent {
+                        code: KeyCode::Char(key),
+                        modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                        ..
+                    } => TextObject::for_key(key),
+                    _ => None,
+                };
+                match object {
+                    Some(object) => OperatorTarget::Object(object, ch == 'i'),
+                    None => return Ok(self.cancel_operator()),
+                }
+            }
+            _ => match Self::motion_for(ch) {
+                Some(motion) => OperatorTarget::Motion(motion),
+                None => return Ok(self.cancel_operator()),
+            },
+        };
+        let op = match op {
+            Op::Surround(None) => match self.read_typed_char()? {
+                Some(key) if surround::delimiters(key).is_some() => Op::Surround(Some(key)),
+                _ => return Ok(self.cancel_operator()),
+            },
+            _ => op,
+        };
+        if !self.apply_operator(op, target, count) {
+            self.abort_replay();
+            return Ok(self.cancel_operator());
+        }
+        let edit = Edit::Operator {
+            op,
+            target,
+            count,
+            text: String::new(),
+        };
+        match op {
+            Op::Delete | Op::Indent | Op::Outdent | Op::Surround(_) => self.last_edit = Some(edit),
+            // The text typed next is part of the change.
+            Op::Change => self.pending_edit = Some(edit),
+            Op::Yank => {}
+        }
+        Ok(true)
+    }
+
+    /// Applies `op` from the cursor to `target`. Returns `false`, leaving
+    /// the text alone, if there is no such target.
+    fn apply_operator(&mut self, op: Op, target: OperatorTarget, count: Option<usize>) -> bool {
+        let cursor = self.output.cursor();
+        let rows = &self.output.editor_rows;
+        let mut motion = match target {
+            // `dd`, `yy` and `cc` act on `count` whole rows.
+            OperatorTarget::Rows => {
+                let last = (cursor.0 + count.unwrap_or(1) - 1).min(rows.number_of_rows() - 1);
+                self.output.operate_on_rows(op, cursor.0, last);
+                return true;
+            }
+            OperatorTarget::Object(object, inner) => {
+                let Some((start, end, kind)) = text_object::range(rows, cursor, object, inner)
+                else {
+                    return false;
+                };
+                match kind {
+                    MotionKind::Linewise => self.output.operate_on_rows(op, start.0, end.0),
+                    _ => self.output.operate_on_text(op, start, end),
+                }
+                return true;
+            }
+            OperatorTarget::Motion(motion) => motion,
+        };
+        // As in vim, `cw` on a word changes only to its end.
+        if let (Op::Change, Motion::Word(WordMotion::NextStart, big)) = (op, motion) {
+            let on_blank = rows.get_row(cursor.0)[cursor.1..]
+                .chars()
+                .next()
+                .is_none_or(char::is_whitespace);
+            if !on_blank {
+                motion = Motion::Word(WordMotion::NextEnd, big);
+            }
+        }
+        // A char search or bracket match that finds nothing leaves the text
+        // alone.
+        if let Motion::FindChar(search) = motion {
+            let line = rows.get_row(cursor.0);
+            if motion::find_char(line, cursor.1, search, count.unwrap_or(1)).is_none() {
+                return false;
+            }
+        }
+        if motion == Motion::MatchBracket && text_object::matching_bracket(rows, cursor).is_none() {
+            return false;
+        }
+        let mut target = motion::target(rows, cursor, motion, count);
+        // Nor does `dw` on the last word of a row reach into the next.
+        if let Motion::Word(WordMotion::NextStart, _) = motion {
+            if target.0 > cursor.0 {
+                target = (cursor.0, rows.get_row(cursor.0).len());
+            }
+        }
+        let (start, end) = if cursor <= target {
+            (cursor, target)
+        } else {
+            (target, cursor)
+        };
+        match motion.kind() {
+            MotionKind::Linewise => self.output.operate_on_rows(op, start.0, end.0),
+            MotionKind::Exclusive => self.output.operate_on_text(op, start, end),
+            MotionKind::Inclusive => {
+                let end = (end.0, rows.get_editor_row(end.0).next_boundary(end.1));
+                self.output.operate_on_text(op, start, end)
+            }
+        }
+        true
+    }
+
+    /// Reads the delimiter to change or delete for `cs` or `ds`, and with
+    /// `change` the one to put instead, and makes the change.
+    fn read_surround_change(&mut self, change: bool) -> std::result::Result<bool, std::io::Error> {
+        self.cancel_operator();
+        let old = self.read_typed_char()?;
+        let new = if change { self.read_typed_char()? } else { None };
+        match old {
+            Some(old) if (!change || new.is_some()) && self.output.change_surround(old, new) => {
+                self.last_edit = Some(Edit::ChangeSurround(old, new));
+            }
+            _ => self.abort_replay(),
+        }
+        Ok(true)
+    }
+
+    /// Reads a key, and returns the char it types if it is a plain one.
+    fn read_typed_char(&mut self) -> std::result::Result<Option<char>, std::io::Error> {
+        Ok(match self.reader.read_key()? {
+            KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } => Some(ch),
+            _ => None,
+        })
+    }
+
+    /// Drops a pending operator. Returns `true`, for the editor to keep
+    /// running.
+    fn cancel_operator(&mut self) -> bool {
+        self.operator_count = None;
+        self.output.pending_count = None;
+        self.output.pending_keys.clear();
+        self.output.set_mode(Mode::Normal);
+        true
+    }
+
+    fn process_normal_key(&mut self, key: KeyEvent) -> std::result::Result<bool, std::io::Error> {
+        if self.take_count_digit(&key) {
+            return Ok(true);
+        }
+        if self.output.pending_keys.ends_with('g')
+            && key.code == KeyCode::Char('g')
+            && key.modifiers == KeyModifiers::CONTROL
+        {
+            self.output.pending_keys.clear();
+            self.output.pending_count = None;
+            self.output.show_counts();
+            return Ok(true);
+        }
+        if let KeyEvent {
+            code: KeyCode::Char(ch),
+            modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+            ..
+        } = key
+        {
+            if self.output.pending_keys.ends_with('g') {
+                self.output.pending_keys.clear();
+                let count = self.output.pending_count.take();
+                match ch {
+                    'g' => self.jump_by(Motion::FirstLine, count),
+                    'a' => self.output.show_char_info(),
+                    't' => self.output.cycle_buffer(count.unwrap_or(1) as isize),
+                    'T' => self.output.cycle_buffer(-(count.unwrap_or(1) as isize)),
+                    'J' => {
+                        let count = count.unwrap_or(1);
+                        self.join_without_spaces(count);
+                        self.last_edit = Some(Edit::JoinWithoutSpaces(count));
+                    }
+                    _ => {}
+                }
+                return Ok(true);
+            }
+            if ch == 'g' {
+                self.output.pending_keys.push('g');
+                return Ok(true);
+            }
+            if let Some(bracket @ (']' | '[')) = self.output.pending_keys.chars().last() {
+                self.output.pending_keys.clear();
+                let count = self.output.pending_count.take().unwrap_or(1);
+                let action = match (bracket, ch) {
+                    (']', 'c') => Action::NextChange,
+                    ('[', 'c') => Action::PreviousChange,
+                    (']', 'q') => Action::NextMatch,
+                    ('[', 'q') => Action::PreviousMatch,
+                    _ => return Ok(true),
+                };
+                return self.dispatch_action(action, count);
+            }
+            if ch == ']' || ch == '[' {
+                self.output.pending_keys.push(ch);
+                return Ok(true);
+            }
+            if self.output.pending_keys.ends_with('z') {
+                self.output.pending_keys.clear();
+                self.output.pending_count = None;
+                let action = match ch {
+                    'z' => Action::ScrollCenter,
+                    't' => Action::ScrollTop,
+                    'b' => Action::ScrollBottom,
+                    'a' => Action::ToggleFold,
+                    'o' => Action::OpenFold,
+                    'c' => Action::CloseFold,
+                    'R' => Action::OpenAllFolds,
+                    'M' => Action::CloseAllFolds,
+                    _ => return Ok(true),
+                };
+                return self.dispatch_action(action, 1);
+            }
+            if ch == 'z' {
+                self.output.pending_keys.push('z');
+                return Ok(true);
+            }
+        }
+        self.output.pending_keys.clear();
+        let typed_count = self.output.pending_count.take();
+        let count = typed_count.unwrap_or(1);
+        let visual = self.output.mode == Mode::Visual;
+        let block = self.output.block.is_some();
+        let KeyEvent {
+            code: KeyCode::Char(ch),
+            modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+            ..
+        } = key
+        else {
+            return match key.code {
+                KeyCode::Esc if visual => {
+                    self.output.set_mode(Mode::Normal);
+                    self.output.extra_cursors.clear();
+                    Ok(true)
+                }
+                KeyCode::Esc => {
+                    self.output.clear_search();
+                    self.output.extra_cursors.clear();
+                    Ok(true)
+                }
+                KeyCode::Tab | KeyCode::BackTab if visual && !block => {
+                    self.output.indent_selection(key.code == KeyCode::BackTab);
+                    Ok(true)
+                }
+                KeyCode::Enter => self.dispatch_action(Action::MoveDown, count),
+                KeyCode::Backspace if key.modifiers == KeyModifiers::NONE => {
+                    self.dispatch_action(Action::MoveLeft, count)
+                }
+                // Ctrl-D scrolls, as in vim, until there are cursors or a
+                // selection to add the next occurrence to. Alt-D selects the
+                // word at the cursor to start from.
+                KeyCode::Char('d')
+                    if !visual
+                        && key.modifiers == KeyModifiers::CONTROL
+                        && self.output.extra_cursors.is_empty()
+                        && self.output.selection_anchor.is_none() =>
+                {
+                    self.dispatch_action(Action::HalfPageDown, count)
+                }
+                _ => self.process_common_key(key, count),
+            };
+        };
+        match ch {
+            'i' | 'I' | 'a' | 'A' | 'c' if block => self.begin_block_insert(ch),
+            'd' | 'x' if block => {
+                self.output.copy_block();
+                self.output.delete_block();
+                self.output.end_block();
+            }
+            'y' if block => {
+                self.output.copy_block();
+                self.output.end_block();
+            }
+            'h' | 'j' | 'k' | 'l' if block => {
+                let step = match ch {
+                    'h' => Step::Left,
+                    'j' => Step::Down,
+                    'k' => Step::Up,
+                    _ => Step::Right,
+                };
+                (0..count).for_each(|_| self.output.move_block_corner(step))
+            }
+            'o' if block => {
+                // The other corner moves from now on, as in vim.
+                if let Some(block) = self.output.block.as_mut() {
+                    let corner = std::mem::replace(&mut block.anchor, block.corner);
+                    self.output.set_block_corner(corner)
+                }
+            }
+            'h' => return self.dispatch_action(Action::MoveLeft, count),
+            'j' => return self.dispatch_action(Action::MoveDown, count),
+            'k' => return self.dispatch_action(Action::MoveUp, count),
+            'l' => return self.dispatch_action(Action::MoveRight, count),
+            'w' | 'W' => self.move_word(WordMotion::NextStart, ch == 'W', count, false),
+            'b' | 'B' => self.move_word(WordMotion::PrevStart, ch == 'B', count, false),
+            'e' | 'E' => self.move_word(WordMotion::NextEnd, ch == 'E', count, false),
+            'i' | 'a' | 'I' | 'A' | 'o' | 'O' => self.begin_insert(ch),
+            'r' | 'R' if visual => {}
+            'r' => self.replace_chars(count)?,
+            'R' => {
+                self.output.extra_cursors.clear();
+                self.begin_insert('R')
+            }
+            '0' | '$' => self.move_by(Self::motion_for(ch).unwrap(), typed_count),
+            'G' => self.jump_by(Motion::LastLine, typed_count),
+            '%' => self.jump_by(Motion::MatchBracket, None),
+            'H' | 'M' | 'L' => self.move_on_screen(ch, count),
+            '*' | '#' => self.search_word(ch == '*'),
+            'n' | 'N' => self.search_again(ch == 'n'),
+            '/' => self.find("Search", SearchDirection::Forward)?,
+            '?' => self.find("Search backward", SearchDirection::Backward)?,
+            '{' | '}' => {
+                // Only jumps of more than a few rows are worth going back to.
+                let from = self.output.jump_here();
+                self.move_by(Motion::Paragraph(ch == '}'), typed_count);
+                if self.output.cursor().0.abs_diff(from.row) > PARAGRAPH_JUMP_ROWS {
+                    self.jumps.push(from);
+                }
+            }
+            'f' | 'F' | 't' | 'T' | ';' | ',' => {
+                if let Some(motion) = self.read_char_search(ch)? {
+                    self.move_by(motion, typed_count)
+                }
+            }
+            'v' if visual => self.output.set_mode(Mode::Normal),
+            'v' => self.output.set_mode(Mode::Visual),
+            'x' | 'd' if visual => self.output.at_each_cursor(Output::cut_selection),
+            'y' if visual => {
+                self.output.copy_selection();
+            }
+            'c' if visual => self.output.at_each_cursor(|output| {
+                output.cut_selection();
+                output.set_mode(Mode::Insert)
+            }),
+            '>' | '<' if visual => {
+                let op = if ch == '>' { Op::Indent } else { Op::Outdent };
+                // `.` shifts as many rows again, from the cursor.
+                if let Some((start, end)) = self.output.selection() {
+                    self.last_edit = Some(Edit::Operator {
+                        op,
+                        target: OperatorTarget::Rows,
+                        count: Some(end.0 - start.0 + 1),
+                        text: String::new(),
+                    });
+                }
+                self.output.at_each_cursor(|output| {
+                    if let Some((start, end)) = output.selection() {
+                        output.operate_on_rows(op, start.0, end.0)
+                    }
+                })
+            }
+            'd' | 'y' | 'c' | '>' | '<' => {
+                let op = match ch {
+                    'd' => Op::Delete,
+                    'y' => Op::Yank,
+                    '>' => Op::Indent,
+                    '<' => Op::Outdent,
+                    _ => Op::Change,
+                };
+                self.operator_count = typed_count;
+                self.output.pending_keys = ch.to_string();
+                self.output.set_mode(Mode::OperatorPending(op))
+            }
+            'x' => return self.dispatch_action(Action::DeleteForward, count),
+            'J' => return self.dispatch_action(Action::JoinLines, count),
+            'U' if visual => self.output.at_each_cursor(|output| output.change_case(Case::Upper)),
+            'u' if visual => self.output.at_each_cursor(|output| output.change_case(Case::Lower)),
+            'u' => return self.dispatch_action(Action::Undo, count),
+            'p' | 'P' if !visual => {
+                self.last_edit = Some(Edit::Put(ch == 'p', count));
+                self.output
+                    .at_each_cursor(|output| (0..count).for_each(|_| output.put(ch == 'p')))
+            }
+            '~' => {
+                if !visual {
+                    self.last_edit = Some(Edit::ToggleCase(count));
+                }
+                self.output.at_each_cursor(|output| output.toggle_case(count))
+            }
+            '.' => self.repeat_edit(typed_count)?,
+            'm' => self.set_mark()?,
+            '`' | '\'' => self.jump_to_mark(ch == '\'')?,
+            'q' => return self.toggle_macro_recording(),
+            '@' => self.read_and_play_macro(count)?,
+            ':' => return self.command_line(),
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn process_insert_key(&mut self, key: KeyEvent) -> std::result::Result<bool, std::io::Error> {
+        match key {
+            KeyEvent {
+                code: KeyCode::Esc,
+                ..
+            } => {
+                self.finish_edit();
+                self.output.set_mode(Mode::Normal);
+                self.output.extra_cursors.clear();
+                if !self.output.config.modal {
+                    self.output.seal_undo();
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } => {
+                if self.output.block.is_some() {
+                    self.output.insert_at_block(ch);
+                } else {
+                    let typed = self.output.typed_pair(ch);
+                    self.output.at_each_cursor(|output| {
+                        output.delete_selection();
+                        output.type_char(ch, typed);
+                    });
+                }
+                self.record_typed(ch)
+            }
+            KeyEvent {
+                code: KeyCode::BackTab,
+                ..
+            } if self.output.block.is_none() => {
+                self.output.indent_selection(true);
+            }
+            KeyEvent {
+                code: KeyCode::Tab,
+                ..
+            } => {
+                if self.output.block.is_some() {
+                    self.output.insert_at_block('\t');
+                } else if self.output.indent_selection(false) {
+                    return Ok(true);
+                } else {
+                    self.output.at_each_cursor(|output| {
+                        output.delete_selection();
+                        output.insert_tab();
+                    });
+                }
+                self.record_typed('\t')
+            }
+            KeyEvent {
+                code: KeyCode::Enter,
+                ..
+            } => {
+                self.output.at_each_cursor(|output| {
+                    output.delete_selection();
+                    output.insert_newline();
+                });
+                self.record_typed('\n')
+            }
+            KeyEvent {
+                code: KeyCode::Backspace,
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } if self.output.block.is_some() => self.output.delete_at_block(false),
+            KeyEvent {
+                code: KeyCode::Backspace,
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } => {
+                let mut deleted_selection = false;
+                let pair = self.output.deletes_pair();
+                self.output.at_each_cursor(|output| {
+                    if output.delete_selection() {
+                        deleted_selection = true;
+                    } else {
+                        if pair && output.in_empty_pair() {
+                            output.delete_forward();
+                        }
+                        output.delete_char();
+                    }
+                });
+                if !deleted_selection {
+                    self.record_typed(BACKSPACE)
+                }
+            }
+            _ => {
+                // Moving around ends the text `.` repeats, and starts anew
+                // from wherever the cursor ends up.
+                self.finish_edit();
+                self.output.auto_closed = 0;
+                // Without Normal mode to go back to, text typed between two
+                // other keys is a step to undo, and so is what a key bound to
+                // an action changes.
+                let modeless = !self.output.config.modal;
+                if modeless {
+                    self.output.seal_undo();
+                }
+                let result = self.process_common_key(key, 1);
+                if modeless {
+                    self.output.seal_undo();
+                }
+                if self.output.mode == Mode::Insert {
+                    self.pending_edit = Some(Edit::Insert {
+                        entry: 'i',
+                        text: String::new(),
+                    });
+                }
+                return result;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Handles a key in Replace mode, where typing overwrites text and
+    /// Backspace puts it back.
+    fn process_replace_key(&mut self, key: KeyEvent) -> std::result::Result<bool, std::io::Error> {
+        match key {
+            KeyEvent {
+                code: KeyCode::Esc,
+                ..
+            } => {
+                self.finish_edit();
+                self.replaced.clear();
+                self.output.set_mode(Mode::Normal);
+                // The chars typed over since `R` are undone together.
+                self.output.seal_undo();
+            }
+            KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } => {
+                self.replace_typed(ch);
+                self.record_typed(ch)
+            }
+            KeyEvent {
+                code: KeyCode::Tab,
+                ..
+            } => {
+                self.replace_typed('\t');
+                self.record_typed('\t')
+            }
+            KeyEvent {
+                code: KeyCode::Enter,
+                ..
+            } => {
+                // Backspace doesn't go back over the new row.
+                self.replaced.clear();
+                self.output.insert_newline();
+                self.record_typed('\n')
+            }
+            KeyEvent {
+                code: KeyCode::Backspace,
+                ..
+            } => {
+                self.replace_backspace();
+                self.record_typed(BACKSPACE)
+            }
+            _ => {
+                self.finish_edit();
+                self.replaced.clear();
+                let result = self.process_common_key(key, 1);
+                if self.output.mode == Mode::Replace {
+                    self.pending_edit = Some(Edit::Insert {
+                        entry: 'R',
+                        text: String::new(),
+                    });
+                }
+                return result;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Overwrites the char under the cursor with `ch` typed in Replace
+    /// mode, remembering the old one.
+    fn replace_typed(&mut self, ch: char) {
+        if self.output.editable() {
+            let replaced = self.output.replace_char(ch);
+            self.replaced.push(replaced);
+        }
+    }
+
+    /// Moves back a char in Replace mode, putting back the char typed over
+    /// there, or deleting it if it was added at the end of the row.
+    fn replace_backspace(&mut self) {
+        match self.replaced.pop() {
+            Some(Some(original)) => {
+                self.output.move_cursor(KeyCode::Left);
+                self.output.replace_char(original);
+                self.output.move_cursor(KeyCode::Left);
+            }
+            Some(None) => self.output.delete_char(),
+            None => self.output.move_cursor(KeyCode::Left),
+        }
+    }
+
+    /// Replaces `count` chars from the cursor with the char typed next, for
+    /// `r`.
+    fn replace_chars(&mut self, count: usize) -> std::result::Result<(), std::io::Error> {
+        let ch = match self.reader.read_key()? {
+            KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } => ch,
+            KeyEvent {
+                code: KeyCode::Tab,
+                ..
+            } => '\t',
+            _ => return Ok(()),
+        };
+        self.apply_replace(ch, count);
+        self.last_edit = Some(Edit::Replace(ch, count));
+        Ok(())
+    }
+
+    /// Joins `count` rows, or the selected ones, leaving their whitespace
+    /// alone, for `gJ`.
+    fn join_without_spaces(&mut self, count: usize) {
+        let mut joined = true;
+        self.output
+            .at_each_cursor(|output| joined &= output.join_lines(count, false));
+        if !joined {
+            self.abort_replay();
+        }
+    }
+
+    /// Replaces `count` chars with `ch` at every cursor. A row too short
+    /// stops a macro being replayed.
+    fn apply_replace(&mut self, ch: char, count: usize) {
+        let mut replaced = true;
+        self.output
+            .at_each_cursor(|output| replaced &= output.replace_chars(ch, count));
+        if !replaced {
+            self.abort_replay();
+        }
+    }
+
+    /// Whether `key` acts on the block selection. Other keys drop it.
+    fn keeps_block(&self, key: &KeyEvent) -> bool {
+        if let Some(action) = self.output.config.keymap.resolve(key) {
+            return matches!(
+                action,
+                Action::SelectBlockUp
+                    | Action::SelectBlockDown
+                    | Action::SelectBlockLeft
+                    | Action::SelectBlockRight
+                    | Action::Copy
+                    | Action::Cut
+                    | Action::Paste
+                    | Action::DeleteForward
+            );
+        }
+        match (self.output.mode, key.code) {
+            (Mode::Insert, KeyCode::Char(_) | KeyCode::Tab | KeyCode::Backspace) => true,
+            (Mode::Normal, KeyCode::Char(ch)) => "iIaAcdxyhjklo".contains(ch),
+            _ => false,
+        }
+    }
+
+    /// Moves the cursor to where the mouse was clicked, or with Alt held
+    /// adds a cursor there. Dragging after Alt+Click selects a block. The
+    /// wheel scrolls.
+    fn mouse(&mut self, click: MouseEvent) {
+        if let Mode::OperatorPending(_) = self.output.mode {
+            return;
+        }
+        if let MouseEventKind::ScrollUp | MouseEventKind::ScrollDown = click.kind {
+            let down = click.kind == MouseEventKind::ScrollDown;
+            let output = &mut self.output;
+            output.cursor_controller.scroll_lines(down, WHEEL_LINES, &output.editor_rows);
+            return;
+        }
+        if let MouseEventKind::Drag(_) = click.kind {
+            return self.output.drag_block(click.column as usize, click.row as usize);
+        }
+        let add = click.modifiers.contains(KeyModifiers::ALT);
+        if self.output.mode == Mode::Insert && !add {
+            // As with other moves, typing after the click starts anew.
+            self.finish_edit();
+            self.pending_edit = Some(Edit::Insert {
+                entry: 'i',
+                text: String::new(),
+            });
+        }
+        self.output
+            .click(click.column as usize, click.row as usize, add);
+    }
+
+    /// Enters Insert mode typing on every row of the block selection: at its
+    /// left edge for `i` and `I`, its right edge for `a` and `A`, or in
+    /// place of its text for `c`.
+    fn begin_block_insert(&mut self, entry: char) {
+        let Some(mut block) = self.output.block else {
+            return;
+        };
+        match entry {
+            'c' => {
+                self.output.copy_block();
+                self.output.delete_block();
+            }
+            'a' | 'A' => block.collapse_to(block.columns().end),
+            _ => block.collapse_to(block.columns().start),
+        }
+        if entry != 'c' {
+            self.output.block = Some(block);
+            self.output.set_block_corner(block.corner);
+        }
+        self.output.set_mode(Mode::Insert);
+    }
+
+    /// Enters Insert mode the way `entry` does: `i` at the cursor, `a`
+    /// after it, `I` at the first non-blank, `A` at the end of the row, and
+    /// `o` or `O` on a new row below or above.
+    fn begin_insert(&mut self, entry: char) {
+        if let 'o' | 'O' = entry {
+            if !self.output.editable() {
+                return;
+            }
+        }
+        if entry == 'R' {
+            self.output.seal_undo();
+        }
+        self.output.at_each_cursor(|output| output.move_to_insert(entry));
+        self.replaced.clear();
+        self.output
+            .set_mode(if entry == 'R' { Mode::Replace } else { Mode::Insert });
+        self.pending_edit = Some(Edit::Insert {
+            entry,
+            text: String::new(),
+        });
+    }
+
+    /// Adds `ch` to the text typed into the insert or change `.` will
+    /// repeat.
+    fn record_typed(&mut self, ch: char) {
+        let (Some(Edit::Insert { text, .. }) | Some(Edit::Operator { text, .. })) =
+            &mut self.pending_edit
+        else {
+            return;
+        };
+        // A Backspace after a plain char just takes it back.
+        match text.chars().next_back() {
+            Some(last) if ch == BACKSPACE && !['\t', '\n', BACKSPACE].contains(&last) => {
+                text.pop();
+            }
+            _ => text.push(ch),
+        }
+    }
+
+    /// Types `text` as recorded by [`Editor::record_typed`].
+    fn type_text(&mut self, text: &str) {
+        for ch in text.chars() {
+            if self.output.mode == Mode::Replace {
+                match ch {
+                    '\n' => self.output.insert_newline(),
+                    BACKSPACE => self.replace_backspace(),
+                    _ => self.replace_typed(ch),
+                }
+                continue;
+            }
+            match ch {
+                '\n' => self.output.insert_newline(),
+                '\t' => self.output.insert_tab(),
+                BACKSPACE => {
+                    if self.output.deletes_pair() {
+                        self.output.delete_forward();
+                    }
+                    self.output.delete_char()
+                }
+                _ => {
+                    let typed = self.output.typed_pair(ch);
+                    self.output.type_char(ch, typed)
+                }
+            }
+        }
+    }
+
+    /// Makes the insert or change being typed the edit `.` repeats, unless
+    /// it is an insert that typed nothing.
+    fn finish_edit(&mut self) {
+        match self.pending_edit.take() {
+            Some(Edit::Insert { entry, text }) if text.is_empty() && !"oO".contains(entry) => {}
+            Some(edit) => self.last_edit = Some(edit),
+            None => {}
+        }
+    }
+
+    /// Repeats the last edit at the cursor. A typed `count` replaces the
+    /// edit's own, or types an insert's text `count` times.
+    fn repeat_edit(&mut self, count: Option<usize>) -> std::result::Result<(), std::io::Error> {
+        let Some(edit) = self.last_edit.clone() else {
+            self.abort_replay();
+            return Ok(());
+        };
+        match edit {
+            Edit::Insert { entry, text } => {
+                self.begin_insert(entry);
+                if let Mode::Insert | Mode::Replace = self.output.mode {
+                    (0..count.unwrap_or(1)).for_each(|_| self.type_text(&text));
+                    self.output.set_mode(Mode::Normal);
+                }
+            }
+            Edit::Operator {
+                op,
+                target,
+                count: edit_count,
+                text,
+            } => {
+                if !self.apply_operator(op, target, count.or(edit_count)) {
+                    self.abort_replay();
+                } else if self.output.mode == Mode::Insert {
+                    self.type_text(&text);
+                    self.output.set_mode(Mode::Normal);
+                }
+            }
+            Edit::Action(action, edit_count) => {
+                self.dispatch_action(action, count.unwrap_or(edit_count))?;
+            }
+            Edit::Replace(ch, edit_count) => self.apply_replace(ch, count.unwrap_or(edit_count)),
+            Edit::JoinWithoutSpaces(edit_count) => {
+                self.join_without_spaces(count.unwrap_or(edit_count))
+            }
+            Edit::ChangeSurround(old, new) => {
+                if !self.output.change_surround(old, new) {
+                    self.abort_replay();
+                }
+            }
+            Edit::ToggleCase(edit_count) => self
+                .output
+                .at_each_cursor(|output| output.toggle_case(count.unwrap_or(edit_count))),
+            Edit::Put(after, edit_count) => self.output.at_each_cursor(|output| {
+                (0..count.unwrap_or(edit_count)).for_each(|_| output.put(after))
+            }),
+        }
+        self.pending_edit = None;
+        Ok(())
+    }
+
+    /// Handles the keys that work the same in every mode, through the
+    /// keymap.
+    fn process_common_key(
+        &mut self,
+        key: KeyEvent,
+        count: usize,
+    ) -> std::result::Result<bool, std::io::Error> {
+        match self.output.config.keymap.resolve(&key) {
+            Some(action) => self.dispatch_action(action, count),
+            None => Ok(true),
+        }
+    }
+
+    /// Moves the cursor `count` times, extending the selection with `select`.
+    /// Outside Visual mode, moving without `select` drops the selection.
+    /// Like [`Editor::move_by`], not moving at all stops a macro replay.
+    fn move_cursor(&mut self, direction: KeyCode, count: usize, select: bool) {
+        if select {
+            self.output.start_selection();
+        } else if self.output.mode != Mode::Visual {
+            self.output.selection_anchor = None;
+        }
+        let before = self.output.cursor();
+        (0..count).for_each(|_| self.output.move_cursor(direction));
+        if self.output.cursor() == before {
+            self.abort_replay();
+        }
+    }
+
+    /// Moves the cursor by `count` words, extending the selection with
+    /// `select` like [`Editor::move_cursor`].
+    fn move_word(&mut self, motion: WordMotion, big: bool, count: usize, select: bool) {
+        if select {
+            self.output.start_selection();
+        } else if self.output.mode != Mode::Visual {
+            self.output.selection_anchor = None;
+        }
+        let before = self.output.cursor();
+        let cursor = &mut self.output.cursor_controller;
+        for _ in 0..count {
+            (cursor.cursor_y, cursor.cursor_x) = word_motion::apply(
+                &self.output.editor_rows,
+                (cursor.cursor_y, cursor.cursor_x),
+                motion,
+                big,
+            );
+        }
+        if self.output.cursor() == before {
+            self.abort_replay();
+        }
+    }
+
+    /// Moves the cursor `count` words the way Ctrl+Left and Ctrl+Right do,
+    /// extending the selection with `select` like [`Editor::move_cursor`].
+    fn skip_words(&mut self, forward: bool, count: usize, select: bool) {
+        if select {
+            self.output.start_selection();
+        } else if self.output.mode != Mode::Visual {
+            self.output.selection_anchor = None;
+        }
+        let before = self.output.cursor();
+        let cursor = &mut self.output.cursor_controller;
+        (0..count).for_each(|_| cursor.move_word(forward, &self.output.editor_rows));
+        if self.output.cursor() == before {
+            self.abort_replay();
+        }
+    }
+
+    /// Moves `count` changes down, or up with `back`, as the diff shows
+    /// them.
+    fn jump_to_change(&mut self, back: bool, count: usize) {
+        if self.output.editor_rows.diff.is_none() {
+            self.output
+                .status_message
+                .set_message("No diff shown; :diff shows one".into());
+            self.abort_replay();
+            return;
+        }
+        let from = self.output.jump_here();
+        if self.output.jump_to_change(back, count) {
+            self.jumps.push(from);
+        } else {
+            self.output.status_message.set_message("No more changes".into());
+            self.abort_replay();
+        }
+    }
+
+    /// Goes `count` entries back through the jump list, skipping jumps into
+    /// buffers that are gone.
+    fn jump_back(&mut self, count: usize) {
+        for _ in 0..count {
+            while let Some(jump) = self.jumps.back(self.output.jump_here()) {
+                if self.output.jump_to(jump) {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn jump_forward(&mut self, count: usize) {
+        for _ in 0..count {
+            while let Some(jump) = self.jumps.forward() {
+                if self.output.jump_to(jump) {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Runs `action` `count` times where that makes sense. Returns `false`
+    /// when the editor should quit.
+    fn dispatch_action(
+        &mut self,
+        action: Action,
+        count: usize,
+    ) -> std::result::Result<bool, std::io::Error> {
+        if let Action::DeleteForward
+        | Action::DeleteLine
+        | Action::DeleteWordBack
+        | Action::DeleteWordForward
+        | Action::JoinLines
+        | Action::Uppercase
+        | Action::Lowercase
+        | Action::TitleCase
+        | Action::TransposeChars
+        | Action::TransposeWords
+        | Action::ToggleComment
+        | Action::Paste = action
+        {
+            self.last_edit = Some(Edit::Action(action, count));
+        }
+        match action {
+            Action::Quit => {
+                let modified = self.output.modified_buffers();
+                if modified.is_empty() || self.quit_warned {
+                    return Ok(false);
+                }
+                let message = format!(
+                    "No write since last change to {}. Quit again to discard",
+                    modified.join(", ")
+                );
+                self.output.status_message.set_message(message);
+                self.quit_warned = true;
+                return Ok(true);
+            }
+            Action::NextBuffer => self.output.cycle_buffer(count as isize),
+            Action::PreviousBuffer => self.output.cycle_buffer(-(count as isize)),
+            Action::Save => {
+                self.save()?;
+            }
+            Action::Reload => {
+                if self.output.editor_rows.dirty > 0 {
+                    let message = format!(
+                        "Discard unsaved changes to {}? (y/n)",
+                        self.output.editor_rows.display_name()
+                    );
+                    self.output.status_message.set_message(message);
+                    self.output.refresh_screen()?;
+                    if self.reader.read_key()?.code != KeyCode::Char('y') {
+                        self.output.status_message.set_message(String::new());
+                        return Ok(true);
+                    }
+                }
+                self.reload()
+            }
+            Action::Find => self.find("Search", SearchDirection::Forward)?,
+            Action::GotoLine => self.goto_line()?,
+            Action::CommandPalette => return self.command_palette(),
+            Action::FindFile => self.find_file()?,
+            Action::FindInFiles => self.find_in_files(None)?,
+            Action::NextMatch => self.step_match(false, count),
+            Action::PreviousMatch => self.step_match(true, count),
+            Action::ListBuffers => self.list_buffers()?,
+            Action::CloseBuffer => self.close_buffer(false)?,
+            Action::Replace => self.replace()?,
+            /* add the following*/
+            Action::MoveUp => self.move_cursor(KeyCode::Up, count, false),
+            Action::MoveDown => self.move_cursor(KeyCode::Down, count, false),
+            Action::PageUp | Action::PageDown => {
+                let rows = self.output.cursor_controller.screen_rows * count;
+                let direction = if action == Action::PageUp { KeyCode::Up } else { KeyCode::Down };
+                self.move_cursor(direction, rows, false)
+            }
+            Action::HalfPageUp | Action::HalfPageDown => {
+                let editor_rows = &self.output.editor_rows;
+                let down = action == Action::HalfPageDown;
+                if self.output.mode != Mode::Visual {
+                    self.output.selection_anchor = None;
+                }
+                (0..count).for_each(|_| self.output.cursor_controller.scroll_half_page(down, editor_rows));
+            }
+            Action::ScrollLineUp | Action::ScrollLineDown => {
+                let down = action == Action::ScrollLineDown;
+                let output = &mut self.output;
+                output.cursor_controller.scroll_lines(down, count, &output.editor_rows);
+            }
+            Action::ToggleFold => self.output.toggle_fold(),
+            Action::OpenFold => self.output.open_fold(),
+            Action::CloseFold => self.output.close_fold(),
+            Action::OpenAllFolds => self.output.editor_rows.folds.clear(),
+            Action::CloseAllFolds => self.output.close_all_folds(),
+            Action::ScrollCenter => {
+                let output = &mut self.output;
+                output.cursor_controller.center_on_cursor(&output.editor_rows);
+            }
+            Action::ScrollTop | Action::ScrollBottom => {
+                let place = if action == Action::ScrollTop {
+                    ScreenPlace::Top
+                } else {
+                    ScreenPlace::Bottom
+                };
+                let output = &mut self.output;
+                output.cursor_controller.scroll_cursor_to(place, &output.editor_rows);
+            }
+            Action::MoveLeft => self.move_cursor(KeyCode::Left, count, false),
+            Action::MoveRight => self.move_cursor(KeyCode::Right, count, false),
+            // end
+            Action::SelectUp => self.move_cursor(KeyCode::Up, count, true),
+            Action::SelectDown => self.move_cursor(KeyCode::Down, count, true),
+            Action::SelectLeft => self.move_cursor(KeyCode::Left, count, true),
+            Action::SelectRight => self.move_cursor(KeyCode::Right, count, true),
+            Action::LineStart | Action::LineEnd => self
+                .output
+                .at_each_cursor(|output| output.move_to_row_edge(action == Action::LineEnd)),
+            Action::SelectAll => self.output.select_all(),
+            Action::SelectNextOccurrence => self.output.select_next_occurrence(),
+            Action::SelectBlockUp
+            | Action::SelectBlockDown
+            | Action::SelectBlockLeft
+            | Action::SelectBlockRight => {
+                let step = match action {
+                    Action::SelectBlockUp => Step::Up,
+                    Action::SelectBlockDown => Step::Down,
+                    Action::SelectBlockLeft => Step::Left,
+                    _ => Step::Right,
+                };
+                (0..count).for_each(|_| self.output.move_block_corner(step))
+            }
+            Action::WordLeft => self.skip_words(false, count, false),
+            Action::WordRight => self.skip_words(true, count, false),
+            Action::SelectWordLeft => self.skip_words(false, count, true),
+            Action::SelectWordRight => self.skip_words(true, count, true),
+            Action::JumpBack => self.jump_back(count),
+            Action::JumpForward => self.jump_forward(count),
+            Action::RefreshDiff => self.output.refresh_diff(),
+            Action::NextChange => self.jump_to_change(false, count),
+            Action::PreviousChange => self.jump_to_change(true, count),
+            // Without a register to name, record into `q`, as `qq` does.
+            Action::RecordMacro if self.macro_register.is_some() => self.stop_macro_recording(),
+            Action::RecordMacro => self.start_macro_recording('q'),
+            Action::PlayMacro => self.play_macro(self.last_macro, count),
+            Action::Window => self.window_command(count)?,
+            Action::SetMark => self.set_mark()?,
+            Action::JumpToMark => self.jump_to_mark(false)?,
+            Action::Copy if self.output.block.is_some() => self.output.copy_block(),
+            Action::Copy => {
+                self.output.copy_selection();
+            }
+            Action::Cut if self.output.block.is_some() => {
+                self.output.copy_block();
+                self.output.delete_block();
+                self.output.end_block();
+            }
+            Action::Cut => self.output.cut_selection(),
+            Action::Paste => self
+                .output
+                .at_each_cursor(|output| (0..count).for_each(|_| output.paste())),
+            Action::DeleteForward if self.output.block.is_some() => {
+                (0..count).for_each(|_| self.output.delete_at_block(true))
+            }
+            Action::DeleteForward => self.output.at_each_cursor(|output| {
+                if !output.delete_selection() {
+                    (0..count).for_each(|_| output.delete_forward())
+                }
+            }),
+            Action::DeleteLine => self.output.delete_lines(count),
+            Action::DeleteWordBack => self
+                .output
+                .at_each_cursor(|output| (0..count).for_each(|_| output.delete_word(false))),
+            Action::DeleteWordForward => self
+                .output
+                .at_each_cursor(|output| (0..count).for_each(|_| output.delete_word(true))),
+            Action::Uppercase => self.output.at_each_cursor(|output| output.change_case(Case::Upper)),
+            Action::Lowercase => self.output.at_each_cursor(|output| output.change_case(Case::Lower)),
+            Action::TitleCase => self.output.at_each_cursor(|output| output.change_case(Case::Title)),
+            Action::TransposeChars => self.output.at_each_cursor(Output::transpose_chars),
+            Action::TransposeWords => self.output.at_each_cursor(Output::transpose_words),
+            Action::JoinLines => {
+                let mut joined = true;
+                self.output
+                    .at_each_cursor(|output| joined &= output.join_lines(count, true));
+                if !joined {
+                    self.abort_replay();
+                }
+            }
+            Action::ToggleComment => self.output.toggle_comment(count),
+            Action::ToggleWhitespace => {
+                self.output.config.show_whitespace = !self.output.config.show_whitespace;
+                let message = if self.output.config.show_whitespace {
+                    "Showing whitespace"
+                } else {
+                    "Not showing whitespace"
+                };
+                self.output.status_message.set_message(message.into());
+            }
+            Action::ToggleHex => self.output.toggle_hex(),
+            Action::ToggleWrap => {
+                self.output.config.wrap = !self.output.config.wrap;
+                let message = if self.output.config.wrap {
+                    "Wrapping long rows"
+                } else {
+                    "Not wrapping long rows"
+                };
+                self.output.status_message.set_message(message.into());
+            }
+            Action::Undo => self.undo(count, false),
+            Action::Redo => self.undo(count, true),
+        }
+        Ok(true)
+    }
+
+    /// Undoes the last `count` steps, or redoes the last `count` undone
+    /// with `redo`, and says how many there were.
+    fn undo(&mut self, count: usize, redo: bool) {
+        let done = self.output.undo(count, redo);
+        let message = match (done, redo) {
+            (0, false) => "Already at oldest change".to_string(),
+            (0, true) => "Already at newest change".to_string(),
+            (done, false) => format!("{} changes undone", done),
+            (done, true) => format!("{} changes redone", done),
+        };
+        if done == 0 {
+            self.abort_replay();
+        }
+        self.output.status_message.set_message(message);
+    }
+
+    /// Tells when another program has changed the buffer's file, offering
+    /// to read it again if the buffer has no changes of its own to lose.
+    /// Each change is only told about once.
+    fn check_file_changed(&mut self) -> std::result::Result<(), std::io::Error> {
+        let editor_rows = &mut self.output.editor_rows;
+        if !editor_rows.changed_on_disk() {
+            return Ok(());
+        }
+        editor_rows.disk_modified = editor_rows.modified_on_disk();
+        let name = editor_rows.display_name().to_string();
+        if editor_rows.dirty > 0 {
+            self.output.status_message.set_message(format!(
+                "{} was changed by another program; saving will overwrite its changes",
+                name
+            ));
+            return Ok(());
+        }
+        self.output
+            .status_message
+            .set_message(format!("{} changed on disk. Reload? (y/n)", name));
+        self.output.refresh_screen()?;
+        if self.reader.read_key()?.code == KeyCode::Char('y') {
+            self.reload();
+        } else {
+            self.output.status_message.set_message(String::new());
+        }
+        Ok(())
+    }
+
+    /// Whether the buffer has unsaved changes to a file, and no key has been
+    /// pressed for `autosave_interval` seconds. Not while typing, since
+    /// saving may strip the space just typed.
+    fn autosave_due(&self) -> bool {
+        let interval = self.output.config.autosave_interval;
+        let editor_rows = &self.output.editor_rows;
+        interval > 0
+            && matches!(self.output.mode, Mode::Normal | Mode::Visual)
+            && editor_rows.dirty > 0
+            && editor_rows.filename.is_some()
+            && editor_rows.loader.is_none()
+            && self.last_key.elapsed() >= Duration::from_secs(interval as u64)
+    }
+
+    fn autosave(&mut self) -> std::result::Result<(), std::io::Error> {
+        if self.save()? {
+            let name = self.output.editor_rows.display_name();
+            let message = format!("Auto-saved {}", name);
+            self.output.status_message.set_message(message);
+        }
+        // A failed save is tried again after another interval.
+        self.last_key = Instant::now();
+        Ok(())
+    }
+
+    fn run(&mut self) -> std::result::Result<bool, std::io::Error> {
+        if let Err(err) = self.output.editor_rows.poll_loader() {
+            self.output
+                .status_message
+                .set_message(format!("Error while loading file: {}", err));
+        }
+        self.output.poll_search(&mut self.search_job);
+        self.output.poll_git();
+        if self.reader.pending.is_empty() {
+            if self.autosave_due() {
+                self.autosave()?;
+            }
+            if self.output.editor_rows.diff_stale() && self.last_key.elapsed() >= DIFF_DELAY {
+                self.output.editor_rows.update_diff();
+            }
+            self.check_file_changed()?;
+            self.output.refresh_screen()?;
+        }
+        if (self.output.editor_rows.loader.is_some()
+            || self.search_job.is_some()
+            || self.output.git_pending())
+            && !self.reader.key_ready(Duration::from_millis(100))?
+        {
+            return Ok(true);
+        }
+        // A diff edits left out of date is worked out again once the keys
+        // stop coming.
+        if self.output.editor_rows.diff_stale() && !self.reader.key_ready(DIFF_DELAY)? {
+            return Ok(true);
+        }
+        // Look at the file now and then while waiting for a key, without
+        // redrawing until something changes.
+        while !self.reader.key_ready(FILE_CHECK_INTERVAL)? {
+            if self.output.editor_rows.changed_on_disk() || self.autosave_due() {
+                return Ok(true);
+            }
+        }
+        self.process_keypress()
+    }
+}
+
+fn main() -> std::result::Result<(), std::io::Error> {
+    let _clean_up = CleanUp;
+    terminal::enable_raw_mode()?;
+
+    let mut editor = Editor::new();
+    if editor.output.config.mouse {
+        execute!(stdout(), event::EnableMouseCapture)?;
+    }
+    while editor.run()? {}
+
+    if editor.output.config.persist_history {
+        editor.history.save()?;
+    }
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use HighlightType::{Normal, TrailingWhitespace, Whitespace};
+
+    /// An editor showing `editor_rows` on an 80 by 24 screen, editing the
+    /// vi way the keys tests type assume.
+    fn editor_for(editor_rows: EditorRows, config: Config) -> Editor {
+        editor_as_configured(editor_rows, Config { modal: true, ..config })
+    }
+
+    /// An editor showing `editor_rows` on an 80 by 24 screen, with or
+    /// without modes as `config` has it.
+    fn editor_as_configured(editor_rows: EditorRows, config: Config) -> Editor {
+        let output = Output::with_buffers((80, 24), config, editor_rows, Vec::new());
+        Editor::with_output(output, Histories::default())
+    }
+
+    /// An editor of an unnamed buffer holding `rows`.
+    fn editor(rows: &[&str]) -> Editor {
+        let config = Config::default();
+        let rows = rows.iter().map(|row| row.to_string()).collect();
+        editor_for(EditorRows::with_rows(None, rows, &config), config)
+    }
+
+
+    /// Has the editor handle `keys` as if typed, with the keys they replay.
+    fn press(editor: &mut Editor, keys: &[KeyEvent]) {
+        editor.reader.typed.extend(keys);
+        while !editor.reader.typed.is_empty() || !editor.reader.pending.is_empty() {
+            editor.process_keypress().unwrap();
+        }
+    }
+
+    /// Types each char of `keys` as a key of its own.
+    fn type_keys(editor: &mut Editor, keys: &str) {
+        let keys: Vec<KeyEvent> = keys.chars().map(|ch| KeyEvent::from(KeyCode::Char(ch))).collect();
+        press(editor, &keys);
+    }
+
+    fn shift(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::SHIFT)
+    }
+
+    fn ctrl(ch: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(ch), KeyModifiers::CONTROL)
+    }
+
+    /// The text of the buffer's rows.
+    fn rows(editor: &Editor) -> Vec<String> {
+        editor.output.editor_rows.row_strings()
+    }
+
+    /// A new file `name` holding `contents`, in a directory of its own
+    /// emptied of anything an earlier run left.
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let dir = env::temp_dir().join("dot-tests").join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn highlights_trailing_whitespace() {
+        let row = Row::new("ab \t".into(), 4);
+        assert_eq!(row.highlight, [Normal, Normal, TrailingWhitespace, TrailingWhitespace]);
+    }
+
+    #[test]
+    fn highlights_a_row_of_only_whitespace() {
+        let row = Row::new("\t  ".into(), 4);
+        assert_eq!(row.highlight, [TrailingWhitespace; 6]);
+    }
+
+    #[test]
+    fn leaves_a_clean_row_alone() {
+        let row = Row::new("a b".into(), 4);
+        assert_eq!(row.highlight, [Normal; 3]);
+    }
+
+    #[test]
+    fn strips_trailing_whitespace_on_save() {
+        let path = temp_file("strip.txt", "a  \n\tb\t\n \t\nc");
+        let config = Config {
+            strip_trailing_whitespace_on_save: true,
+            ..Config::default()
+        };
+        let mut editor = editor_for(EditorRows::from_file(path.clone(), &config).unwrap(), config);
+        editor.output.cursor_controller.cursor_x = 3;
+        assert!(editor.save().unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n\tb\n\nc\n");
+        assert_eq!(editor.output.cursor(), (0, 1));
+        assert_eq!(editor.output.editor_rows.dirty, 0);
+    }
+
+    #[test]
+    fn adds_a_missing_final_newline() {
+        let config = Config::default();
+        let path = temp_file("no_newline.txt", "a\nb");
+        let mut editor_rows = EditorRows::from_file(path, &config).unwrap();
+        assert_eq!(editor_rows.bytes(), b"a\nb");
+        assert!(!editor_rows.ensure_final_newline(false));
+        assert_eq!(editor_rows.bytes(), b"a\nb\n");
+    }
+
+    #[test]
+    fn collapses_trailing_blank_lines() {
+        let config = Config::default();
+        let path = temp_file("blank_lines.txt", "a\n\n\n\n");
+        let mut editor_rows = EditorRows::from_file(path, &config).unwrap();
+        assert!(!editor_rows.ensure_final_newline(false));
+        assert_eq!(editor_rows.bytes(), b"a\n\n\n\n");
+        assert!(editor_rows.ensure_final_newline(true));
+        assert_eq!(editor_rows.bytes(), b"a\n");
+    }
+
+    #[test]
+    fn adopts_the_detected_indentation() {
+        let config = Config::default();
+        let rows = |indent: &str| vec!["a".to_string(), format!("{}b", indent), "c".to_string()];
+        let mut editor_rows = EditorRows::with_rows(None, rows("  "), &config);
+        editor_rows.detect_indentation();
+        assert_eq!((editor_rows.soft_tabs, editor_rows.tab_stop), (true, 2));
+        let mut editor_rows = EditorRows::with_rows(None, rows("\t"), &config);
+        editor_rows.detect_indentation();
+        assert_eq!((editor_rows.soft_tabs, editor_rows.tab_stop), (false, config.tab_stop));
+    }
+
+    #[test]
+    fn selects_the_whole_buffer() {
+        let mut editor = editor(&["ab", "cd"]);
+        press(&mut editor, &[ctrl('a')]);
+        assert_eq!(editor.output.selection(), Some(((0, 0), (1, 2))));
+        assert!(editor.output.delete_selection());
+        assert_eq!(rows(&editor), [""]);
+    }
+
+    #[test]
+    fn typing_replaces_a_selection_extended_with_shift_arrows() {
+        let mut editor = editor(&["abcd", "efgh"]);
+        type_keys(&mut editor, "i");
+        press(&mut editor, &[shift(KeyCode::Right), shift(KeyCode::Right), shift(KeyCode::Down)]);
+        assert_eq!(editor.output.selection(), Some(((0, 0), (1, 2))));
+        type_keys(&mut editor, "x");
+        assert_eq!(rows(&editor), ["xgh"]);
+        assert_eq!(editor.output.selection(), None);
+    }
+
+    #[test]
+    fn selects_backwards_from_the_anchor() {
+        let mut editor = editor(&["abcd", "efgh"]);
+        type_keys(&mut editor, "jlli");
+        press(&mut editor, &[shift(KeyCode::Up), shift(KeyCode::Left)]);
+        assert_eq!(editor.output.selection(), Some(((0, 1), (1, 2))));
+        assert!(editor.output.delete_selection());
+        assert_eq!(rows(&editor), ["agh"]);
+        assert_eq!(editor.output.cursor(), (0, 1));
+    }
+
+    #[test]
+    fn visual_selection_takes_in_the_char_under_the_cursor() {
+        let mut editor = editor(&["abcd"]);
+        type_keys(&mut editor, "lvl");
+        assert_eq!(editor.output.selection(), Some(((0, 1), (0, 3))));
+        type_keys(&mut editor, "d");
+        assert_eq!(rows(&editor), ["ad"]);
+    }
+
+    #[test]
+    fn copies_a_selection_within_a_row() {
+        let mut editor = editor(&["abcd"]);
+        type_keys(&mut editor, "li");
+        press(&mut editor, &[shift(KeyCode::Right), shift(KeyCode::Right), ctrl('c')]);
+        assert_eq!(editor.output.clipboard.text, "bc");
+        assert_eq!(rows(&editor), ["abcd"]);
+        press(&mut editor, &[KeyCode::End.into(), ctrl('v')]);
+        assert_eq!(rows(&editor), ["abcdbc"]);
+    }
+
+    #[test]
+    fn cuts_a_selection_across_rows_keeping_its_newlines() {
+        let mut editor = editor(&["abc", "def", "ghi"]);
+        type_keys(&mut editor, "li");
+        press(&mut editor, &[shift(KeyCode::Down), shift(KeyCode::Down), ctrl('x')]);
+        assert_eq!(editor.output.clipboard.text, "bc\ndef\ng");
+        assert_eq!(rows(&editor), ["ahi"]);
+        assert_eq!(editor.output.selection(), None);
+        press(&mut editor, &[ctrl('v')]);
+        assert_eq!(rows(&editor), ["abc", "def", "ghi"]);
+        assert_eq!(editor.output.cursor(), (2, 1));
+    }
+
+    #[test]
+    fn pastes_over_a_selection() {
+        let mut editor = editor(&["one two"]);
+        type_keys(&mut editor, "i");
+        press(&mut editor, &[shift(KeyCode::Right), shift(KeyCode::Right), shift(KeyCode::Right)]);
+        press(&mut editor, &[ctrl('c'), KeyCode::End.into()]);
+        press(&mut editor, &[shift(KeyCode::Left), shift(KeyCode::Left), shift(KeyCode::Left)]);
+        press(&mut editor, &[ctrl('v')]);
+        assert_eq!(rows(&editor), ["one one"]);
+        assert_eq!(editor.output.selection(), None);
+    }
+
+    #[test]
+    fn hands_yanked_rows_to_the_terminal_with_osc52_clipboard() {
+        let config = Config {
+            osc52_clipboard: true,
+            ..Config::default()
+        };
+        let rows = vec!["hi".to_string(), "there".to_string()];
+        let mut editor = editor_for(EditorRows::with_rows(None, rows, &config), config);
+        type_keys(&mut editor, "yy");
+        assert_eq!(editor.output.editor_contents.content, osc52::copy_sequence("hi\n"));
+        editor.output.config.osc52_clipboard = false;
+        type_keys(&mut editor, "jyy");
+        assert_eq!(editor.output.clipboard.text, "there");
+        assert_eq!(editor.output.editor_contents.content, osc52::copy_sequence("hi\n"));
+    }
+
+    #[test]
+    fn replays_a_recorded_insert_and_move() {
+        let mut editor = editor(&["a", "b", "c", "d"]);
+        type_keys(&mut editor, "qaI-");
+        press(&mut editor, &[KeyCode::Esc.into()]);
+        type_keys(&mut editor, "jq");
+        assert_eq!(rows(&editor), ["-a", "b", "c", "d"]);
+        type_keys(&mut editor, "@a");
+        assert_eq!(rows(&editor), ["-a", "-b", "c", "d"]);
+        type_keys(&mut editor, "@@");
+        assert_eq!(rows(&editor), ["-a", "-b", "-c", "d"]);
+        assert_eq!(editor.output.cursor().0, 3);
+        type_keys(&mut editor, "u");
+        assert_eq!(rows(&editor), ["-a", "-b", "c", "d"]);
+    }
+
+    #[test]
+    fn replays_a_macro_recorded_with_alt_r_a_count_of_times() {
+        let mut editor = editor(&["a", "b", "c", "d"]);
+        let alt_r = KeyEvent::new(KeyCode::Char('r'), KeyModifiers::ALT);
+        press(&mut editor, &[alt_r]);
+        type_keys(&mut editor, "A!");
+        press(&mut editor, &[KeyCode::Esc.into()]);
+        type_keys(&mut editor, "j");
+        press(&mut editor, &[alt_r]);
+        type_keys(&mut editor, "2");
+        press(&mut editor, &[ctrl('y')]);
+        assert_eq!(rows(&editor), ["a!", "b!", "c!", "d"]);
+    }
+
+    /// The names of the open buffers in tab line order.
+    fn buffer_names(editor: &Editor) -> Vec<&str> {
+        let output = &editor.output;
+        output.buffer_order.iter().map(|&id| output.buffer(id).display_name()).collect()
+    }
+
+    #[test]
+    fn opens_files_as_buffers_in_place_of_the_empty_one() {
+        let a = temp_file("open_a.txt", "a\n");
+        let b = temp_file("open_b.txt", "b\n");
+        let mut editor = editor(&[""]);
+        editor.open_file(a.to_str().unwrap());
+        assert_eq!(buffer_names(&editor), ["open_a.txt"]);
+        editor.open_file(b.to_str().unwrap());
+        assert_eq!(buffer_names(&editor), ["open_a.txt", "open_b.txt"]);
+        assert_eq!(rows(&editor), ["b"]);
+        editor.open_file(a.to_str().unwrap());
+        assert_eq!(buffer_names(&editor), ["open_a.txt", "open_b.txt"]);
+        assert_eq!(rows(&editor), ["a"]);
+    }
+
+    #[test]
+    fn cycles_buffers_keeping_each_cursor() {
+        let a = temp_file("cycle_a.txt", "a\naa\n");
+        let b = temp_file("cycle_b.txt", "b\n");
+        let mut editor = editor(&[""]);
+        editor.open_file(a.to_str().unwrap());
+        editor.open_file(b.to_str().unwrap());
+        editor.output.cycle_buffer(1);
+        assert_eq!(editor.output.editor_rows.display_name(), "cycle_a.txt");
+        type_keys(&mut editor, "jl");
+        editor.output.cycle_buffer(-1);
+        assert_eq!(editor.output.editor_rows.display_name(), "cycle_b.txt");
+        assert_eq!(editor.output.cursor(), (0, 0));
+        editor.output.cycle_buffer(-1);
+        assert_eq!(editor.output.cursor(), (1, 1));
+    }
+
+    #[test]
+    fn keeps_a_cursor_for_each_of_three_buffers() {
+        let a = temp_file("three_a.txt", "a\naa\n");
+        let b = temp_file("three_b.txt", "b\nbb\n");
+        let c = temp_file("three_c.txt", "ccc\n");
+        let mut editor = editor(&[""]);
+        for file in [&a, &b, &c] {
+            editor.open_file(file.to_str().unwrap());
+        }
+        editor.output.cycle_buffer(1);
+        type_keys(&mut editor, "jl");
+        editor.output.cycle_buffer(1);
+        type_keys(&mut editor, "j");
+        editor.output.cycle_buffer(1);
+        type_keys(&mut editor, "ll");
+        let cursors = [("three_a.txt", (1, 1)), ("three_b.txt", (1, 0)), ("three_c.txt", (0, 2))];
+        for (name, cursor) in cursors {
+            editor.output.cycle_buffer(1);
+            assert_eq!(editor.output.editor_rows.display_name(), name);
+            assert_eq!(editor.output.cursor(), cursor);
+        }
+    }
+
+    #[test]
+    fn leaves_out_files_that_cant_be_read() {
+        let a = temp_file("unreadable_a.txt", "a\n");
+        let dir = a.parent().unwrap().to_str().unwrap().to_string();
+        let files = vec![dir.clone(), a.to_str().unwrap().to_string()];
+        let (opened, errors) = EditorRows::from_files(files, &Config::default());
+        assert_eq!(opened.len(), 1);
+        assert_eq!(opened[0].display_name(), "unreadable_a.txt");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with(&format!("Can't open {}: ", dir)));
+    }
+
+    #[test]
+    fn closes_buffers_down_to_an_empty_one() {
+        let a = temp_file("close_a.txt", "a\n");
+        let b = temp_file("close_b.txt", "b\n");
+        let mut editor = editor(&[""]);
+        editor.open_file(a.to_str().unwrap());
+        editor.open_file(b.to_str().unwrap());
+        type_keys(&mut editor, "x");
+        editor.close_buffer(true).unwrap();
+        assert_eq!(buffer_names(&editor), ["close_a.txt"]);
+        assert_eq!(rows(&editor), ["a"]);
+        assert_eq!(fs::read_to_string(&b).unwrap(), "b\n");
+        editor.close_buffer(false).unwrap();
+        assert_eq!(buffer_names(&editor), ["[No Name]"]);
+        assert_eq!(rows(&editor), [""]);
+    }
+
+    #[test]
+    fn sets_an_option_from_the_command_line() {
+        let mut editor = editor(&["\tx"]);
+        editor.execute_command("set tab_stop=4", None).unwrap();
+        assert_eq!(editor.output.config.tab_stop, 4);
+        let row = editor.output.editor_rows.get_editor_row(0);
+        assert_eq!(row.get_render_x(1, editor.output.editor_rows.tab_stop), 4);
+        editor.execute_command("set tabstop=2", None).unwrap();
+        assert_eq!(editor.output.config.tab_stop, 4);
+        assert!(editor.output.status_message.message.is_some());
+    }
+
+    #[test]
+    fn sorts_only_the_selected_rows() {
+        let mut editor = editor(&["d", "c", "b", "a"]);
+        editor.execute_command("sort", Some(1..=2)).unwrap();
+        assert_eq!(rows(&editor), ["d", "b", "c", "a"]);
+        assert_eq!(editor.output.selection(), Some(((1, 0), (2, 1))));
+        press(&mut editor, &[KeyCode::Esc.into()]);
+        editor.execute_command("sort!", None).unwrap();
+        assert_eq!(rows(&editor), ["d", "c", "b", "a"]);
+        type_keys(&mut editor, "u");
+        assert_eq!(rows(&editor), ["d", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn removes_rows_repeating_the_one_above() {
+        let mut editor = editor(&["a", "a", "b", "a", "a", "c"]);
+        editor.execute_command("dedup", None).unwrap();
+        assert_eq!(rows(&editor), ["a", "b", "a", "c"]);
+        assert_eq!(editor.output.status_message.message.as_deref(), Some("2 duplicate rows removed"));
+    }
+
+    #[test]
+    fn removes_every_repeated_row_with_a_bang() {
+        let mut editor = editor(&["a", "b", "a", "c", "b"]);
+        editor.execute_command("dedup!", None).unwrap();
+        assert_eq!(rows(&editor), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn dedups_only_the_selected_rows() {
+        let mut editor = editor(&["a", "a", "a", "b", "b"]);
+        editor.execute_command("uniq", Some(1..=3)).unwrap();
+        assert_eq!(rows(&editor), ["a", "a", "b", "b"]);
+        assert_eq!(editor.output.selection(), Some(((1, 0), (2, 1))));
+    }
+
+    #[test]
+    fn changes_case_over_a_range_of_rows() {
+        let config = Config::default();
+        let rows = vec!["one two".to_string(), "three".to_string(), "four five".to_string()];
+        let mut editor_rows = EditorRows::with_rows(None, rows, &config);
+        assert!(editor_rows.change_case((0, 4), (2, 4), Case::Upper));
+        assert_eq!(editor_rows.row_strings(), ["one TWO", "THREE", "FOUR five"]);
+        assert!(editor_rows.change_case((0, 1), (0, 7), Case::Title));
+        assert_eq!(editor_rows.row_strings(), ["one Two", "THREE", "FOUR five"]);
+        assert!(!editor_rows.change_case((1, 0), (1, 5), Case::Upper));
+        assert_eq!(editor_rows.dirty, 2);
+    }
+
+    #[test]
+    fn toggles_case_within_a_row() {
+        let config = Config::default();
+        let mut editor_rows = EditorRows::with_rows(None, vec!["Hello World".to_string()], &config);
+        assert!(editor_rows.toggle_case(0, 0, 7));
+        assert_eq!(editor_rows.row_strings(), ["hELLO world"]);
+        assert!(!editor_rows.toggle_case(0, 5, 6));
+
+        let mut editor = editor(&["abc déf"]);
+        type_keys(&mut editor, "2~");
+        assert_eq!(rows(&editor), ["ABc déf"]);
+        assert_eq!(editor.output.cursor(), (0, 2));
+        type_keys(&mut editor, "w9~");
+        assert_eq!(rows(&editor), ["ABc DÉF"]);
+    }
+
+    #[test]
+    fn deletes_the_word_before_the_cursor() {
+        let mut editor = editor(&["foo", "bar.baz qux"]);
+        type_keys(&mut editor, "jA");
+        let ctrl_backspace = KeyEvent::new(KeyCode::Backspace, KeyModifiers::CONTROL);
+        press(&mut editor, &[ctrl_backspace]);
+        assert_eq!(rows(&editor), ["foo", "bar.baz "]);
+        press(&mut editor, &[ctrl('h')]);
+        assert_eq!(rows(&editor), ["foo", "bar."]);
+        press(&mut editor, &[ctrl_backspace, ctrl_backspace]);
+        assert_eq!(rows(&editor), ["foo"]);
+        assert_eq!(editor.output.cursor(), (0, 3));
+    }
+
+    #[test]
+    fn deletes_the_word_after_the_cursor() {
+        let mut editor = editor(&["foo bar", "baz"]);
+        type_keys(&mut editor, "i");
+        let ctrl_delete = KeyEvent::new(KeyCode::Delete, KeyModifiers::CONTROL);
+        press(&mut editor, &[ctrl_delete]);
+        assert_eq!(rows(&editor), [" bar", "baz"]);
+        press(&mut editor, &[ctrl_delete]);
+        assert_eq!(rows(&editor), ["", "baz"]);
+        press(&mut editor, &[ctrl_delete]);
+        assert_eq!(rows(&editor), ["baz"]);
+        assert_eq!(editor.output.cursor(), (0, 0));
+    }
+
+    #[test]
+    fn closes_brackets_and_types_over_the_closing_char() {
+        let mut editor = editor(&[""]);
+        type_keys(&mut editor, "if(a[");
+        assert_eq!(rows(&editor), ["f(a[])"]);
+        type_keys(&mut editor, "0])");
+        assert_eq!(rows(&editor), ["f(a[0])"]);
+        assert_eq!(editor.output.cursor(), (0, 7));
+        type_keys(&mut editor, ")");
+        assert_eq!(rows(&editor), ["f(a[0]))"]);
+    }
+
+    #[test]
+    fn deletes_an_empty_pair_with_backspace() {
+        let mut editor = editor(&[""]);
+        type_keys(&mut editor, "i\"");
+        assert_eq!(rows(&editor), ["\"\""]);
+        press(&mut editor, &[KeyCode::Backspace.into()]);
+        assert_eq!(rows(&editor), [""]);
+    }
+
+    #[test]
+    fn auto_close_can_be_turned_off() {
+        let mut editor = editor(&[""]);
+        editor.output.config.auto_close = false;
+        type_keys(&mut editor, "i(");
+        assert_eq!(rows(&editor), ["("]);
+    }
+
+    #[test]
+    fn indents_and_dedents_the_selected_rows() {
+        let mut editor = editor(&["a", "", "b", "c"]);
+        editor.output.editor_rows.soft_tabs = true;
+        editor.output.editor_rows.tab_stop = 4;
+        type_keys(&mut editor, "i");
+        press(&mut editor, &[shift(KeyCode::Down), shift(KeyCode::Down), KeyCode::Tab.into()]);
+        assert_eq!(rows(&editor), ["    a", "", "b", "c"]);
+        assert_eq!(editor.output.selection(), Some(((0, 4), (2, 0))));
+        press(&mut editor, &[shift(KeyCode::Right), KeyCode::Tab.into()]);
+        assert_eq!(rows(&editor), ["        a", "", "    b", "c"]);
+        assert_eq!(editor.output.selection(), Some(((0, 8), (2, 5))));
+        press(&mut editor, &[KeyCode::BackTab.into(), KeyCode::BackTab.into()]);
+        assert_eq!(rows(&editor), ["a", "", "b", "c"]);
+    }
+
+    #[test]
+    fn dedents_the_cursor_row_with_shift_tab() {
+        let mut editor = editor(&["\t\ta", "\tb"]);
+        type_keys(&mut editor, "i");
+        press(&mut editor, &[KeyCode::BackTab.into()]);
+        assert_eq!(rows(&editor), ["\ta", "\tb"]);
+        press(&mut editor, &[KeyCode::Tab.into()]);
+        assert_eq!(rows(&editor), ["\t\ta", "\tb"]);
+    }
+
+    #[test]
+    fn toggles_comments_on_the_selected_rows() {
+        let config = Config::default();
+        let lines = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let editor_rows = EditorRows::with_rows(Some("x.rs".into()), lines, &config);
+        let mut editor = editor_for(editor_rows, config);
+        type_keys(&mut editor, "vj");
+        editor.output.toggle_comment(1);
+        assert_eq!(rows(&editor), ["// a", "// b", "c"]);
+        press(&mut editor, &[KeyCode::Esc.into()]);
+        type_keys(&mut editor, "k");
+        editor.output.toggle_comment(2);
+        assert_eq!(rows(&editor), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn types_without_modes_unless_modal_editing_is_on() {
+        let config = Config::default();
+        let editor_rows = EditorRows::with_rows(None, vec!["world".to_string()], &config);
+        let mut editor = editor_as_configured(editor_rows, config);
+        assert!(editor.output.mode == Mode::Insert);
+        type_keys(&mut editor, "ab");
+        press(&mut editor, &[KeyCode::Esc.into(), KeyCode::End.into()]);
+        type_keys(&mut editor, "cd");
+        assert!(editor.output.mode == Mode::Insert);
+        assert_eq!(rows(&editor), ["abworldcd"]);
+        press(&mut editor, &[ctrl('z')]);
+        assert_eq!(rows(&editor), ["abworld"]);
+        press(&mut editor, &[ctrl('z')]);
+        assert_eq!(rows(&editor), ["world"]);
+
+        let mut config = Config::default();
+        config.set("modal", "true").unwrap();
+        let editor_rows = EditorRows::with_rows(None, vec!["world".to_string()], &config);
+        let mut editor = editor_as_configured(editor_rows, config);
+        assert!(editor.output.mode == Mode::Normal);
+        type_keys(&mut editor, "x");
+        assert_eq!(rows(&editor), ["orld"]);
+    }
+
+    #[test]
+    fn shows_tabs_and_trailing_spaces() {
+        let row = Row::new("\ta b  ".into(), 4);
+        let mut highlight = row.highlight.clone();
+        assert_eq!(row.visible_render(&mut highlight, 4, false), "→   a b··");
+        assert_eq!(highlight[0], Whitespace);
+        assert_eq!(highlight[1..6], [Normal; 5]);
+        assert_eq!(highlight[7..], [TrailingWhitespace; 2]);
+    }
+
+    #[test]
+    fn shows_every_space_with_show_all_spaces() {
+        let row = Row::new("a b\u{a0}c".into(), 4);
+        let mut highlight = row.highlight.clone();
+        assert_eq!(row.visible_render(&mut highlight, 4, true), "a·b°c");
+        assert_eq!(highlight[1], Whitespace);
+        let mut highlight = row.highlight.clone();
+        assert_eq!(row.visible_render(&mut highlight, 4, false), "a b°c");
+        assert_eq!(highlight[1], Normal);
+    }
+
+    /// The text `draw_rows` drew for the first `height` screen rows, without
+    /// escape sequences, one line per row.
+    fn drawn_rows(editor: &mut Editor, height: usize) -> Vec<String> {
+        let rect = Rect {
+            left: 0,
+            top: 0,
+            width: 80,
+            height,
+        };
+        editor.output.editor_contents.content.clear();
+        editor.output.draw_rows(rect, false);
+        let mut lines = Vec::new();
+        let mut chars = editor.output.editor_contents.content.chars();
+        while let Some(ch) = chars.next() {
+            if ch == '\x1b' {
+                // Skip to the letter ending the sequence; a move starts a row.
+                let end = chars.by_ref().find(|ch| ch.is_ascii_alphabetic());
+                if end == Some('H') {
+                    lines.push(String::new());
+                }
+            } else if let Some(line) = lines.last_mut() {
+                line.push(ch);
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn draws_the_eol_marker_after_each_row() {
+        let mut editor = editor(&["ab", "", "c d"]);
+        editor.output.config.line_numbers = false;
+        assert_eq!(drawn_rows(&mut editor, 4), ["ab", "", "c d", "~"]);
+        editor.output.config.set("eol_marker", "$").unwrap();
+        assert_eq!(drawn_rows(&mut editor, 4), ["ab$", "$", "c d$", "~"]);
+        editor.output.config.set("eol_marker", "").unwrap();
+        assert_eq!(editor.output.config.eol_marker, None);
+        assert!(editor.output.config.set("eol_marker", "ab").is_err());
+    }
+
+    #[test]
+    fn reloads_a_clean_buffer_changed_on_disk() {
+        let path = temp_file("reload.txt", "one\ntwo\nthree\n");
+        let mut editor = editor(&[""]);
+        editor.open_file(path.to_str().unwrap());
+        type_keys(&mut editor, "G");
+        fs::write(&path, "uno\n").unwrap();
+        press(&mut editor, &[KeyCode::F(5).into()]);
+        assert_eq!(rows(&editor), ["uno"]);
+        assert_eq!(editor.output.cursor(), (0, 0));
+        assert_eq!(editor.output.editor_rows.dirty, 0);
+    }
+
+    #[test]
+    fn asks_before_reloading_over_unsaved_changes() {
+        let path = temp_file("reload_dirty.txt", "one\n");
+        let mut editor = editor(&[""]);
+        editor.open_file(path.to_str().unwrap());
+        type_keys(&mut editor, "x");
+        fs::write(&path, "uno\n").unwrap();
+        // Any answer but `y` keeps the changes, rather than being typed.
+        press(&mut editor, &[KeyCode::F(5).into(), KeyCode::Char('x').into()]);
+        assert_eq!(rows(&editor), ["ne"]);
+        assert!(editor.output.editor_rows.dirty > 0);
+        press(&mut editor, &[KeyCode::F(5).into(), KeyCode::Char('y').into()]);
+        assert_eq!(rows(&editor), ["uno"]);
+        assert_eq!(editor.output.editor_rows.dirty, 0);
+    }
+
+    /// Has another program rewrite `path` with `contents`, a minute later
+    /// by its modification time.
+    fn change_on_disk(path: &Path, contents: &str) {
+        let modified = fs::metadata(path).unwrap().modified().unwrap();
+        fs::write(path, contents).unwrap();
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(modified + Duration::from_secs(60)).unwrap();
+    }
+
+    #[test]
+    fn notices_the_file_changing_on_disk_but_not_its_own_saves() {
+        let path = temp_file("watch.txt", "one\n");
+        let mut editor = editor(&[""]);
+        editor.open_file(path.to_str().unwrap());
+        assert!(!editor.output.editor_rows.changed_on_disk());
+        type_keys(&mut editor, "x");
+        assert!(editor.save().unwrap());
+        assert!(!editor.output.editor_rows.changed_on_disk());
+        change_on_disk(&path, "uno\n");
+        assert!(editor.output.editor_rows.changed_on_disk());
+    }
+
+    #[test]
+    fn offers_to_reload_a_clean_buffer_changed_on_disk() {
+        let path = temp_file("watch_clean.txt", "one\n");
+        let mut editor = editor(&[""]);
+        editor.open_file(path.to_str().unwrap());
+        change_on_disk(&path, "uno\n");
+        editor.reader.typed.push_back(KeyCode::Char('n').into());
+        editor.check_file_changed().unwrap();
+        assert_eq!(rows(&editor), ["one"]);
+        assert!(!editor.output.editor_rows.changed_on_disk());
+        change_on_disk(&path, "eins\n");
+        editor.reader.typed.push_back(KeyCode::Char('y').into());
+        editor.check_file_changed().unwrap();
+        assert_eq!(rows(&editor), ["eins"]);
+    }
+
+    #[test]
+    fn warns_of_a_conflict_with_unsaved_changes() {
+        let path = temp_file("watch_dirty.txt", "one\n");
+        let mut editor = editor(&[""]);
+        editor.open_file(path.to_str().unwrap());
+        type_keys(&mut editor, "x");
+        change_on_disk(&path, "uno\n");
+        editor.check_file_changed().unwrap();
+        assert_eq!(
+            editor.output.status_message.message.as_deref(),
+            Some("watch_dirty.txt was changed by another program; saving will overwrite its changes")
+        );
+        assert_eq!(rows(&editor), ["ne"]);
+        assert!(!editor.output.editor_rows.changed_on_disk());
+    }
+
+    #[test]
+    fn leaves_the_file_alone_when_saving_fails() {
+        let path = temp_file("atomic.txt", "one\n");
+        let mut editor = editor(&[""]);
+        editor.open_file(path.to_str().unwrap());
+        type_keys(&mut editor, "x");
+        // A directory in the way of the temporary file stops the write.
+        let temp = path.with_file_name(format!(".atomic.txt.{}.tmp", std::process::id()));
+        fs::create_dir(&temp).unwrap();
+        assert!(editor.output.editor_rows.save(false).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\n");
+        fs::remove_dir(&temp).unwrap();
+        assert_eq!(editor.output.editor_rows.save(false).unwrap(), 3);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ne\n");
+        assert!(!temp.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_the_file_mode_on_save() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = temp_file("mode.sh", "echo\n");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o751)).unwrap();
+        let mut editor = editor(&[""]);
+        editor.open_file(path.to_str().unwrap());
+        type_keys(&mut editor, "x");
+        assert!(editor.save().unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "cho\n");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o751);
+    }
+
+    #[test]
+    fn backs_the_file_up_on_the_first_save_only() {
+        let path = temp_file("backup.txt", "one\n");
+        let backup = path.with_file_name("backup.txt~");
+        let mut editor = editor(&[""]);
+        editor.output.config.make_backup = true;
+        editor.open_file(path.to_str().unwrap());
+        type_keys(&mut editor, "x");
+        assert!(editor.save().unwrap());
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "one\n");
+        type_keys(&mut editor, "x");
+        assert!(editor.save().unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "e\n");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "one\n");
+    }
+
+    #[test]
+    fn makes_no_backup_unless_asked() {
+        let path = temp_file("no_backup.txt", "one\n");
+        let mut editor = editor(&[""]);
+        editor.open_file(path.to_str().unwrap());
+        type_keys(&mut editor, "x");
+        assert!(editor.save().unwrap());
+        assert!(!path.with_file_name("no_backup.txt~").exists());
+    }
+
+    /// Has the last key come `secs` seconds ago.
+    fn idle_for(editor: &mut Editor, secs: u64) {
+        editor.last_key = Instant::now().checked_sub(Duration::from_secs(secs)).unwrap();
+    }
+
+    #[test]
+    fn autosaves_once_the_keys_stop_for_the_interval() {
+        let path = temp_file("autosave.txt", "one\n");
+        let mut editor = editor(&[""]);
+        editor.output.config.autosave_interval = 5;
+        editor.open_file(path.to_str().unwrap());
+        type_keys(&mut editor, "x");
+        idle_for(&mut editor, 4);
+        assert!(!editor.autosave_due());
+        idle_for(&mut editor, 5);
+        assert!(editor.autosave_due());
+        editor.autosave().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ne\n");
+        assert_eq!(
+            editor.output.status_message.message.as_deref(),
+            Some("Auto-saved autosave.txt")
+        );
+        assert!(!editor.autosave_due());
+    }
+
+    #[test]
+    fn skips_autosave_without_changes_or_a_file_name() {
+        let path = temp_file("autosave_clean.txt", "one\n");
+        let mut editor = editor(&["unnamed"]);
+        editor.output.config.autosave_interval = 5;
+        type_keys(&mut editor, "x");
+        idle_for(&mut editor, 10);
+        assert!(!editor.autosave_due());
+        editor.open_file(path.to_str().unwrap());
+        idle_for(&mut editor, 10);
+        assert!(!editor.autosave_due());
+        type_keys(&mut editor, "ix");
+        idle_for(&mut editor, 10);
+        assert!(!editor.autosave_due());
+        editor.output.config.autosave_interval = 0;
+        press(&mut editor, &[KeyCode::Esc.into()]);
+        idle_for(&mut editor, 10);
+        assert!(!editor.autosave_due());
+    }
+
+    #[test]
+    fn jumps_to_a_mark_or_the_first_non_blank_of_its_row() {
+        let mut editor = editor(&["  one two", "three"]);
+        type_keys(&mut editor, "wwma");
+        assert_eq!(editor.output.cursor(), (0, 6));
+        type_keys(&mut editor, "j`a");
+        assert_eq!(editor.output.cursor(), (0, 6));
+        type_keys(&mut editor, "j'a");
+        assert_eq!(editor.output.cursor(), (0, 2));
+        type_keys(&mut editor, "'b");
+        assert_eq!(editor.output.status_message.message.as_deref(), Some("Mark b not set"));
+    }
+
+    #[test]
+    fn lists_the_jump_list() {
+        let mut editor = editor(&["a", "b", "c"]);
+        editor.execute_command("jumps", None).unwrap();
+        assert_eq!(editor.output.status_message.message.as_deref(), Some("No jumps yet"));
+        type_keys(&mut editor, "G");
+        editor.execute_command("jumps", None).unwrap();
+        assert_eq!(editor.output.status_message.message.as_deref(), Some("Jumps: 1:1"));
+    }
+
+    #[test]
+    fn folds_an_indented_block_away_with_za() {
+        let mut editor = editor(&["a:", "    b", "    c", "d"]);
+        type_keys(&mut editor, "za");
+        assert_eq!(editor.output.editor_rows.folds.under(0), Some(2));
+        type_keys(&mut editor, "j");
+        assert_eq!(editor.output.cursor(), (3, 0));
+        type_keys(&mut editor, "kza");
+        assert!(editor.output.editor_rows.folds.is_empty());
+        type_keys(&mut editor, "jza");
+        assert_eq!(editor.output.editor_rows.folds.under(0), Some(2));
+        assert_eq!(editor.output.cursor(), (0, 0));
+    }
+
+    #[test]
+    fn draws_a_closed_fold_as_its_header_and_a_marker() {
+        let mut editor = editor(&["a:", "    b", "    c", "d"]);
+        type_keys(&mut editor, "za");
+        let lines = drawn_rows(&mut editor, 3);
+        assert_eq!(lines[0].trim_end(), "a: ▸ 2 lines");
+        assert_eq!(lines[1].trim_end(), "d");
+        assert!(lines.iter().all(|line| !line.contains('b') && !line.contains('c')));
+    }
+
+    #[test]
+    fn shows_a_binary_file_in_a_read_only_hex_view() {
+        let path = temp_file("binary.bin", "ab\0cd");
+        let mut editor = editor(&[""]);
+        editor.open_file(path.to_str().unwrap());
+        let hex = editor.output.editor_rows.hex.as_ref().unwrap();
+        assert_eq!(hex.bytes, b"ab\0cd");
+        type_keys(&mut editor, "lxi");
+        let hex = editor.output.editor_rows.hex.as_ref().unwrap();
+        assert_eq!((hex.cursor, &hex.bytes[..]), (1, &b"ab\0cd"[..]));
+        assert_eq!(editor.output.editor_rows.dirty, 0);
+    }
+
+    #[test]
+    fn compares_two_files_side_by_side() {
+        let config = Config::default();
+        let lines = |rows: &[&str]| rows.iter().map(|row| row.to_string()).collect();
+        let old = EditorRows::with_rows(None, lines(&["a", "b", "c"]), &config);
+        let new = EditorRows::with_rows(None, lines(&["a", "x", "c", "d"]), &config);
+        let mut output = Output::with_buffers((80, 24), config, old, vec![new]);
+        output.compare_files();
+        assert_eq!(output.views.len(), 2);
+        assert_eq!(output.status_message.message.as_deref(), Some("2 changes"));
+        assert_eq!(output.editor_rows.hunk_rows().len(), 2);
+    }
+
+    #[test]
+    fn builds_bytes_as_saving_writes_them() {
+        let path = temp_file("bytes.txt", "ab\ncd\n");
+        let mut editor = editor(&[""]);
+        editor.open_file(path.to_str().unwrap());
+        assert_eq!(editor.output.editor_rows.bytes(), b"ab\ncd\n");
+        type_keys(&mut editor, "x");
+        assert!(editor.save().unwrap());
+        assert_eq!(fs::read(&path).unwrap(), editor.output.editor_rows.bytes());
+    }
+
+    #[test]
+    fn cuts_the_message_to_the_screen_by_chars() {
+        let mut editor = editor(&[""]);
+        editor.output.win_size.0 = 5;
+        editor.output.status_message.set_message("Can't open café.txt".into());
+        editor.output.editor_contents.content.clear();
+        editor.output.draw_message_bar();
+        assert!(editor.output.editor_contents.content.ends_with("Can't"));
+        editor.output.status_message.set_message("Save as: naïve".into());
+        editor.output.win_size.0 = 12;
+        editor.output.editor_contents.content.clear();
+        editor.output.draw_message_bar();
+        assert!(editor.output.editor_contents.content.ends_with("Save as: naï"));
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+
+/// Named positions in a buffer, set with a letter `a`-`z`. Marks stay on
+/// their row as rows are inserted or deleted above them, and go away with
+/// the row itself.
+#[derive(Default)]
+pub struct Marks {
+    /// Letter to `(row, byte column)`.
+    marks: BTreeMap<char, (usize, usize)>,
+}
+
+impl Marks {
+    pub fn is_empty(&self) -> bool {
+        self.marks.is_empty()
+    }
+
+    pub fn get(&self, letter: char) -> Option<(usize, usize)> {
+        self.marks.get(&letter).copied()
+    }
+
+    /// Sets mark `letter` at `pos`, or deletes it if it is already there.
+    /// Returns whether the mark is now set.
+    pub fn toggle(&mut self, letter: char, pos: (usize, usize)) -> bool {
+        if self.marks.get(&letter) == Some(&pos) {
+            self.marks.remove(&letter);
+            return false;
+        }
+        self.marks.insert(letter, pos);
+        true
+    }
+
+    /// The first mark on `row`, for the gutter.
+    pub fn on_row(&self, row: usize) -> Option<char> {
+        self.marks
+            .iter()
+            .find(|(_, &(mark_row, _))| mark_row == row)
+            .map(|(&letter, _)| letter)
+    }
+
+    /// Lists the marks as `letter:line:column`, numbered from 1.
+    pub fn summary(&self) -> String {
+        self.marks
+            .iter()
+            .map(|(letter, (row, col))| format!("{}:{}:{}", letter, row + 1, col + 1))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Shifts marks down past a row inserted at `at`.
+    pub fn row_inserted(&mut self, at: usize) {
+        for (row, _) in self.marks.values_mut() {
+            if *row >= at {
+                *row += 1;
+            }
+        }
+    }
+
+    /// Drops marks on row `at` and shifts the ones below it up.
+    pub fn row_deleted(&mut self, at: usize) {
+        self.marks.retain(|_, &mut (row, _)| row != at);
+        for (row, _) in self.marks.values_mut() {
+            if *row > at {
+                *row -= 1;
+            }
+        }
+    }
+
+    /// Moves marks on row `at + 1` onto the end of row `at`, `len` bytes
+    /// long before the join.
+    pub fn rows_joined(&mut self, at: usize, len: usize) {
+        for (row, col) in self.marks.values_mut() {
+            if *row == at + 1 {
+                *row = at;
+                *col += len;
+            } else if *row > at + 1 {
+                *row -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets_and_deletes_a_mark_on_the_same_spot() {
+        let mut marks = Marks::default();
+        assert!(marks.toggle('a', (1, 2)));
+        assert!(marks.toggle('a', (3, 0)));
+        assert_eq!(marks.get('a'), Some((3, 0)));
+        assert!(!marks.toggle('a', (3, 0)));
+        assert!(marks.is_empty());
+    }
+
+    #[test]
+    fn keeps_marks_on_their_rows() {
+        let mut marks = Marks::default();
+        marks.toggle('a', (1, 0));
+        marks.toggle('b', (3, 2));
+        marks.row_inserted(2);
+        assert_eq!((marks.get('a'), marks.get('b')), (Some((1, 0)), Some((4, 2))));
+        marks.row_deleted(1);
+        assert_eq!((marks.get('a'), marks.get('b')), (None, Some((3, 2))));
+        marks.rows_joined(2, 5);
+        assert_eq!(marks.get('b'), Some((2, 7)));
+        assert_eq!(marks.summary(), "b:3:8");
+    }
+}
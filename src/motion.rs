@@ -0,0 +1,182 @@
+use crate::text_object;
+use crate::word_motion::{self, Lines, WordMotion};
+
+/// A cursor movement that an operator can act over.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// A word motion, over WORDs when `big`.
+    Word(WordMotion, bool),
+    /// `0`
+    LineStart,
+    /// `$`
+    LineEnd,
+    /// `gg`, or line `count` when given one.
+    FirstLine,
+    /// `G`, or line `count` when given one.
+    LastLine,
+    /// `f`, `F`, `t` or `T`, within the row.
+    FindChar(CharSearch),
+    /// `%`, to the bracket matching the one at or after the cursor.
+    MatchBracket,
+    /// `}`, or `{` without `forward`, to the blank row past the paragraph.
+    Paragraph(bool),
+}
+
+/// A search for a char on the cursor's row.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct CharSearch {
+    pub ch: char,
+    /// Searches to the right (`f`, `t`) rather than the left (`F`, `T`).
+    pub forward: bool,
+    /// Stops next to the char (`t`, `T`) rather than on it.
+    pub till: bool,
+}
+
+impl CharSearch {
+    /// The same search in the other direction, for `,`.
+    pub fn reversed(self) -> Self {
+        Self {
+            forward: !self.forward,
+            ..self
+        }
+    }
+}
+
+/// How much of the text between the start and end of a motion it covers.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MotionKind {
+    /// Up to but not including the end.
+    Exclusive,
+    /// Up to and including the char at the end.
+    Inclusive,
+    /// Every row from the start row to the end row.
+    Linewise,
+}
+
+impl Motion {
+    pub fn kind(self) -> MotionKind {
+        match self {
+            Motion::Left
+            | Motion::Right
+            | Motion::LineStart
+            | Motion::Paragraph(_)
+            | Motion::Word(WordMotion::NextStart | WordMotion::PrevStart, _) => {
+                MotionKind::Exclusive
+            }
+            Motion::Word(WordMotion::NextEnd, _) | Motion::LineEnd | Motion::MatchBracket => {
+                MotionKind::Inclusive
+            }
+            Motion::FindChar(search) if search.forward => MotionKind::Inclusive,
+            Motion::FindChar(_) => MotionKind::Exclusive,
+            Motion::Up | Motion::Down | Motion::FirstLine | Motion::LastLine => {
+                MotionKind::Linewise
+            }
+        }
+    }
+}
+
+/// Where `motion` repeated `count` times leads from `from`, as `(row, byte
+/// column)`. `count` is `None` when no count was typed, which matters to
+/// `gg` and `G`. Left and right stay on the row. A char search or bracket
+/// match that finds nothing stays put.
+pub fn target(
+    lines: &impl Lines,
+    from: (usize, usize),
+    motion: Motion,
+    count: Option<usize>,
+) -> (usize, usize) {
+    let (row, col) = from;
+    let times = count.unwrap_or(1);
+    let last_row = lines.line_count() - 1;
+    let line = lines.line(row);
+    match motion {
+        Motion::Left => (0..times).fold(from, |(row, col), _| {
+            (row, col - line[..col].chars().next_back().map_or(0, char::len_utf8))
+        }),
+        Motion::Right => (0..times).fold(from, |(row, col), _| {
+            (row, col + line[col..].chars().next().map_or(0, char::len_utf8))
+        }),
+        Motion::Up => column_on(lines, row.saturating_sub(times), col),
+        Motion::Down => column_on(lines, (row + times).min(last_row), col),
+        Motion::Word(word, big) => (0..times).fold(from, |from, _| {
+            word_motion::apply(lines, from, word, big)
+        }),
+        Motion::LineStart => (row, 0),
+        Motion::LineEnd => {
+            let row = (row + times - 1).min(last_row);
+            let line = lines.line(row);
+            (row, line.len() - line.chars().next_back().map_or(0, char::len_utf8))
+        }
+        Motion::FirstLine => (count.unwrap_or(1).clamp(1, last_row + 1) - 1, 0),
+        Motion::LastLine => (count.unwrap_or(last_row + 1).clamp(1, last_row + 1) - 1, 0),
+        Motion::FindChar(search) => (row, find_char(line, col, search, times).unwrap_or(col)),
+        Motion::MatchBracket => text_object::matching_bracket(lines, from).unwrap_or(from),
+        Motion::Paragraph(forward) => (0..times).fold(from, |(row, _), _| {
+            paragraph_end(lines, row, forward)
+        }),
+    }
+}
+
+/// The first blank row (of only whitespace) below `row` past some text, or
+/// above it without `forward`. Without one, the end of the last row or the
+/// start of the first.
+fn paragraph_end(lines: &impl Lines, mut row: usize, forward: bool) -> (usize, usize) {
+    let last_row = lines.line_count() - 1;
+    let mut seen_text = false;
+    loop {
+        row = match forward {
+            true if row < last_row => row + 1,
+            false if row > 0 => row - 1,
+            _ => break,
+        };
+        if !lines.line(row).trim().is_empty() {
+            seen_text = true;
+        } else if seen_text {
+            return (row, 0);
+        }
+    }
+    if forward {
+        (last_row, lines.line(last_row).len())
+    } else {
+        (0, 0)
+    }
+}
+
+/// The byte column `search` repeated `count` times leads to from `col` on
+/// `line`, or `None` if it runs out of matches.
+pub fn find_char(line: &str, col: usize, search: CharSearch, count: usize) -> Option<usize> {
+    let found = if search.forward {
+        let from = col + line[col..].chars().next().map_or(0, char::len_utf8);
+        line[from..]
+            .match_indices(search.ch)
+            .nth(count - 1)
+            .map(|(at, _)| from + at)
+    } else {
+        line[..col]
+            .rmatch_indices(search.ch)
+            .nth(count - 1)
+            .map(|(at, _)| at)
+    }?;
+    if !search.till {
+        return Some(found);
+    }
+    Some(if search.forward {
+        found - line[..found].chars().next_back().map_or(0, char::len_utf8)
+    } else {
+        found + search.ch.len_utf8()
+    })
+}
+
+/// `col` moved onto row `row`, clamped to it and onto a char boundary.
+fn column_on(lines: &impl Lines, row: usize, col: usize) -> (usize, usize) {
+    let line = lines.line(row);
+    let mut col = col.min(line.len());
+    while !line.is_char_boundary(col) {
+        col -= 1;
+    }
+    (row, col)
+}
@@ -0,0 +1,47 @@
+/// Chars that open a pair, with the char closing it.
+const PAIRS: [(char, char); 5] = [('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+
+/// The char closing a pair `open` opens.
+pub fn closing(open: char) -> Option<char> {
+    PAIRS.iter().find(|&&(o, _)| o == open).map(|&(_, close)| close)
+}
+
+/// Whether `ch` closes a pair.
+pub fn is_closing(ch: char) -> bool {
+    PAIRS.iter().any(|&(_, close)| close == ch)
+}
+
+/// Whether typing `open` between `before` and `after` should add its
+/// closing char too: only where nothing follows but whitespace or another
+/// closing char, and for quotes not straight after a word, where they are
+/// more likely apostrophes.
+pub fn should_close(open: char, before: Option<char>, after: Option<char>) -> bool {
+    let Some(close) = closing(open) else {
+        return false;
+    };
+    if close == open && before.is_some_and(|ch| ch.is_alphanumeric() || ch == '_') {
+        return false;
+    }
+    after.is_none_or(|ch| ch.is_whitespace() || (is_closing(ch) && ch != open))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closes_before_whitespace_or_a_closing_char() {
+        assert!(should_close('(', None, None));
+        assert!(should_close('[', Some('x'), Some(' ')));
+        assert!(should_close('{', Some('('), Some(')')));
+        assert!(!should_close('(', None, Some('x')));
+        assert!(!should_close('x', None, None));
+    }
+
+    #[test]
+    fn leaves_quotes_after_a_word_alone() {
+        assert!(should_close('"', Some(' '), None));
+        assert!(!should_close('\'', Some('n'), None));
+        assert!(!should_close('"', None, Some('"')));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::text_buffer::Snapshot;
+use crate::word_motion::is_word_char;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+/// Rows scanned between checks for cancellation and progress reports.
+const REPORT_EVERY: usize = 16 * 1024;
+
+/// What a search looks for.
+#[derive(Clone, Default, PartialEq)]
+pub struct Query {
+    pub text: String,
+    /// Only match where the neighbouring chars are not word chars.
+    pub whole_word: bool,
+}
+
+impl Query {
+    pub fn new(text: String) -> Self {
+        Self {
+            text,
+            whole_word: false,
+        }
+    }
+
+    /// Byte columns of the matches in `line`.
+    pub fn matches<'a>(&'a self, line: &'a str) -> impl Iterator<Item = usize> + 'a {
+        line.match_indices(self.text.as_str())
+            .map(|(col, _)| col)
+            .filter(move |&col| {
+                !self.whole_word
+                    || (line[..col].chars().next_back().is_none_or(|ch| !is_word_char(ch))
+                        && line[col + self.text.len()..]
+                            .chars()
+                            .next()
+                            .is_none_or(|ch| !is_word_char(ch)))
+            })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+pub enum SearchEvent {
+    /// The match nearest the starting point, as `(row, byte column)`, and
+    /// whether the search went past the end of the buffer (or its start,
+    /// backward) to get there.
+    Found(usize, usize, bool),
+    /// Matches counted so far.
+    Counted(usize),
+    /// Every row has been scanned. `index` is the 1-based position of the
+    /// found match among all `total` matches.
+    Finished { index: Option<usize>, total: usize },
+}
+
+/// Scans a snapshot of the buffer for a query on a worker thread.
+///
+/// The first match is reported as soon as it is found, followed by a count
+/// of all matches. Dropping the job cancels the scan.
+pub struct SearchJob {
+    receiver: Receiver<SearchEvent>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl SearchJob {
+    pub fn spawn(
+        snapshot: Snapshot,
+        query: Query,
+        from: (usize, usize),
+        direction: SearchDirection,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancelled = Arc::clone(&cancel);
+        thread::spawn(move || {
+            let Some(found) = find(&snapshot, &query, from, direction, &cancelled) else {
+                return;
+            };
+            if let Some((row, col)) = found {
+                let wrapped = match direction {
+                    SearchDirection::Forward => (row, col) < from,
+                    SearchDirection::Backward => (row, col) >= from,
+                };
+                if sender.send(SearchEvent::Found(row, col, wrapped)).is_err() {
+                    return;
+                }
+            }
+            let mut total = 0;
+            let mut before = 0;
+            for row in 0..snapshot.line_count() {
+                if row % REPORT_EVERY == 0 {
+                    if cancelled.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    if row > 0 && sender.send(SearchEvent::Counted(total)).is_err() {
+                        return;
+                    }
+                }
+                for col in query.matches(snapshot.line(row)) {
+                    if found.is_some_and(|found| (row, col) < found) {
+                        before += 1;
+                    }
+                    total += 1;
+                }
+            }
+            let index = found.map(|_| before + 1);
+            let _ = sender.send(SearchEvent::Finished { index, total });
+        });
+        Self { receiver, cancel }
+    }
+
+    /// Events reported since the last call, without waiting for more.
+    pub fn events(&self) -> Vec<SearchEvent> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+impl Drop for SearchJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Finds the match closest to `from` in `direction`, wrapping around the end
+/// of the buffer. Returns `None` if cancelled.
+fn find(
+    snapshot: &Snapshot,
+    query: &Query,
+    from: (usize, usize),
+    direction: SearchDirection,
+    cancelled: &AtomicBool,
+) -> Option<Option<(usize, usize)>> {
+    let len = snapshot.line_count();
+    // Step `len` comes back to the starting row for the part of it that was
+    // skipped on the first visit.
+    for step in 0..=len {
+        if step % REPORT_EVERY == 0 && cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        let row = match direction {
+            SearchDirection::Forward => (from.0 + step) % len,
+            SearchDirection::Backward => (from.0 + len - step % len) % len,
+        };
+        let mut matches = query.matches(snapshot.line(row));
+        let col = match (direction, step) {
+            (SearchDirection::Forward, 0) => matches.find(|&col| col >= from.1),
+            (SearchDirection::Forward, _) if step == len => {
+                matches.next().filter(|&col| col < from.1)
+            }
+            (SearchDirection::Forward, _) => matches.next(),
+            (SearchDirection::Backward, 0) => matches.filter(|&col| col < from.1).last(),
+            (SearchDirection::Backward, _) if step == len => {
+                matches.last().filter(|&col| col >= from.1)
+            }
+            (SearchDirection::Backward, _) => matches.last(),
+        };
+        if let Some(col) = col {
+            return Some(Some((row, col)));
+        }
+    }
+    Some(None)
+}
@@ -0,0 +1,126 @@
+use std::cmp::Ordering;
+
+/// How `:sort` orders rows.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct SortOptions {
+    /// Largest first.
+    pub reverse: bool,
+    /// Compare letters regardless of case.
+    pub ignore_case: bool,
+    /// Compare the numbers rows start with by value, so `9` comes before
+    /// `10`. Rows not starting with a number go first.
+    pub numeric: bool,
+    /// Keep only the first of rows comparing equal.
+    pub unique: bool,
+}
+
+impl SortOptions {
+    /// Parses the flags after `:sort`: `i` to ignore case, `n` for numeric
+    /// order and `u` to drop duplicates.
+    pub fn parse(flags: &str, reverse: bool) -> Result<Self, String> {
+        let mut options = SortOptions {
+            reverse,
+            ..SortOptions::default()
+        };
+        for flag in flags.chars().filter(|ch| !ch.is_whitespace()) {
+            match flag {
+                'i' => options.ignore_case = true,
+                'n' => options.numeric = true,
+                'u' => options.unique = true,
+                _ => return Err(format!("Unknown sort option: {}", flag)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Sorts `rows` as `options` say. Rows comparing equal keep their order,
+/// unless reversed.
+pub fn sort(rows: &mut Vec<String>, options: SortOptions) {
+    rows.sort_by(|a, b| compare(a, b, options));
+    if options.unique {
+        rows.dedup_by(|a, b| compare(a, b, options) == Ordering::Equal);
+    }
+    if options.reverse {
+        rows.reverse();
+    }
+}
+
+/// Orders rows by code point, by their leading numbers first with
+/// `numeric`.
+fn compare(a: &str, b: &str, options: SortOptions) -> Ordering {
+    let by_number = if options.numeric {
+        match (leading_number(a), leading_number(b)) {
+            // Without leading zeros, longer numbers are larger.
+            (Some(a), Some(b)) => a.len().cmp(&b.len()).then(a.cmp(b)),
+            (a, b) => a.is_some().cmp(&b.is_some()),
+        }
+    } else {
+        Ordering::Equal
+    };
+    by_number.then_with(|| {
+        if options.ignore_case {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        } else {
+            a.cmp(b)
+        }
+    })
+}
+
+/// The digits `row` starts with after any blanks, without leading zeros.
+fn leading_number(row: &str) -> Option<&str> {
+    let row = row.trim_start();
+    let digits = &row[..row.len() - row.trim_start_matches(|ch: char| ch.is_ascii_digit()).len()];
+    if digits.is_empty() {
+        return None;
+    }
+    Some(match digits.trim_start_matches('0') {
+        "" => "0",
+        digits => digits,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(rows: &[&str], flags: &str, reverse: bool) -> Vec<String> {
+        let mut rows = rows.iter().map(|row| row.to_string()).collect();
+        sort(&mut rows, SortOptions::parse(flags, reverse).unwrap());
+        rows
+    }
+
+    #[test]
+    fn sorts_by_code_point() {
+        assert_eq!(sorted(&["b", "a", "B", "c"], "", false), ["B", "a", "b", "c"]);
+        assert_eq!(sorted(&["b", "a", "c"], "", true), ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn sorts_ignoring_case_keeping_equal_rows_in_order() {
+        assert_eq!(sorted(&["b", "a", "B", "A"], "i", false), ["a", "A", "b", "B"]);
+    }
+
+    #[test]
+    fn sorts_by_leading_number() {
+        let rows = ["10 ten", "9 nine", "x", " 009 padded", "100"];
+        assert_eq!(
+            sorted(&rows, "n", false),
+            ["x", " 009 padded", "9 nine", "10 ten", "100"]
+        );
+    }
+
+    #[test]
+    fn keeps_the_first_of_equal_rows() {
+        assert_eq!(sorted(&["b", "a", "b", "a"], "u", false), ["a", "b"]);
+        assert_eq!(sorted(&["A", "a", "b"], "iu", false), ["A", "b"]);
+    }
+
+    #[test]
+    fn rejects_unknown_flags() {
+        assert_eq!(
+            SortOptions::parse("x", false),
+            Err("Unknown sort option: x".into())
+        );
+    }
+}
@@ -0,0 +1,23 @@
+/// The text `ys` puts before and after a region for `key`, as in
+/// vim-surround: an opening bracket adds a space inside each delimiter, a
+/// closing one (or `b`, `B`, `r`, `a`) just the brackets, and other
+/// punctuation goes on both sides as it is.
+pub fn delimiters(key: char) -> Option<(String, String)> {
+    let (open, close, spaced) = match key {
+        '(' => ('(', ')', true),
+        ')' | 'b' => ('(', ')', false),
+        '{' => ('{', '}', true),
+        '}' | 'B' => ('{', '}', false),
+        '[' => ('[', ']', true),
+        ']' | 'r' => ('[', ']', false),
+        '<' => ('<', '>', true),
+        '>' | 'a' => ('<', '>', false),
+        _ if key.is_ascii_punctuation() => (key, key, false),
+        _ => return None,
+    };
+    Some(if spaced {
+        (format!("{} ", open), format!(" {}", close))
+    } else {
+        (open.to_string(), close.to_string())
+    })
+}
@@ -0,0 +1,192 @@
+use crate::Row;
+use std::sync::Arc;
+
+/// Files at least this large are loaded into a [`RopeBuffer`] rather than a
+/// [`VecBuffer`].
+pub const ROPE_THRESHOLD: u64 = 8 * 1024 * 1024;
+
+/// Number of rows a [`RopeBuffer`] chunk holds before it is split in two.
+const CHUNK_SIZE: usize = 1024;
+
+/// Row storage behind `EditorRows`.
+///
+/// Everything that reads or edits the buffer goes through this trait so the
+/// representation can be picked per file.
+pub trait TextBuffer {
+    fn line_count(&self) -> usize;
+
+    fn line(&self, at: usize) -> &Row;
+
+    fn line_mut(&mut self, at: usize) -> &mut Row;
+
+    fn insert(&mut self, at: usize, row: Row);
+
+    fn delete(&mut self, at: usize) -> Row;
+
+    /// Iterates the rows in order without collecting them, for saving.
+    fn lines(&self) -> Box<dyn Iterator<Item = &Row> + '_>;
+
+    /// Takes a read-only copy of the rows that can be sent to another
+    /// thread. Rows are shared with the buffer until either side changes.
+    fn snapshot(&self) -> Snapshot;
+}
+
+/// A frozen view of a [`TextBuffer`], see [`TextBuffer::snapshot`].
+pub struct Snapshot {
+    chunks: Vec<Arc<Vec<Row>>>,
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl Snapshot {
+    pub fn line_count(&self) -> usize {
+        self.len
+    }
+
+    pub fn line(&self, at: usize) -> &str {
+        let chunk = self.starts.partition_point(|&start| start <= at) - 1;
+        &self.chunks[chunk][at - self.starts[chunk]].row_content
+    }
+}
+
+/// Picks the storage for a file of `size` bytes.
+pub fn for_size(size: u64) -> Box<dyn TextBuffer> {
+    if size >= ROPE_THRESHOLD {
+        Box::<RopeBuffer>::default()
+    } else {
+        Box::<VecBuffer>::default()
+    }
+}
+
+/// A plain vector of rows; cheap for ordinary files.
+#[derive(Default)]
+pub struct VecBuffer {
+    rows: Arc<Vec<Row>>,
+}
+
+impl TextBuffer for VecBuffer {
+    fn line_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn line(&self, at: usize) -> &Row {
+        &self.rows[at]
+    }
+
+    fn line_mut(&mut self, at: usize) -> &mut Row {
+        &mut Arc::make_mut(&mut self.rows)[at]
+    }
+
+    fn insert(&mut self, at: usize, row: Row) {
+        Arc::make_mut(&mut self.rows).insert(at, row)
+    }
+
+    fn delete(&mut self, at: usize) -> Row {
+        Arc::make_mut(&mut self.rows).remove(at)
+    }
+
+    fn lines(&self) -> Box<dyn Iterator<Item = &Row> + '_> {
+        Box::new(self.rows.iter())
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            chunks: vec![Arc::clone(&self.rows)],
+            starts: vec![0],
+            len: self.rows.len(),
+        }
+    }
+}
+
+/// A shallow rope: rows live in chunks of at most `2 * CHUNK_SIZE`, so
+/// inserting or deleting a row only shifts the rows of one chunk plus the
+/// chunk start offsets, however large the file is. Chunks are shared with
+/// snapshots and copied on write.
+#[derive(Default)]
+pub struct RopeBuffer {
+    chunks: Vec<Arc<Vec<Row>>>,
+    /// Index of the first row of each chunk.
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl RopeBuffer {
+    /// Returns the chunk holding row `at` and the row's offset within it.
+    fn locate(&self, at: usize) -> (usize, usize) {
+        let chunk = self.starts.partition_point(|&start| start <= at) - 1;
+        (chunk, at - self.starts[chunk])
+    }
+
+    fn shift_starts(&mut self, after_chunk: usize, added: bool) {
+        self.starts[after_chunk + 1..].iter_mut().for_each(|start| {
+            if added {
+                *start += 1
+            } else {
+                *start -= 1
+            }
+        });
+    }
+}
+
+impl TextBuffer for RopeBuffer {
+    fn line_count(&self) -> usize {
+        self.len
+    }
+
+    fn line(&self, at: usize) -> &Row {
+        let (chunk, offset) = self.locate(at);
+        &self.chunks[chunk][offset]
+    }
+
+    fn line_mut(&mut self, at: usize) -> &mut Row {
+        let (chunk, offset) = self.locate(at);
+        &mut Arc::make_mut(&mut self.chunks[chunk])[offset]
+    }
+
+    fn insert(&mut self, at: usize, row: Row) {
+        if self.chunks.is_empty() {
+            self.chunks.push(Arc::new(Vec::with_capacity(CHUNK_SIZE)));
+            self.starts.push(0);
+        }
+        let (chunk, offset) = if at == self.len {
+            let last = self.chunks.len() - 1;
+            (last, self.chunks[last].len())
+        } else {
+            self.locate(at)
+        };
+        let rows = Arc::make_mut(&mut self.chunks[chunk]);
+        rows.insert(offset, row);
+        let tail = (rows.len() >= 2 * CHUNK_SIZE).then(|| rows.split_off(CHUNK_SIZE));
+        self.shift_starts(chunk, true);
+        self.len += 1;
+        if let Some(tail) = tail {
+            self.chunks.insert(chunk + 1, Arc::new(tail));
+            self.starts
+                .insert(chunk + 1, self.starts[chunk] + CHUNK_SIZE);
+        }
+    }
+
+    fn delete(&mut self, at: usize) -> Row {
+        let (chunk, offset) = self.locate(at);
+        let row = Arc::make_mut(&mut self.chunks[chunk]).remove(offset);
+        self.shift_starts(chunk, false);
+        self.len -= 1;
+        if self.chunks[chunk].is_empty() {
+            self.chunks.remove(chunk);
+            self.starts.remove(chunk);
+        }
+        row
+    }
+
+    fn lines(&self) -> Box<dyn Iterator<Item = &Row> + '_> {
+        Box::new(self.chunks.iter().flat_map(|chunk| chunk.iter()))
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            chunks: self.chunks.clone(),
+            starts: self.starts.clone(),
+            len: self.len,
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use crate::motion::MotionKind;
+use crate::word_motion::{is_word_char, Lines};
+
+/// A position as `(row, byte column)`.
+type Pos = (usize, usize);
+
+/// Text around the cursor an operator can act on, typed as `i` or `a`
+/// followed by the object's key.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TextObject {
+    /// `w`, or `W` with `big`.
+    Word { big: bool },
+    /// Brackets such as `(` and `)`, possibly on different rows.
+    Pair(char, char),
+    /// A quoted string on the cursor's row.
+    Quote(char),
+}
+
+impl TextObject {
+    /// The object typed as `key` after `i` or `a`.
+    pub fn for_key(key: char) -> Option<Self> {
+        let object = match key {
+            'w' | 'W' => TextObject::Word { big: key == 'W' },
+            '(' | ')' | 'b' => TextObject::Pair('(', ')'),
+            '{' | '}' | 'B' => TextObject::Pair('{', '}'),
+            '[' | ']' => TextObject::Pair('[', ']'),
+            '"' | '\'' | '`' => TextObject::Quote(key),
+            _ => return None,
+        };
+        Some(object)
+    }
+}
+
+/// The text `object` covers around `pos`, with the end exclusive, or `None`
+/// if the cursor is not in one. `inner` leaves out delimiters and
+/// surrounding whitespace. Inner pairs that span whole rows are linewise,
+/// the rest exclusive.
+pub fn range(
+    lines: &impl Lines,
+    pos: (usize, usize),
+    object: TextObject,
+    inner: bool,
+) -> Option<(Pos, Pos, MotionKind)> {
+    match object {
+        TextObject::Word { big } => {
+            let (start, end) = word(lines.line(pos.0), pos.1, big, inner);
+            Some(((pos.0, start), (pos.0, end), MotionKind::Exclusive))
+        }
+        TextObject::Pair(open, close) => pair(lines, pos, open, close, inner),
+        TextObject::Quote(quote) => {
+            let (start, end) = quoted(lines.line(pos.0), pos.1, quote, inner)?;
+            Some(((pos.0, start), (pos.0, end), MotionKind::Exclusive))
+        }
+    }
+}
+
+/// Where the delimiters of the pair or quoted string `object` around `pos`
+/// start, the opening one first, as `ds` and `cs` look for them.
+pub fn delimiters(lines: &impl Lines, pos: Pos, object: TextObject) -> Option<(Pos, Pos)> {
+    match object {
+        TextObject::Word { .. } => None,
+        TextObject::Pair(open, close) => {
+            let (start, end, _) = pair(lines, pos, open, close, false)?;
+            Some((start, (end.0, end.1 - close.len_utf8())))
+        }
+        TextObject::Quote(quote) => {
+            let (start, end) = quoted(lines.line(pos.0), pos.1, quote, true)?;
+            Some(((pos.0, start - quote.len_utf8()), (pos.0, end)))
+        }
+    }
+}
+
+/// What kind of run a char belongs to, as word motions see it.
+fn class(ch: char, big: bool) -> u8 {
+    if ch.is_whitespace() {
+        0
+    } else if big || is_word_char(ch) {
+        1
+    } else {
+        2
+    }
+}
+
+/// Byte range of the run of like chars under `col`, and with `!inner` the
+/// whitespace after it, or before it if there is none after.
+fn word(line: &str, col: usize, big: bool, inner: bool) -> (usize, usize) {
+    let Some(ch) = line[col..].chars().next() else {
+        return (col, col);
+    };
+    let run_end = |from: usize, class_of: u8| {
+        line[from..]
+            .char_indices()
+            .find(|&(_, ch)| class(ch, big) != class_of)
+            .map_or(line.len(), |(at, _)| from + at)
+    };
+    let run_start = |to: usize, class_of: u8| {
+        line[..to]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, ch)| class(ch, big) == class_of)
+            .last()
+            .map_or(to, |(at, _)| at)
+    };
+    let class_of = class(ch, big);
+    let (start, end) = (run_start(col, class_of), run_end(col, class_of));
+    if inner {
+        return (start, end);
+    }
+    if class_of == 0 {
+        // On blanks, `aw` is the blanks and the word after them.
+        return match line[end..].chars().next() {
+            Some(next) => (start, run_end(end, class(next, big))),
+            None => (start, end),
+        };
+    }
+    let trailing = run_end(end, 0);
+    if trailing > end {
+        (start, trailing)
+    } else {
+        (run_start(start, 0), end)
+    }
+}
+
+/// The innermost `open`/`close` pair around `pos`, counting a bracket under
+/// the cursor as part of it.
+fn pair(
+    lines: &impl Lines,
+    pos: (usize, usize),
+    open: char,
+    close: char,
+    inner: bool,
+) -> Option<(Pos, Pos, MotionKind)> {
+    let under = lines.line(pos.0)[pos.1..].chars().next();
+    let start = if under == Some(open) {
+        pos
+    } else {
+        // Start before a closing bracket under the cursor so it is matched.
+        let from = if under == Some(close) {
+            pos
+        } else {
+            next(lines, pos).unwrap_or(pos)
+        };
+        scan(lines, from, open, close, false)?
+    };
+    let end = scan(lines, next(lines, start)?, close, open, true)?;
+    if !inner {
+        return Some((start, (end.0, end.1 + close.len_utf8()), MotionKind::Exclusive));
+    }
+    let mut inner_start = (start.0, start.1 + open.len_utf8());
+    let mut inner_end = end;
+    // Brackets alone at the ends of their rows enclose whole rows.
+    let open_ends_row = inner_start.1 == lines.line(start.0).len();
+    let close_starts_row = lines.line(end.0)[..end.1].trim().is_empty();
+    if open_ends_row && close_starts_row && end.0 > start.0 + 1 {
+        return Some(((start.0 + 1, 0), (end.0 - 1, 0), MotionKind::Linewise));
+    }
+    if open_ends_row && end.0 > start.0 {
+        inner_start = (start.0 + 1, 0);
+    }
+    if close_starts_row && end.0 > inner_start.0 {
+        inner_end = (end.0 - 1, lines.line(end.0 - 1).len());
+    }
+    Some((inner_start, inner_end, MotionKind::Exclusive))
+}
+
+/// The bracket matching the first of `()[]{}` at or after `pos` on its row,
+/// skipping nested pairs.
+pub fn matching_bracket(lines: &impl Lines, pos: Pos) -> Option<Pos> {
+    let line = lines.line(pos.0);
+    let (at, ch) = line[pos.1..]
+        .char_indices()
+        .find(|&(_, ch)| BRACKETS.iter().any(|&(open, close)| ch == open || ch == close))?;
+    let bracket = (pos.0, pos.1 + at);
+    let &(open, close) = BRACKETS
+        .iter()
+        .find(|&&(open, close)| ch == open || ch == close)?;
+    if ch == open {
+        scan(lines, next(lines, bracket)?, close, open, true)
+    } else {
+        scan(lines, bracket, open, close, false)
+    }
+}
+
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// Looks for an unmatched `target` starting at `from` (forward) or before it
+/// (backward), skipping nested pairs of `target` and `other`.
+fn scan(
+    lines: &impl Lines,
+    from: (usize, usize),
+    target: char,
+    other: char,
+    forward: bool,
+) -> Option<(usize, usize)> {
+    let mut depth = 0;
+    let mut pos = from;
+    loop {
+        if !forward {
+            pos = previous(lines, pos)?;
+        }
+        match lines.line(pos.0)[pos.1..].chars().next() {
+            Some(ch) if ch == target && depth == 0 => return Some(pos),
+            Some(ch) if ch == target => depth -= 1,
+            Some(ch) if ch == other => depth += 1,
+            _ => {}
+        }
+        if forward {
+            pos = next(lines, pos)?;
+        }
+    }
+}
+
+/// The position after `pos`, stepping over row ends.
+fn next(lines: &impl Lines, (row, col): (usize, usize)) -> Option<(usize, usize)> {
+    match lines.line(row)[col..].chars().next() {
+        Some(ch) => Some((row, col + ch.len_utf8())),
+        None if row + 1 < lines.line_count() => Some((row + 1, 0)),
+        None => None,
+    }
+}
+
+fn previous(lines: &impl Lines, (row, col): (usize, usize)) -> Option<(usize, usize)> {
+    match lines.line(row)[..col].chars().next_back() {
+        Some(ch) => Some((row, col - ch.len_utf8())),
+        None if row > 0 => Some((row - 1, lines.line(row - 1).len())),
+        None => None,
+    }
+}
+
+/// The quoted string on `line` around `col`, or the first one after it.
+/// Quotes escaped with a backslash don't count. Without `inner` the quotes
+/// and the whitespace after them (or else before them) are included.
+fn quoted(line: &str, col: usize, quote: char, inner: bool) -> Option<(usize, usize)> {
+    let mut quotes = Vec::new();
+    let mut escaped = false;
+    for (at, ch) in line.char_indices() {
+        if ch == quote && !escaped {
+            quotes.push(at);
+        }
+        escaped = ch == '\\' && !escaped;
+    }
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(_, close)| col <= close)?;
+    if inner {
+        return Some((open + quote.len_utf8(), close));
+    }
+    let end = close + quote.len_utf8();
+    let trailing = end + (line[end..].len() - line[end..].trim_start().len());
+    if trailing > end {
+        Some((open, trailing))
+    } else {
+        Some((open - (line[..open].len() - line[..open].trim_end().len()), end))
+    }
+}
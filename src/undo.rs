@@ -0,0 +1,109 @@
+/// Most groups of changes kept for undoing; older ones are dropped.
+const MAX_GROUPS: usize = 1000;
+
+/// A change to a buffer's rows, described by what reverts it.
+pub enum Change {
+    /// Row `at` held `content` before it was edited.
+    Set { at: usize, content: String },
+    /// A row was inserted at `at`.
+    Inserted { at: usize },
+    /// Row `at` held `content` and was deleted.
+    Deleted { at: usize, content: String },
+}
+
+/// The changes one command made, undone and redone together.
+pub struct Group {
+    pub changes: Vec<Change>,
+    /// Where the cursor was before the command.
+    pub cursor: (usize, usize),
+}
+
+/// Changes to undo and redo for one buffer.
+pub struct UndoStack {
+    undo: Vec<Group>,
+    redo: Vec<Group>,
+    /// Set when the next change starts a new group, to the cursor the group
+    /// will return to.
+    next_cursor: Option<(usize, usize)>,
+    /// How many groups there were to undo when the rows were last saved, or
+    /// `None` if undoing and redoing can no longer get back to them.
+    saved: Option<usize>,
+}
+
+impl Default for UndoStack {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            next_cursor: None,
+            saved: Some(0),
+        }
+    }
+}
+
+impl UndoStack {
+    /// Ends the group being recorded. The next change starts a new one,
+    /// with the cursor at `cursor`.
+    pub fn seal(&mut self, cursor: (usize, usize)) {
+        self.next_cursor = Some(cursor);
+    }
+
+    /// Adds `change` to the group being recorded. A new change makes what
+    /// was undone impossible to redo.
+    pub fn record(&mut self, change: Change) {
+        if self.saved.is_some_and(|saved| saved > self.undo.len()) {
+            self.saved = None;
+        }
+        self.redo.clear();
+        if self.next_cursor.is_some() || self.undo.is_empty() {
+            let cursor = self.next_cursor.take().unwrap_or_default();
+            if self.undo.len() == MAX_GROUPS {
+                self.undo.remove(0);
+                self.saved = self.saved.and_then(|saved| saved.checked_sub(1));
+            }
+            self.undo.push(Group {
+                changes: Vec::new(),
+                cursor,
+            });
+        }
+        let changes = &mut self.undo.last_mut().unwrap().changes;
+        // Only the first content of a row edited over and over is needed.
+        if let (Change::Set { at, .. }, Some(Change::Set { at: last, .. })) = (&change, changes.last()) {
+            if at == last {
+                return;
+            }
+        }
+        changes.push(change);
+    }
+
+    /// Notes that the rows were saved as they are, ending the group being
+    /// recorded so later changes can be undone back to them.
+    pub fn mark_saved(&mut self, cursor: (usize, usize)) {
+        self.seal(cursor);
+        self.saved = Some(self.undo.len());
+    }
+
+    /// Whether undoing and redoing have brought the rows back to how they
+    /// were last saved.
+    pub fn at_saved(&self) -> bool {
+        self.saved == Some(self.undo.len())
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Group> {
+        self.undo.pop()
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Group> {
+        self.redo.pop()
+    }
+
+    /// Keeps `group`, which reverts an undo, for redoing.
+    pub fn push_redo(&mut self, group: Group) {
+        self.redo.push(group);
+    }
+
+    /// Keeps `group`, which reverts a redo, for undoing again.
+    pub fn push_undo(&mut self, group: Group) {
+        self.undo.push(group);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::ignore::{self, Ignore};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+/// Files a walk lists at most, so a huge tree can't fill up memory.
+pub const MAX_FILES: usize = 100_000;
+
+/// Directories never worth looking in for files to edit, ignored or not.
+const SKIPPED: [&str; 4] = [".git", ".hg", ".svn", "node_modules"];
+
+/// Lists the files under a directory on a background thread, leaving out
+/// what `.gitignore` files there leave out.
+///
+/// Dropping the walk cancels the thread.
+pub struct FileWalk {
+    receiver: Receiver<Vec<String>>,
+    cancel: Arc<AtomicBool>,
+    finished: bool,
+}
+
+impl FileWalk {
+    /// Starts listing the files under `root`, by their paths relative to
+    /// it.
+    pub fn spawn(root: PathBuf) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancelled = Arc::clone(&cancel);
+        thread::spawn(move || {
+            let mut walker = Walker {
+                root,
+                ignores: Vec::new(),
+                sender,
+                cancelled,
+                found: 0,
+            };
+            walker.walk("");
+        });
+        Self {
+            receiver,
+            cancel,
+            finished: false,
+        }
+    }
+
+    /// Appends the paths found since the last call to `paths`, without
+    /// waiting for more. Returns whether anything changed, including the
+    /// walk finishing.
+    pub fn receive(&mut self, paths: &mut Vec<String>) -> bool {
+        let mut changed = false;
+        while !self.finished {
+            match self.receiver.try_recv() {
+                Ok(batch) => paths.extend(batch),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => self.finished = true,
+            }
+            changed = true;
+        }
+        changed
+    }
+
+    /// Waits for the next paths found, or returns `None` once the walk is
+    /// done.
+    pub fn wait(&mut self) -> Option<Vec<String>> {
+        let batch = self.receiver.recv().ok();
+        self.finished = batch.is_none();
+        batch
+    }
+
+    /// Whether every file has been found and received.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+}
+
+impl Drop for FileWalk {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+struct Walker {
+    root: PathBuf,
+    /// The `.gitignore` files of the directories being walked, outermost
+    /// first.
+    ignores: Vec<Ignore>,
+    sender: Sender<Vec<String>>,
+    cancelled: Arc<AtomicBool>,
+    found: usize,
+}
+
+impl Walker {
+    /// Sends the files in `dir`, relative to the root, then walks its
+    /// subdirectories. Returns `false` once the walk should stop.
+    fn walk(&mut self, dir: &str) -> bool {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return false;
+        }
+        let path = self.root.join(dir);
+        let Ok(entries) = fs::read_dir(&path) else {
+            return true;
+        };
+        let ignore = fs::read_to_string(path.join(".gitignore"))
+            .ok()
+            .map(|contents| Ignore::parse(dir, &contents));
+        let has_ignore = ignore.is_some();
+        self.ignores.extend(ignore);
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let child = if dir.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", dir, name)
+            };
+            // Links to directories aren't followed, so the walk can't loop.
+            let is_dir = file_type.is_dir();
+            let is_file = file_type.is_file()
+                || (file_type.is_symlink()
+                    && entry.path().metadata().is_ok_and(|metadata| metadata.is_file()));
+            if (is_dir && SKIPPED.contains(&name.as_str()))
+                || !(is_dir || is_file)
+                || ignore::is_ignored(&self.ignores, &child, is_dir)
+            {
+                continue;
+            }
+            if is_dir {
+                dirs.push(child)
+            } else {
+                files.push(child)
+            }
+        }
+        files.sort();
+        files.truncate(MAX_FILES - self.found);
+        self.found += files.len();
+        let mut going = self.sender.send(files).is_ok() && self.found < MAX_FILES;
+        dirs.sort();
+        for child in dirs {
+            if !going {
+                break;
+            }
+            going = self.walk(&child);
+        }
+        if has_ignore {
+            self.ignores.pop();
+        }
+        going
+    }
+}
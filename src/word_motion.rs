@@ -0,0 +1,245 @@
+use std::ops::Range;
+
+/// Text that word motions can walk over, one line at a time.
+pub trait Lines {
+    fn line_count(&self) -> usize;
+
+    fn line(&self, at: usize) -> &str;
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum WordMotion {
+    /// To the start of the next word (`w`).
+    NextStart,
+    /// To the start of the current or previous word (`b`).
+    PrevStart,
+    /// To the end of the current or next word (`e`).
+    NextEnd,
+}
+
+/// Whether `ch` belongs to a word, as opposed to punctuation or whitespace.
+/// Shared by word motions and whole-word search so both agree on what a
+/// word is.
+pub fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Moves from `pos` over a run of separators and then a run of word chars,
+/// forward or backward, the way Ctrl+Right and Ctrl+Left do in most
+/// editors. Row ends count as separators, so moves go on to other rows.
+pub fn skip_word(lines: &impl Lines, pos: (usize, usize), forward: bool) -> (usize, usize) {
+    let walker = Walker { lines, big: false };
+    if forward {
+        walker.skip_forward(pos)
+    } else {
+        walker.skip_backward(pos)
+    }
+}
+
+/// Byte ranges on `line` of the word at or before `col` and the word after
+/// it, for swapping them, or of the last two words when `col` is on the
+/// last. Words are runs of word chars, so punctuation stays in between.
+pub fn transpose_pair(line: &str, col: usize) -> Option<(Range<usize>, Range<usize>)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (at, ch) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (start, is_word_char(ch)) {
+            (None, true) => start = Some(at),
+            (Some(from), false) => {
+                words.push(from..at);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    let at = words.iter().rposition(|word| word.start <= col).unwrap_or(0);
+    let first = at.min(words.len().checked_sub(2)?);
+    Some((words[first].clone(), words[first + 1].clone()))
+}
+
+/// Moves from `pos`, as `(row, byte column)`, by `motion`. Words are runs of
+/// word chars or runs of other non-blank chars; with `big` (`W`, `B`, `E`)
+/// they are any run of non-blank chars. Empty lines count as words.
+pub fn apply(
+    lines: &impl Lines,
+    pos: (usize, usize),
+    motion: WordMotion,
+    big: bool,
+) -> (usize, usize) {
+    let walker = Walker { lines, big };
+    match motion {
+        WordMotion::NextStart => walker.next_start(pos),
+        WordMotion::PrevStart => walker.prev_start(pos),
+        WordMotion::NextEnd => walker.next_end(pos),
+    }
+}
+
+/// What kind of run a char belongs to.
+#[derive(PartialEq)]
+enum Class {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+struct Walker<'a, L> {
+    lines: &'a L,
+    big: bool,
+}
+
+impl<L: Lines> Walker<'_, L> {
+    /// The class of the char at `pos`; line ends are blanks.
+    fn class(&self, (row, col): (usize, usize)) -> Class {
+        match self.lines.line(row)[col..].chars().next() {
+            None => Class::Blank,
+            Some(ch) if ch.is_whitespace() => Class::Blank,
+            Some(_) if self.big => Class::Word,
+            Some(ch) if is_word_char(ch) => Class::Word,
+            Some(_) => Class::Punctuation,
+        }
+    }
+
+    fn is_empty_line(&self, (row, _): (usize, usize)) -> bool {
+        self.lines.line(row).is_empty()
+    }
+
+    /// The next position, stepping over line ends.
+    fn forward(&self, (row, col): (usize, usize)) -> Option<(usize, usize)> {
+        let line = self.lines.line(row);
+        match line[col..].chars().next() {
+            Some(ch) => Some((row, col + ch.len_utf8())),
+            None if row + 1 < self.lines.line_count() => Some((row + 1, 0)),
+            None => None,
+        }
+    }
+
+    fn backward(&self, (row, col): (usize, usize)) -> Option<(usize, usize)> {
+        match self.lines.line(row)[..col].chars().next_back() {
+            Some(ch) => Some((row, col - ch.len_utf8())),
+            None if row > 0 => Some((row - 1, self.lines.line(row - 1).len())),
+            None => None,
+        }
+    }
+
+    fn skip_forward(&self, from: (usize, usize)) -> (usize, usize) {
+        let mut pos = from;
+        for word in [false, true] {
+            while (self.class(pos) == Class::Word) == word {
+                match self.forward(pos) {
+                    Some(next) => pos = next,
+                    None => return pos,
+                }
+            }
+        }
+        pos
+    }
+
+    fn skip_backward(&self, from: (usize, usize)) -> (usize, usize) {
+        let mut pos = from;
+        for word in [false, true] {
+            while let Some(previous) = self
+                .backward(pos)
+                .filter(|&previous| (self.class(previous) == Class::Word) == word)
+            {
+                pos = previous;
+            }
+        }
+        pos
+    }
+
+    fn next_start(&self, from: (usize, usize)) -> (usize, usize) {
+        let mut pos = from;
+        let class = self.class(pos);
+        while self.class(pos) == class && class != Class::Blank {
+            match self.forward(pos) {
+                Some(next) => pos = next,
+                None => return pos,
+            }
+        }
+        while self.class(pos) == Class::Blank && !(pos != from && self.is_empty_line(pos)) {
+            match self.forward(pos) {
+                Some(next) => pos = next,
+                None => return pos,
+            }
+        }
+        pos
+    }
+
+    fn next_end(&self, from: (usize, usize)) -> (usize, usize) {
+        let Some(mut pos) = self.forward(from) else {
+            return from;
+        };
+        while self.class(pos) == Class::Blank {
+            match self.forward(pos) {
+                Some(next) => pos = next,
+                None => return pos,
+            }
+        }
+        let class = self.class(pos);
+        while let Some(next) = self.forward(pos).filter(|&next| self.class(next) == class) {
+            pos = next;
+        }
+        pos
+    }
+
+    fn prev_start(&self, from: (usize, usize)) -> (usize, usize) {
+        let Some(mut pos) = self.backward(from) else {
+            return from;
+        };
+        while self.class(pos) == Class::Blank && !self.is_empty_line(pos) {
+            match self.backward(pos) {
+                Some(previous) => pos = previous,
+                None => return pos,
+            }
+        }
+        let class = self.class(pos);
+        while let Some(previous) = self
+            .backward(pos)
+            .filter(|&previous| previous.0 == pos.0 && self.class(previous) == class)
+        {
+            pos = previous;
+        }
+        pos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Lines for Vec<&str> {
+        fn line_count(&self) -> usize {
+            self.len()
+        }
+
+        fn line(&self, at: usize) -> &str {
+            self[at]
+        }
+    }
+
+    #[test]
+    fn skips_separators_then_a_word_forward() {
+        let lines = vec!["foo.bar  baz", "qux"];
+        assert_eq!(skip_word(&lines, (0, 0), true), (0, 3));
+        assert_eq!(skip_word(&lines, (0, 3), true), (0, 7));
+        assert_eq!(skip_word(&lines, (0, 7), true), (0, 12));
+        assert_eq!(skip_word(&lines, (0, 12), true), (1, 3));
+        assert_eq!(skip_word(&lines, (1, 3), true), (1, 3));
+    }
+
+    #[test]
+    fn skips_separators_then_a_word_backward() {
+        let lines = vec!["foo.bar  baz", "qux"];
+        assert_eq!(skip_word(&lines, (1, 0), false), (0, 9));
+        assert_eq!(skip_word(&lines, (0, 9), false), (0, 4));
+        assert_eq!(skip_word(&lines, (0, 4), false), (0, 0));
+        assert_eq!(skip_word(&lines, (0, 0), false), (0, 0));
+    }
+
+    #[test]
+    fn steps_over_multibyte_chars() {
+        let lines = vec!["héllo wörld"];
+        assert_eq!(skip_word(&lines, (0, 0), true), (0, 6));
+        assert_eq!(skip_word(&lines, (0, 6), true), (0, 13));
+    }
+}
@@ -0,0 +1,58 @@
+/// Render columns where each screen row starts when `render` is wrapped to
+/// `width` columns. Rows break after the last blank that fits, or inside a
+/// word that is wider than the screen.
+pub fn breaks(render: &str, width: usize) -> Vec<usize> {
+    let chars: Vec<char> = render.chars().collect();
+    let mut starts = vec![0];
+    let mut start = 0;
+    while chars.len() - start > width {
+        let end = start + width;
+        start = (start + 1..=end)
+            .rev()
+            .find(|&at| chars[at - 1].is_whitespace())
+            .unwrap_or(end);
+        starts.push(start);
+    }
+    starts
+}
+
+/// The screen row, out of those starting at `breaks`, showing render column
+/// `render_x`.
+pub fn line_of(breaks: &[usize], render_x: usize) -> usize {
+    breaks
+        .iter()
+        .rposition(|&start| start <= render_x)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaves_a_short_row_whole() {
+        assert_eq!(breaks("short", 10), [0]);
+        assert_eq!(breaks("", 10), [0]);
+        assert_eq!(breaks("exactly 10", 10), [0]);
+    }
+
+    #[test]
+    fn breaks_after_the_last_blank_that_fits() {
+        assert_eq!(breaks("one two three four five", 10), [0, 8, 14]);
+    }
+
+    #[test]
+    fn breaks_inside_a_word_wider_than_the_screen() {
+        assert_eq!(breaks("abcdefghijklmnopqrstuvwxy", 10), [0, 10, 20]);
+        assert_eq!(breaks("ab cdefghijklmno", 10), [0, 3, 13]);
+    }
+
+    #[test]
+    fn finds_the_screen_row_of_a_column() {
+        let breaks = [0, 8, 14];
+        assert_eq!(line_of(&breaks, 0), 0);
+        assert_eq!(line_of(&breaks, 7), 0);
+        assert_eq!(line_of(&breaks, 8), 1);
+        assert_eq!(line_of(&breaks, 20), 2);
+    }
+}